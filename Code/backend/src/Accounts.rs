@@ -0,0 +1,308 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use chrono::prelude::*;
+use chrono::Duration;
+use common::Backend::{Credentials, Role, Session, User};
+use rand::Rng;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::Config::AppConfig;
+use crate::Query::COMPUTER;
+use crate::Storage::{GameStore, StoreError};
+
+// how long a login lasts
+const SESSION_DAYS: i64 = 30;
+
+// An account as the stores keep it; only the `User` part leaves the server
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Account {
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub id: Option<String>,
+	pub name: String,
+	// the name in lower case, unique so "Ann" cannot pose as "ann"
+	pub key: String,
+	// Argon2 in PHC string format, salt and parameters included
+	pub password_hash: String,
+	pub created_at: String,
+	#[serde(default)]
+	pub role: Role,
+	// banned accounts cannot log in, and their sessions are gone
+	#[serde(default)]
+	pub banned: bool,
+}
+
+impl Account {
+	pub fn user(&self) -> User {
+		User {
+			id: self.id.clone().unwrap_or_default(),
+			name: self.name.clone(),
+			created_at: self.created_at.clone(),
+			role: self.role,
+		}
+	}
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SessionRecord {
+	// SHA-256 of the token, so the stored sessions cannot be used to log in
+	pub token: String,
+	pub user_id: String,
+	pub expires_at: String,
+}
+
+// Returned by stores when another account already has the name
+#[derive(Debug)]
+pub struct NameTaken;
+
+impl fmt::Display for NameTaken {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "this name is taken")
+	}
+}
+
+impl Error for NameTaken {}
+
+pub enum AccountError {
+	Invalid(String),
+	Taken,
+	WrongCredentials,
+	Banned,
+	Storage(String),
+}
+
+impl From<StoreError> for AccountError {
+	fn from(err: StoreError) -> Self {
+		if err.is::<NameTaken>() {
+			AccountError::Taken
+		} else {
+			AccountError::Storage(err.to_string())
+		}
+	}
+}
+
+pub fn name_key(name: &str) -> String {
+	name.trim().to_lowercase()
+}
+
+// Names show up on the scoreboard, so keep them short and plain. The
+// computer's name and the names of its levels are taken.
+fn check_name(name: &str) -> Result<(), String> {
+	let length = name.chars().count();
+	if !(2..=32).contains(&length) {
+		return Err("names must be 2 to 32 characters long".to_string());
+	}
+	if !name.chars().all(|c| c.is_alphanumeric() || " _-.".contains(c)) {
+		return Err("names may only use letters, digits, spaces and _ - .".to_string());
+	}
+	if name_key(name).starts_with(&COMPUTER.to_lowercase()) {
+		return Err(format!("names starting with {:?} are reserved", COMPUTER));
+	}
+	Ok(())
+}
+
+fn check_password(password: &str) -> Result<(), String> {
+	if !(8..=128).contains(&password.chars().count()) {
+		return Err("passwords must be 8 to 128 characters long".to_string());
+	}
+	Ok(())
+}
+
+fn hash_password(password: &str) -> Result<String, String> {
+	let salt = SaltString::encode_b64(&rand::thread_rng().gen::<[u8; 16]>()).map_err(|err| err.to_string())?;
+	Argon2::default()
+		.hash_password(password.as_bytes(), &salt)
+		.map(|hash| hash.to_string())
+		.map_err(|err| err.to_string())
+}
+
+fn verify_password(password: &str, hash: &str) -> bool {
+	PasswordHash::new(hash)
+		.and_then(|hash| Argon2::default().verify_password(password.as_bytes(), &hash))
+		.is_ok()
+}
+
+// Checked against when there is no account with the name, so an unknown
+// name takes as long to turn away as a wrong password
+fn dummy_hash() -> &'static str {
+	static HASH: OnceLock<String> = OnceLock::new();
+	HASH.get_or_init(|| hash_password("no account has this password").unwrap_or_default())
+}
+
+// What the stores keep of a session token
+fn token_hash(token: &str) -> String {
+	format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+pub async fn register(db: &dyn GameStore, credentials: Credentials) -> Result<User, AccountError> {
+	let name = credentials.name.trim().to_string();
+	check_name(&name).map_err(AccountError::Invalid)?;
+	check_password(&credentials.password).map_err(AccountError::Invalid)?;
+	let account = Account {
+		id: None,
+		key: name_key(&name),
+		name,
+		password_hash: hash_password(&credentials.password).map_err(AccountError::Storage)?,
+		created_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+		role: Role::Player,
+		banned: false,
+	};
+	Ok(db.insert_account(account).await?.user())
+}
+
+// Check the password and open a session. Unknown names and wrong passwords
+// get the same answer, so logins cannot be used to find out who has an account.
+pub async fn login(db: &dyn GameStore, credentials: Credentials) -> Result<Session, AccountError> {
+	let account = db.find_account(&name_key(&credentials.name)).await?;
+	let hash = match &account {
+		Some(account) => account.password_hash.as_str(),
+		None => dummy_hash(),
+	};
+	let verified = verify_password(&credentials.password, hash);
+	let account = match account {
+		Some(account) if verified => account,
+		_ => return Err(AccountError::WrongCredentials),
+	};
+	if account.banned {
+		return Err(AccountError::Banned);
+	}
+	let user = account.user();
+	let token = {
+		let rng = &mut rand::thread_rng();
+		format!("{:032x}{:032x}", rng.gen::<u128>(), rng.gen::<u128>())
+	};
+	let record = SessionRecord {
+		token: token_hash(&token),
+		user_id: user.id.clone(),
+		expires_at: (Utc::now() + Duration::days(SESSION_DAYS)).to_rfc3339_opts(SecondsFormat::Secs, true),
+	};
+	db.insert_session(record.clone()).await?;
+	Ok(Session { token, user, expires_at: record.expires_at })
+}
+
+// End the session of this token, false if there was none
+pub async fn logout(db: &dyn GameStore, token: &str) -> Result<bool, StoreError> {
+	db.delete_session(&token_hash(token)).await
+}
+
+// The user a session token belongs to; expired sessions are removed on sight
+pub async fn authenticate(db: &dyn GameStore, token: &str) -> Result<Option<User>, StoreError> {
+	let session = match db.find_session(&token_hash(token)).await? {
+		Some(session) => session,
+		None => return Ok(None),
+	};
+	if session.expires_at <= Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true) {
+		logout(db, token).await?;
+		return Ok(None);
+	}
+	Ok(db.get_account(&session.user_id).await?.filter(|account| !account.banned).map(|account| account.user()))
+}
+
+// Request guard for routes that need a logged in user, who sends
+// `Authorization: Bearer <token>` with the token from `POST /sessions`
+#[derive(Clone)]
+pub struct AuthUser {
+	pub user: User,
+	pub token: String,
+}
+
+// What the request's session token says, worked out once per request
+struct SessionLookup(Result<AuthUser, (Status, String)>);
+
+// The user a request comes from. The rate limiter asks before routing and
+// the `AuthUser` guard again later, only the first asks the store.
+pub async fn request_user<'r>(request: &'r Request<'_>) -> &'r Result<AuthUser, (Status, String)> {
+	let lookup = request.local_cache_async(async {
+		let token = match request.headers().get_one("Authorization").and_then(|value| value.strip_prefix("Bearer ")) {
+			Some(token) => token.trim().to_string(),
+			None => return SessionLookup(Err((Status::Unauthorized, "no session token".to_string()))),
+		};
+		let db = match request.rocket().state::<Arc<dyn GameStore>>() {
+			Some(db) => db,
+			None => return SessionLookup(Err((Status::InternalServerError, "no game store".to_string()))),
+		};
+		SessionLookup(match authenticate(db.as_ref(), &token).await {
+			Ok(Some(user)) => Ok(AuthUser { user, token }),
+			Ok(None) => Err((Status::Unauthorized, "unknown or expired session".to_string())),
+			Err(err) => Err((Status::InternalServerError, err.to_string())),
+		})
+	});
+	&lookup.await.0
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AuthUser {
+	type Error = String;
+
+	async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+		match request_user(request).await {
+			Ok(user) => Outcome::Success(user.clone()),
+			Err((status, err)) => Outcome::Error((*status, err.clone())),
+		}
+	}
+}
+
+// Request guard for routes only admins may use: accounts with the admin
+// role, and those whose id is in `admins`, which is how the first admin gets in
+pub struct AdminUser {
+	pub user: User,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminUser {
+	type Error = String;
+
+	async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+		let user = match request.guard::<AuthUser>().await {
+			Outcome::Success(auth) => auth.user,
+			Outcome::Error(err) => return Outcome::Error(err),
+			Outcome::Forward(status) => return Outcome::Forward(status),
+		};
+		let admin = user.role == Role::Admin || request.rocket().state::<AppConfig>()
+			.is_some_and(|config| config.admins.contains(&user.id));
+		if admin {
+			Outcome::Success(AdminUser { user })
+		} else {
+			Outcome::Error((Status::Forbidden, "only admins may do this".to_string()))
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::Storage::fixtures::stores;
+
+	fn credentials(name: &str, password: &str) -> Credentials {
+		Credentials { name: name.to_string(), password: password.to_string() }
+	}
+
+	#[rocket::async_test]
+	async fn keeps_only_hashed_tokens() {
+		for db in stores().await {
+			let db = db.as_ref();
+			assert!(register(db, credentials("Ann", "correct horse")).await.is_ok());
+			let Ok(session) = login(db, credentials("ann", "correct horse")).await else { panic!("login failed") };
+			assert!(db.find_session(&session.token).await.unwrap().is_none());
+			assert_eq!(authenticate(db, &session.token).await.unwrap().unwrap().name, "Ann");
+			assert!(logout(db, &session.token).await.unwrap());
+			assert!(authenticate(db, &session.token).await.unwrap().is_none());
+		}
+	}
+
+	#[rocket::async_test]
+	async fn turns_away_unknown_names_like_wrong_passwords() {
+		for db in stores().await {
+			let db = db.as_ref();
+			assert!(register(db, credentials("Ann", "correct horse")).await.is_ok());
+			assert!(matches!(login(db, credentials("ann", "wrong horse")).await, Err(AccountError::WrongCredentials)));
+			assert!(matches!(login(db, credentials("bob", "correct horse")).await, Err(AccountError::WrongCredentials)));
+		}
+	}
+}
@@ -0,0 +1,203 @@
+use common::Backend::{check_player_name, Game, GameEdit, GamesChanged, MergePlayers, Role, User};
+
+use crate::Accounts::{name_key, Account};
+use crate::Export::check_details;
+use crate::Query::{GameQuery, PlayerKey, COMPUTER, MAX_LIMIT};
+use crate::Ratings;
+use crate::Storage::{GameStore, StoreError};
+
+pub enum AdminError {
+	NoGame,
+	NoAccount,
+	Invalid(String),
+	Storage(String),
+}
+
+impl From<StoreError> for AdminError {
+	fn from(err: StoreError) -> Self {
+		AdminError::Storage(err.to_string())
+	}
+}
+
+async fn find_game(db: &dyn GameStore, id: &str) -> Result<Game, AdminError> {
+	db.get_game(id).await?.ok_or(AdminError::NoGame)
+}
+
+async fn find_account(db: &dyn GameStore, name: &str) -> Result<Account, AdminError> {
+	db.find_account(&name_key(name)).await?.ok_or(AdminError::NoAccount)
+}
+
+// Store a changed game and rate it and every game after it again, since a
+// changed result moves every rating after it
+async fn save_game(db: &dyn GameStore, game: &Game) -> Result<(), AdminError> {
+	if !db.update_game(game).await? {
+		return Err(AdminError::NoGame);
+	}
+	Ratings::recompute_from(db, game).await?;
+	Ok(())
+}
+
+// Correct the players, result or details of a stored game. A renamed seat
+// loses its account, and a winner or first mover under the old name follows
+// the rename unless the edit sets them too.
+pub async fn edit_game(db: &dyn GameStore, id: &str, edit: GameEdit) -> Result<Game, AdminError> {
+	let mut game = find_game(db, id).await?;
+	let renames = [(edit.player1, &mut game.player1, &mut game.player1_id), (edit.player2, &mut game.player2, &mut game.player2_id)];
+	let mut renamed = Vec::new();
+	for (name, player, player_id) in renames {
+		if let Some(name) = name.filter(|name| name != player) {
+			renamed.push((std::mem::replace(player, name.clone()), name));
+			*player_id = None;
+		}
+	}
+	for (old, new) in &renamed {
+		if game.winner == *old {
+			game.winner = new.clone();
+		}
+		if game.first_mover.as_ref() == Some(old) {
+			game.first_mover = Some(new.clone());
+		}
+	}
+	if let Some(winner) = edit.winner {
+		game.winner = winner;
+	}
+	if let Some(first_mover) = edit.first_mover {
+		game.first_mover = Some(first_mover);
+	}
+	if let Some(word) = edit.player1_word {
+		game.player1_word = Some(word);
+	}
+	if let Some(level) = edit.difficulty {
+		game.difficulty = Some(level);
+	}
+	if game.moves.is_empty() { game.check_record() } else { game.check() }.map_err(AdminError::Invalid)?;
+	check_details(&game).map_err(AdminError::Invalid)?;
+	save_game(db, &game).await?;
+	Ok(game)
+}
+
+// Voided games stay stored but count for nothing: not listed, not in the
+// statistics and not rated
+pub async fn set_voided(db: &dyn GameStore, id: &str, voided: bool) -> Result<Game, AdminError> {
+	let mut game = find_game(db, id).await?;
+	if game.voided != voided {
+		game.voided = voided;
+		save_game(db, &game).await?;
+	}
+	Ok(game)
+}
+
+pub async fn delete_game(db: &dyn GameStore, id: &str) -> Result<(), AdminError> {
+	let game = find_game(db, id).await?;
+	if !db.delete_game(id).await? {
+		return Err(AdminError::NoGame);
+	}
+	Ratings::recompute_from(db, &game).await?;
+	Ok(())
+}
+
+// Every game the guest `name` played, oldest first and voided ones too;
+// seats of accounts that had this name are not the guest's
+async fn games_of(db: &dyn GameStore, name: &str) -> Result<Vec<Game>, AdminError> {
+	let guest = PlayerKey::Guest(name.to_string());
+	let query = GameQuery { player: Some(name.to_string()), sort: Some("oldest".to_string()), limit: Some(MAX_LIMIT), ..Default::default() };
+	let mut filter = query.validate().map_err(AdminError::Invalid)?;
+	filter.voided = None;
+	let mut games = Vec::new();
+	loop {
+		let page = filter.page(db.find_games(&filter).await?);
+		let after = page.games.last().map(|last| (last.ended_at.clone().unwrap_or_default(), last.id.clone().unwrap_or_default()));
+		games.extend(page.games.into_iter().filter(|game| guest.seat(game).is_some()));
+		match (page.next_cursor, after) {
+			(Some(_), Some(after)) => filter.after = Some(after),
+			_ => return Ok(games),
+		}
+	}
+}
+
+// Record every game the guest `from` played under `into`, for players who
+// played under two names. When `into` is an account's name the games join
+// that account, so they count toward its statistics and rating. An
+// account's games are its own, so `from` may not be an account's name.
+pub async fn merge_players(db: &dyn GameStore, merge: MergePlayers) -> Result<GamesChanged, AdminError> {
+	check_player_name(&merge.into).map_err(AdminError::Invalid)?;
+	if merge.from == merge.into {
+		return Err(AdminError::Invalid("the names to merge are the same".to_string()));
+	}
+	if [&merge.from, &merge.into].iter().any(|name| name.starts_with(COMPUTER)) {
+		return Err(AdminError::Invalid(format!("{:?} cannot be merged", COMPUTER)));
+	}
+	if db.find_account(&name_key(&merge.from)).await?.is_some() {
+		return Err(AdminError::Invalid(format!("{:?} is an account, only guest names can be merged", merge.from)));
+	}
+	let into_id = db.find_account(&name_key(&merge.into)).await?.and_then(|account| account.id);
+	let into = PlayerKey::new(&merge.into, into_id.as_deref());
+	let games = games_of(db, &merge.from).await?;
+	if games.iter().any(|game| game.player1 == merge.into || game.player2 == merge.into || into.seat(game).is_some()) {
+		return Err(AdminError::Invalid(format!("{:?} and {:?} played each other", merge.from, merge.into)));
+	}
+	if games.is_empty() {
+		return Ok(GamesChanged { games: 0 });
+	}
+	let count = db.rename_player(&merge.from, &merge.into, into_id.as_deref()).await?;
+	// `games` is oldest first, nothing before the first one changed
+	Ratings::recompute_from(db, &games[0]).await?;
+	Ok(GamesChanged { games: count })
+}
+
+// Banning also ends every session of the account
+pub async fn set_banned(db: &dyn GameStore, admin: &User, name: &str, banned: bool) -> Result<User, AdminError> {
+	let mut account = find_account(db, name).await?;
+	if banned && account.id.as_deref() == Some(admin.id.as_str()) {
+		return Err(AdminError::Invalid("you cannot ban yourself".to_string()));
+	}
+	account.banned = banned;
+	if !db.update_account(&account).await? {
+		return Err(AdminError::NoAccount);
+	}
+	if banned {
+		db.delete_sessions(account.id.as_deref().unwrap_or_default()).await?;
+	}
+	Ok(account.user())
+}
+
+pub async fn set_role(db: &dyn GameStore, name: &str, role: Role) -> Result<User, AdminError> {
+	let mut account = find_account(db, name).await?;
+	account.role = role;
+	if !db.update_account(&account).await? {
+		return Err(AdminError::NoAccount);
+	}
+	Ok(account.user())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::Storage::fixtures::{account, game, stores};
+
+	fn merge(from: &str, into: &str) -> MergePlayers {
+		MergePlayers { from: from.to_string(), into: into.to_string() }
+	}
+
+	#[rocket::async_test]
+	async fn merges_only_guests() {
+		for db in stores().await {
+			let db = db.as_ref();
+			let ann = db.insert_account(account("ann")).await.unwrap().id;
+			let robert = db.insert_account(account("robert")).await.unwrap().id;
+			let owned = db.insert_game(Game { player1_id: ann.clone(), ..game("Connect4", "ann", "cat", "ann", 1) }).await.unwrap();
+			db.insert_game(game("Connect4", "bob", "cat", "bob", 2)).await.unwrap();
+
+			assert!(matches!(merge_players(db, merge("ann", "robert")).await, Err(AdminError::Invalid(_))));
+			let kept = db.get_game(owned.id.as_deref().unwrap()).await.unwrap().unwrap();
+			assert_eq!((kept.player1.as_str(), &kept.player1_id), ("ann", &ann));
+
+			let Ok(changed) = merge_players(db, merge("bob", "robert")).await else { panic!("merge failed") };
+			assert_eq!(changed.games, 1);
+			assert_eq!(games_of(db, "bob").await.ok().map(|games| games.len()), Some(0));
+			let robert = PlayerKey::Account(robert.unwrap());
+			let rated = db.latest_rating("Connect4", &robert).await.unwrap().unwrap();
+			assert_eq!((rated.player.as_str(), rated.games), ("robert", 1));
+		}
+	}
+}
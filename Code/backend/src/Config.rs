@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+// Settings read from Rocket.toml and ROCKET_* environment variables (a .env
+// file is loaded first), e.g. ROCKET_STORAGE=sqlite or
+// ROCKET_CORS_ORIGINS='["https://games.example.com"]'
+#[derive(Clone, Debug, Deserialize)]
+pub struct AppConfig {
+	#[serde(default = "default_storage")]
+	pub storage: String,
+	#[serde(default = "default_mongo_uri")]
+	pub mongo_uri: String,
+	#[serde(default = "default_mongo_database")]
+	pub mongo_database: String,
+	#[serde(default = "default_games_collection")]
+	pub games_collection: String,
+	#[serde(default = "default_ratings_collection")]
+	pub ratings_collection: String,
+	#[serde(default = "default_accounts_collection")]
+	pub accounts_collection: String,
+	#[serde(default = "default_sessions_collection")]
+	pub sessions_collection: String,
+	// how long a MongoDB request waits for a server before failing
+	#[serde(default = "default_mongo_timeout_secs")]
+	pub mongo_timeout_secs: u64,
+	// how often the database connection is checked
+	#[serde(default = "default_health_check_secs")]
+	pub health_check_secs: u64,
+	#[serde(default = "default_sqlite_path")]
+	pub sqlite_path: String,
+	#[serde(default = "default_cors_origins")]
+	pub cors_origins: Vec<String>,
+	// ids of the accounts that may use the admin routes, as `GET /users/me`
+	// shows them; ids cannot be registered the way names can
+	#[serde(default)]
+	pub admins: Vec<String>,
+	// what each client may send to each route that writes
+	#[serde(default = "default_rate_limit")]
+	pub rate_limit: RateLimit,
+	// other limits for some of them, by "METHOD /path" prefix, the longest wins
+	#[serde(default = "default_rate_limits")]
+	pub rate_limits: HashMap<String, RateLimit>,
+}
+
+// A token bucket: `burst` requests at once, refilled at `per_minute`
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq)]
+pub struct RateLimit {
+	pub burst: u32,
+	pub per_minute: u32,
+}
+
+fn default_storage() -> String {
+	"mongo".to_string()
+}
+
+fn default_mongo_uri() -> String {
+	"mongodb://localhost:27017".to_string()
+}
+
+fn default_mongo_database() -> String {
+	"Project3".to_string()
+}
+
+fn default_games_collection() -> String {
+	"Games".to_string()
+}
+
+fn default_ratings_collection() -> String {
+	"Ratings".to_string()
+}
+
+fn default_accounts_collection() -> String {
+	"Accounts".to_string()
+}
+
+fn default_sessions_collection() -> String {
+	"Sessions".to_string()
+}
+
+fn default_mongo_timeout_secs() -> u64 {
+	5
+}
+
+fn default_health_check_secs() -> u64 {
+	30
+}
+
+fn default_sqlite_path() -> String {
+	"games.db".to_string()
+}
+
+// where `trunk serve` hosts the frontend
+fn default_cors_origins() -> Vec<String> {
+	vec!["http://127.0.0.1:8080".to_string(), "http://localhost:8080".to_string()]
+}
+
+fn default_rate_limit() -> RateLimit {
+	RateLimit { burst: 30, per_minute: 60 }
+}
+
+// signing up and logging in are where guessing and spam come from, and
+// engine searches are the most expensive thing the server does
+fn default_rate_limits() -> HashMap<String, RateLimit> {
+	HashMap::from([
+		("POST /users".to_string(), RateLimit { burst: 5, per_minute: 5 }),
+		("POST /sessions".to_string(), RateLimit { burst: 10, per_minute: 10 }),
+		("POST /ai/move".to_string(), RateLimit { burst: 10, per_minute: 30 }),
+	])
+}
+
+impl AppConfig {
+	// Catch bad settings at startup with a message saying which key to fix
+	pub fn validate(&self) -> Result<(), String> {
+		if !["mongo", "memory", "sqlite"].contains(&self.storage.as_str()) {
+			return Err(format!("`storage` must be \"mongo\", \"memory\" or \"sqlite\", not {:?}", self.storage));
+		}
+		if self.storage == "mongo" {
+			if !self.mongo_uri.starts_with("mongodb://") && !self.mongo_uri.starts_with("mongodb+srv://") {
+				return Err(format!("`mongo_uri` must start with mongodb:// or mongodb+srv://, not {:?}", self.mongo_uri));
+			}
+			check_name("mongo_database", &self.mongo_database, &['/', '\\', '.', ' ', '"', '$', '\0'])?;
+		}
+		if self.mongo_timeout_secs == 0 {
+			return Err("`mongo_timeout_secs` must be at least 1".to_string());
+		}
+		if self.health_check_secs == 0 {
+			return Err("`health_check_secs` must be at least 1".to_string());
+		}
+		if self.storage == "sqlite" && self.sqlite_path.trim().is_empty() {
+			return Err("`sqlite_path` must not be empty".to_string());
+		}
+		check_name("games_collection", &self.games_collection, &['$', '\0'])?;
+		check_name("ratings_collection", &self.ratings_collection, &['$', '\0'])?;
+		check_name("accounts_collection", &self.accounts_collection, &['$', '\0'])?;
+		check_name("sessions_collection", &self.sessions_collection, &['$', '\0'])?;
+		for origin in &self.cors_origins {
+			let valid = origin == "*"
+				|| ((origin.starts_with("http://") || origin.starts_with("https://")) && !origin.ends_with('/'));
+			if !valid {
+				return Err(format!("`cors_origins` entry {:?} must be \"*\" or a scheme and host like \"http://localhost:8080\" without a trailing slash", origin));
+			}
+		}
+		check_rate_limit("rate_limit", &self.rate_limit)?;
+		for (route, limit) in &self.rate_limits {
+			let valid = route.split_once(' ').is_some_and(|(method, path)| {
+				["POST", "PUT", "PATCH", "DELETE"].contains(&method) && path.starts_with('/')
+			});
+			if !valid {
+				return Err(format!("`rate_limits` key {:?} must be a write method and a path like \"POST /users\"", route));
+			}
+			check_rate_limit(&format!("rate_limits.{:?}", route), limit)?;
+		}
+		Ok(())
+	}
+
+	// The `rate_limits` entry for a write request, from its method and
+	// path; None when `rate_limit` applies
+	pub fn rate_limit_for(&self, method: &str, path: &str) -> Option<(&str, RateLimit)> {
+		self.rate_limits
+			.iter()
+			.filter(|(route, _)| route.split_once(' ').is_some_and(|(m, prefix)| m == method && path.starts_with(prefix)))
+			.max_by_key(|(route, _)| route.len())
+			.map(|(route, limit)| (route.as_str(), *limit))
+	}
+
+	pub fn allows_origin(&self, origin: &str) -> bool {
+		self.cors_origins.iter().any(|allowed| allowed == "*" || allowed == origin)
+	}
+}
+
+// MongoDB names cannot be empty or contain certain characters
+fn check_name(key: &str, name: &str, forbidden: &[char]) -> Result<(), String> {
+	if name.is_empty() || name.contains(forbidden) {
+		return Err(format!("`{}` must be a non-empty name without any of {:?}, not {:?}", key, forbidden, name));
+	}
+	Ok(())
+}
+
+fn check_rate_limit(key: &str, limit: &RateLimit) -> Result<(), String> {
+	if limit.burst == 0 || limit.per_minute == 0 {
+		return Err(format!("`{}` must allow a burst and a rate per minute of at least 1, not {:?}", key, limit));
+	}
+	Ok(())
+}
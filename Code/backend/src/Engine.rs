@@ -0,0 +1,145 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::Backend::{AiMove, AiMoveRequest, MoveScore};
+use common::Position::{Move, Position};
+use common::Search;
+use common::Skill::{self, LEVELS, WIN};
+
+// longest search a request may ask for
+const MAX_TIME_MS: u64 = 10_000;
+// a 6x7 board has no more plies left than this
+const MAX_DEPTH: usize = 42;
+
+// The engine's move for the side to move, searched natively on the server.
+// With `time_ms` the search deepens at full strength until the budget runs
+// out; otherwise it plays like the chosen level.
+pub fn ai_move(request: AiMoveRequest) -> Result<AiMove, String> {
+	let start = Instant::now();
+	let position = Position::parse(request.gametype, &request.position)?;
+	if position.is_over() {
+		return Err("the game is already over".to_string());
+	}
+	let skill = match request.difficulty {
+		Some(level) if !(1..=LEVELS.len()).contains(&level) => {
+			return Err(format!("difficulty must be 1 to {}, not {}", LEVELS.len(), level));
+		}
+		Some(level) => Skill::level(level),
+		None => LEVELS[LEVELS.len() - 1],
+	};
+	let threads = Search::available_threads();
+	let moves = Skill::ordered_moves(&position);
+	let depth = skill.depth(request.gametype);
+
+	let (mv, depth, scores) = match request.time_ms {
+		Some(budget) if budget == 0 || budget > MAX_TIME_MS => {
+			return Err(format!("time_ms must be 1 to {}, not {}", MAX_TIME_MS, budget));
+		}
+		Some(budget) => {
+			let (depth, scores) = deepen(&position, &moves, budget, threads, start);
+			(best(&moves, &scores), depth, Some(scores))
+		}
+		None => {
+			let mv = Skill::choose_move(&position, &skill, threads, &mut rand::thread_rng()).ok_or("no legal moves")?;
+			let scores = request.analysis.then(|| Skill::score_moves(&position, &moves, depth, skill.horizon, threads));
+			(mv, depth, scores)
+		}
+	};
+
+	let mut after = position.clone();
+	after.play(mv)?;
+	let analysis = scores.filter(|_| request.analysis).map(|scores| {
+		let mut analysis: Vec<MoveScore> =
+			moves.iter().zip(scores).map(|(mv, score)| MoveScore { mv: mv.to_string(), score }).collect();
+		analysis.sort_by_key(|entry| std::cmp::Reverse(entry.score));
+		analysis
+	});
+	Ok(AiMove {
+		mv: mv.to_string(),
+		position: after.to_string(),
+		depth,
+		elapsed_ms: start.elapsed().as_millis() as u64,
+		analysis,
+	})
+}
+
+// Iterative deepening from one ply, seeing every win it searches, until the
+// budget runs out; answers with the deepest search that finished. Stops early
+// once a win or loss is certain, since deeper will not change it.
+fn deepen(position: &Position, moves: &[Move], budget: u64, threads: usize, start: Instant) -> (usize, Vec<i32>) {
+	let deadline = Duration::from_millis(budget).saturating_sub(start.elapsed());
+	let stop = AtomicBool::new(false);
+	let (finished, search_done) = mpsc::channel::<()>();
+	thread::scope(|scope| {
+		let stop = &stop;
+		// raises `stop` at the deadline, or quits as soon as the search is over
+		scope.spawn(move || {
+			if let Err(RecvTimeoutError::Timeout) = search_done.recv_timeout(deadline) {
+				stop.store(true, Ordering::Relaxed);
+			}
+		});
+		// one ply always finishes, it is over in no time
+		let mut depth = 1;
+		let mut scores = Skill::score_moves(position, moves, depth, depth, threads);
+		while !decided(&scores) && depth < MAX_DEPTH {
+			match Skill::score_moves_until(position, moves, depth + 1, depth + 1, threads, stop) {
+				Some(deeper) => {
+					depth += 1;
+					scores = deeper;
+				}
+				None => break,
+			}
+		}
+		drop(finished);
+		(depth, scores)
+	})
+}
+
+// A win or loss is certain whatever the deeper plies hold
+fn decided(scores: &[i32]) -> bool {
+	scores.iter().all(|score| score.abs() >= WIN - MAX_DEPTH as i32)
+		|| scores.iter().any(|score| *score >= WIN - MAX_DEPTH as i32)
+}
+
+// First of the best scoring moves, in the search's centre-first order
+fn best(moves: &[Move], scores: &[i32]) -> Move {
+	let top = scores.iter().max().copied().unwrap_or(0);
+	let index = scores.iter().position(|score| *score == top).unwrap_or(0);
+	moves[index]
+}
+
+#[cfg(test)]
+mod tests {
+	use common::Position::GameType;
+
+	use super::*;
+
+	fn request(gametype: GameType, time_ms: u64) -> AiMoveRequest {
+		AiMoveRequest {
+			gametype,
+			position: Position::new(gametype).to_string(),
+			difficulty: None,
+			time_ms: Some(time_ms),
+			analysis: true,
+		}
+	}
+
+	// the opening is far too deep to search out, so only the budget ends it
+	#[test]
+	fn keeps_to_the_time_budget() {
+		for gametype in [GameType::Connect4, GameType::TootOtto] {
+			let answer = ai_move(request(gametype, 200)).unwrap();
+			assert!(answer.elapsed_ms < 200 + 150, "{:?} took {} ms", gametype, answer.elapsed_ms);
+			assert!((1..MAX_DEPTH).contains(&answer.depth));
+			assert_eq!(answer.analysis.unwrap().len(), Skill::ordered_moves(&Position::new(gametype)).len());
+		}
+	}
+
+	#[test]
+	fn turns_away_budgets_out_of_range() {
+		assert!(ai_move(request(GameType::Connect4, 0)).is_err());
+		assert!(ai_move(request(GameType::Connect4, MAX_TIME_MS + 1)).is_err());
+	}
+}
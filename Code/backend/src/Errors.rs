@@ -0,0 +1,145 @@
+
+use common::Backend::ErrorBody;
+use rocket::http::{Header, Status};
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use rocket::serde::json::Json;
+
+use crate::Accounts::AccountError;
+use crate::Admin::AdminError;
+use crate::Lobby::LobbyError;
+use crate::Matches::MatchError;
+use crate::Query::InvalidCursor;
+use crate::Storage::StoreError;
+use crate::Throttle;
+
+// An error answer: its status code and a JSON `ErrorBody` saying what went wrong.
+// 400 for input that is malformed or fails validation, 503 when the database fails.
+#[derive(Debug)]
+pub struct ApiError {
+	pub status: Status,
+	pub message: String,
+	// seconds to wait before trying again, sent as `Retry-After`
+	pub retry_after: Option<u64>,
+}
+
+impl ApiError {
+	pub fn new(status: Status, message: impl Into<String>) -> Self {
+		ApiError { status, message: message.into(), retry_after: None }
+	}
+
+	pub fn bad_request(message: impl Into<String>) -> Self {
+		Self::new(Status::BadRequest, message)
+	}
+
+	pub fn not_found(message: impl Into<String>) -> Self {
+		Self::new(Status::NotFound, message)
+	}
+
+	pub fn forbidden(message: impl Into<String>) -> Self {
+		Self::new(Status::Forbidden, message)
+	}
+
+	pub fn conflict(message: impl Into<String>) -> Self {
+		Self::new(Status::Conflict, message)
+	}
+
+	pub fn unavailable(message: impl std::fmt::Display) -> Self {
+		Self::new(Status::ServiceUnavailable, format!("the database is unavailable: {}", message))
+	}
+
+	pub fn internal(message: impl Into<String>) -> Self {
+		Self::new(Status::InternalServerError, message)
+	}
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+	fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+		let body = ErrorBody { status: self.status.code, error: self.message };
+		let mut response = Response::build_from(Json(body).respond_to(request)?);
+		if let Some(wait) = self.retry_after {
+			response.header(Header::new("Retry-After", wait.to_string()));
+		}
+		response.status(self.status).ok()
+	}
+}
+
+// Errors out of a store, apart from bad cursors, mean the database failed
+impl From<StoreError> for ApiError {
+	fn from(err: StoreError) -> Self {
+		if err.is::<InvalidCursor>() {
+			ApiError::bad_request(err.to_string())
+		} else {
+			ApiError::unavailable(err)
+		}
+	}
+}
+
+impl From<MatchError> for ApiError {
+	fn from(err: MatchError) -> Self {
+		match err {
+			MatchError::NotFound => ApiError::not_found("no match with this id"),
+			MatchError::Over => ApiError::conflict("the game is already over"),
+			MatchError::Invalid(message) => ApiError::bad_request(message),
+			MatchError::Forbidden(message) => ApiError::forbidden(message),
+			MatchError::Waiting => ApiError::conflict("waiting for an opponent to join"),
+			MatchError::Full => ApiError::conflict("someone has already joined this match"),
+			MatchError::Engine(message) => ApiError::internal(message),
+			MatchError::Storage(message) => ApiError::unavailable(message),
+		}
+	}
+}
+
+impl From<AccountError> for ApiError {
+	fn from(err: AccountError) -> Self {
+		match err {
+			AccountError::Invalid(message) => ApiError::bad_request(message),
+			AccountError::Taken => ApiError::conflict("this name is taken"),
+			AccountError::WrongCredentials => ApiError::new(Status::Unauthorized, "wrong name or password"),
+			AccountError::Banned => ApiError::forbidden("this account is banned"),
+			AccountError::Storage(message) => ApiError::unavailable(message),
+		}
+	}
+}
+
+impl From<AdminError> for ApiError {
+	fn from(err: AdminError) -> Self {
+		match err {
+			AdminError::NoGame => ApiError::not_found("no game with this id"),
+			AdminError::NoAccount => ApiError::not_found("no account with this name"),
+			AdminError::Invalid(message) => ApiError::bad_request(message),
+			AdminError::Storage(message) => ApiError::unavailable(message),
+		}
+	}
+}
+
+impl From<LobbyError> for ApiError {
+	fn from(err: LobbyError) -> Self {
+		match err {
+			LobbyError::NotQueued => ApiError::not_found("you are not in the queue"),
+			LobbyError::NoRoom => ApiError::not_found("no open room with this code"),
+			LobbyError::Invalid(message) => ApiError::bad_request(message),
+			LobbyError::Forbidden(message) => ApiError::forbidden(message),
+			LobbyError::Matched => ApiError::conflict("you have already been paired, play the match"),
+			LobbyError::Match(err) => err.into(),
+		}
+	}
+}
+
+// Errors Rocket answers itself, such as unknown routes, failed request
+// guards and bodies or query strings it cannot parse, in the same format
+#[catch(default)]
+pub fn catch_all(status: Status, request: &Request) -> ApiError {
+	match status.code {
+		400 | 422 => ApiError::bad_request("the request body or query string is not valid"),
+		401 => ApiError::new(status, "log in first: send `Authorization: Bearer <token>` with a token from POST /sessions"),
+		403 => ApiError::forbidden("this needs an admin account, see the account ids in `admins` in the configuration"),
+		404 => ApiError::not_found(format!("nothing at {} {}", request.method(), request.uri())),
+		413 => ApiError::new(status, "the request body is larger than this route accepts"),
+		429 => {
+			let wait = Throttle::retry_after(request).unwrap_or(1);
+			ApiError { retry_after: Some(wait), ..ApiError::new(status, format!("too many requests, try again in {} seconds", wait)) }
+		}
+		_ => ApiError::new(status, status.reason_lossy()),
+	}
+}
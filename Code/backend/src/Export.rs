@@ -0,0 +1,484 @@
+use std::collections::{BTreeMap, HashSet};
+
+use chrono::prelude::*;
+use chrono::Duration;
+use common::Backend::{standard_variant, Game, ImportProblem, ImportReport};
+use rocket::http::{ContentType, Header};
+use rocket::serde::json::{self, Value};
+use serde::{Deserialize, Serialize};
+
+use crate::Query::{GameFilter, COMPUTER, MAX_LIMIT};
+use crate::Ratings;
+use crate::Storage::{GameStore, StoreError};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Format {
+	Csv,
+	Json,
+	Pgn,
+}
+
+impl Format {
+	// JSON unless the query string asks for another
+	pub fn parse(format: Option<&str>) -> Result<Format, String> {
+		match format.unwrap_or("json") {
+			"csv" => Ok(Format::Csv),
+			"json" => Ok(Format::Json),
+			"pgn" => Ok(Format::Pgn),
+			other => Err(format!("`format` must be csv, json or pgn, not {:?}", other)),
+		}
+	}
+
+	pub fn content_type(self) -> ContentType {
+		match self {
+			Format::Csv => ContentType::CSV,
+			Format::Json => ContentType::JSON,
+			Format::Pgn => ContentType::new("application", "x-chess-pgn"),
+		}
+	}
+
+	fn extension(self) -> &'static str {
+		match self {
+			Format::Csv => "csv",
+			Format::Json => "json",
+			Format::Pgn => "pgn",
+		}
+	}
+}
+
+// A file the browser saves rather than shows
+#[derive(Responder)]
+pub struct Download<R> {
+	inner: R,
+	content_type: ContentType,
+	disposition: Header<'static>,
+}
+
+impl<R> Download<R> {
+	pub fn new(inner: R, format: Format) -> Self {
+		let disposition = format!("attachment; filename=\"games.{}\"", format.extension());
+		Download { inner, content_type: format.content_type(), disposition: Header::new("Content-Disposition", disposition) }
+	}
+}
+
+// CSV has no lists, so the moves are one column separated by spaces
+#[derive(Serialize, Deserialize)]
+struct Row {
+	#[serde(default)]
+	id: Option<String>,
+	gametype: String,
+	player1: String,
+	player2: String,
+	winner: String,
+	started_at: Option<String>,
+	ended_at: Option<String>,
+	duration_secs: Option<i64>,
+	move_count: Option<u32>,
+	difficulty: Option<usize>,
+	variant: String,
+	first_mover: Option<String>,
+	player1_word: Option<String>,
+	moves: String,
+}
+
+const COLUMNS: [&str; 14] = [
+	"id", "gametype", "player1", "player2", "winner", "started_at", "ended_at", "duration_secs",
+	"move_count", "difficulty", "variant", "first_mover", "player1_word", "moves",
+];
+
+impl From<&Game> for Row {
+	fn from(game: &Game) -> Self {
+		Row {
+			id: game.id.clone(),
+			gametype: game.gametype.clone(),
+			player1: game.player1.clone(),
+			player2: game.player2.clone(),
+			winner: game.winner.clone(),
+			started_at: game.started_at.clone(),
+			ended_at: game.ended_at.clone(),
+			duration_secs: game.duration_secs,
+			move_count: game.move_count,
+			difficulty: game.difficulty,
+			variant: game.variant.clone(),
+			first_mover: game.first_mover.clone(),
+			player1_word: game.player1_word.clone(),
+			moves: game.moves.join(" "),
+		}
+	}
+}
+
+impl From<Row> for Game {
+	fn from(row: Row) -> Self {
+		Game {
+			id: row.id,
+			gametype: row.gametype,
+			player1: row.player1,
+			player2: row.player2,
+			winner: row.winner,
+			started_at: row.started_at,
+			ended_at: row.ended_at,
+			duration_secs: row.duration_secs,
+			move_count: row.move_count,
+			difficulty: row.difficulty,
+			variant: row.variant,
+			first_mover: row.first_mover,
+			player1_word: row.player1_word,
+			moves: row.moves.split_whitespace().map(str::to_string).collect(),
+			player1_id: None,
+			player2_id: None,
+			voided: false,
+		}
+	}
+}
+
+// What comes before the first game
+pub fn header(format: Format) -> Vec<u8> {
+	match format {
+		Format::Csv => format!("{}\n", COLUMNS.join(",")).into_bytes(),
+		Format::Json => b"[".to_vec(),
+		Format::Pgn => Vec::new(),
+	}
+}
+
+// One page of games; `first` is the first page of the file
+pub fn encode(format: Format, games: &[Game], first: bool) -> Vec<u8> {
+	match format {
+		Format::Csv => {
+			let mut writer = csv::WriterBuilder::new().has_headers(false).from_writer(Vec::new());
+			for game in games {
+				if let Err(err) = writer.serialize(Row::from(game)) {
+					error!("cannot write game {:?} as CSV: {}", game.id, err);
+				}
+			}
+			writer.into_inner().unwrap_or_default()
+		}
+		Format::Json => {
+			let mut out = Vec::new();
+			for (i, game) in games.iter().enumerate() {
+				if !first || i > 0 {
+					out.push(b',');
+				}
+				out.push(b'\n');
+				out.extend(json::to_string(game).unwrap_or_default().into_bytes());
+			}
+			out
+		}
+		Format::Pgn => games.iter().map(pgn).collect::<String>().into_bytes(),
+	}
+}
+
+// What comes after the last game
+pub fn footer(format: Format) -> Vec<u8> {
+	match format {
+		Format::Json => b"\n]\n".to_vec(),
+		Format::Csv | Format::Pgn => Vec::new(),
+	}
+}
+
+// The filter for the page after this one, None after the last
+pub fn next_page(filter: &GameFilter, games: &[Game]) -> Option<GameFilter> {
+	if games.len() <= filter.limit {
+		return None;
+	}
+	let last = &games[filter.limit - 1];
+	let after = (last.ended_at.clone().unwrap_or_default(), last.id.clone().unwrap_or_default());
+	Some(GameFilter { after: Some(after), ..filter.clone() })
+}
+
+// Results as PGN writes them, from player1's side
+fn pgn_result(game: &Game) -> &'static str {
+	if game.winner.is_empty() {
+		"1/2-1/2"
+	} else if game.winner == game.player1 {
+		"1-0"
+	} else {
+		"0-1"
+	}
+}
+
+fn pgn_tag(out: &mut String, name: &str, value: &str) {
+	out.push_str(&format!("[{} \"{}\"]\n", name, value.replace('\\', "\\\\").replace('"', "\\\"")));
+}
+
+// A game in PGN: the seven tags every reader expects, with White and Black
+// standing for player1 and player2 whoever moved first, then the rest of
+// the record as tags of its own, then the moves numbered in pairs
+fn pgn(game: &Game) -> String {
+	let mut out = String::new();
+	let date = game.ended_at.as_deref().and_then(|ended_at| DateTime::parse_from_rfc3339(ended_at).ok());
+	pgn_tag(&mut out, "Event", &game.gametype);
+	pgn_tag(&mut out, "Site", "?");
+	pgn_tag(&mut out, "Date", &date.map_or("????.??.??".to_string(), |date| date.format("%Y.%m.%d").to_string()));
+	pgn_tag(&mut out, "Round", "-");
+	pgn_tag(&mut out, "White", &game.player1);
+	pgn_tag(&mut out, "Black", &game.player2);
+	pgn_tag(&mut out, "Result", pgn_result(game));
+	pgn_tag(&mut out, "Variant", &game.variant);
+	let optional = [
+		("Id", game.id.clone()),
+		("FirstMover", game.first_mover.clone()),
+		("Player1Word", game.player1_word.clone()),
+		("Difficulty", game.difficulty.map(|level| level.to_string())),
+		("StartedAt", game.started_at.clone()),
+		("EndedAt", game.ended_at.clone()),
+		("DurationSecs", game.duration_secs.map(|secs| secs.to_string())),
+	];
+	for (name, value) in optional {
+		if let Some(value) = value {
+			pgn_tag(&mut out, name, &value);
+		}
+	}
+	out.push('\n');
+	for (ply, mv) in game.moves.iter().enumerate() {
+		if ply % 2 == 0 {
+			out.push_str(&format!("{}. ", ply / 2 + 1));
+		}
+		out.push_str(mv);
+		out.push(' ');
+	}
+	out.push_str(pgn_result(game));
+	out.push_str("\n\n");
+	out
+}
+
+// Games of a PGN file: tag pairs, then moves up to the result
+fn parse_pgn(text: &str) -> Vec<Result<Game, String>> {
+	let mut records = Vec::new();
+	let mut tags: BTreeMap<String, String> = BTreeMap::new();
+	let mut moves: Vec<String> = Vec::new();
+	let mut in_moves = false;
+	for line in text.lines().map(str::trim) {
+		if line.starts_with('[') {
+			if in_moves {
+				records.push(Err("a game's moves do not end with a result".to_string()));
+				tags.clear();
+				moves.clear();
+				in_moves = false;
+			}
+			match parse_tag(line) {
+				Some((name, value)) => {
+					tags.insert(name, value);
+				}
+				None => records.push(Err(format!("not a tag pair: {:?}", line))),
+			}
+			continue;
+		}
+		for token in line.split_whitespace() {
+			in_moves = true;
+			if ["1-0", "0-1", "1/2-1/2", "*"].contains(&token) {
+				records.push(pgn_game(&tags, std::mem::take(&mut moves), token));
+				tags.clear();
+				in_moves = false;
+			} else if !token.ends_with('.') {
+				moves.push(token.to_string());
+			}
+		}
+	}
+	if in_moves || !tags.is_empty() {
+		records.push(Err("the last game does not end with a result".to_string()));
+	}
+	records
+}
+
+fn parse_tag(line: &str) -> Option<(String, String)> {
+	let inner = line.strip_prefix('[')?.strip_suffix(']')?;
+	let (name, value) = inner.split_once(' ')?;
+	let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+	Some((name.to_string(), value.replace("\\\"", "\"").replace("\\\\", "\\")))
+}
+
+fn pgn_game(tags: &BTreeMap<String, String>, moves: Vec<String>, result: &str) -> Result<Game, String> {
+	let tag = |name: &str| tags.get(name).cloned();
+	let required = |name: &str| tag(name).ok_or_else(|| format!("the game has no {} tag", name));
+	let player1 = required("White")?;
+	let player2 = required("Black")?;
+	let winner = match result {
+		"1-0" => player1.clone(),
+		"0-1" => player2.clone(),
+		"1/2-1/2" => String::new(),
+		_ => return Err("only finished games can be imported".to_string()),
+	};
+	let number = |name: &str| tag(name).map(|value| value.parse().map_err(|_| format!("the {} tag must be a number, not {:?}", name, value))).transpose();
+	Ok(Game {
+		id: tag("Id"),
+		gametype: required("Event")?,
+		player1,
+		player2,
+		winner,
+		started_at: tag("StartedAt"),
+		ended_at: tag("EndedAt"),
+		duration_secs: number("DurationSecs")?,
+		move_count: None,
+		difficulty: number("Difficulty")?.map(|level: i64| level as usize),
+		variant: tag("Variant").unwrap_or_else(standard_variant),
+		first_mover: tag("FirstMover"),
+		player1_word: tag("Player1Word"),
+		moves,
+		player1_id: None,
+		player2_id: None,
+		voided: false,
+	})
+}
+
+// Every record of a file, each parsed on its own so one bad record does
+// not stop the others. Fails only if the file as a whole cannot be read.
+pub fn parse(format: Format, text: &str) -> Result<Vec<Result<Game, String>>, String> {
+	match format {
+		Format::Json => {
+			let values: Vec<Value> = json::from_str(text).map_err(|err| format!("the file is not a JSON array: {}", err))?;
+			Ok(values.into_iter().map(|value| json::from_value(value).map_err(|err| err.to_string())).collect())
+		}
+		Format::Csv => {
+			let mut reader = csv::Reader::from_reader(text.as_bytes());
+			let headers = reader.headers().map_err(|err| format!("the file has no CSV header: {}", err))?;
+			if let Some(missing) = COLUMNS.iter().skip(1).find(|column| !headers.iter().any(|header| header == **column)) {
+				return Err(format!("the CSV header has no {:?} column", missing));
+			}
+			Ok(reader.deserialize::<Row>().map(|row| row.map(Game::from).map_err(|err| err.to_string())).collect())
+		}
+		Format::Pgn => Ok(parse_pgn(text)),
+	}
+}
+
+fn normal_time(key: &str, time: &str) -> Result<DateTime<Utc>, String> {
+	DateTime::parse_from_rfc3339(time)
+		.map(|time| time.with_timezone(&Utc))
+		.map_err(|_| format!("`{}` must be an RFC 3339 time, not {:?}", key, time))
+}
+
+// The checks on the fields `Game::check` leaves alone
+pub fn check_details(game: &Game) -> Result<(), String> {
+	if let Some(first) = &game.first_mover {
+		if *first != game.player1 && *first != game.player2 {
+			return Err(format!("the first mover must be one of the players, not {:?}", first));
+		}
+	}
+	match (game.gametype.as_str(), game.player1_word.as_deref()) {
+		("TootOtto", Some("TOOT") | Some("OTTO")) | (_, None) => {}
+		(_, Some(word)) => return Err(format!("player1_word {:?} does not fit a {} game", word, game.gametype)),
+	}
+	if game.difficulty.is_some() && game.player1 != COMPUTER && game.player2 != COMPUTER {
+		return Err(format!("only games against {:?} have a difficulty", COMPUTER));
+	}
+	Ok(())
+}
+
+// Check a record and put it in the shape stored games have. Account ids
+// belong to the server the games came from, so they are dropped.
+fn prepare(mut game: Game) -> Result<Game, String> {
+	if game.variant != standard_variant() {
+		return Err(format!("unknown variant {:?}, only {:?} is played", game.variant, standard_variant()));
+	}
+	if game.moves.is_empty() {
+		game.check_record()?;
+	} else {
+		game.check()?;
+		game.move_count = Some(game.moves.len() as u32);
+	}
+	let ended_at = normal_time("ended_at", game.ended_at.as_deref().ok_or("the game has no `ended_at`")?)?;
+	let started_at = game.started_at.as_deref().map(|started_at| normal_time("started_at", started_at)).transpose()?;
+	if started_at.is_some_and(|started_at| started_at > ended_at) {
+		return Err("the game ends before it starts".to_string());
+	}
+	if ended_at > Utc::now() {
+		return Err("the game ends in the future".to_string());
+	}
+	check_details(&game)?;
+	game.id = None;
+	game.player1_id = None;
+	game.player2_id = None;
+	game.ended_at = Some(ended_at.to_rfc3339_opts(SecondsFormat::Secs, true));
+	game.started_at = started_at.map(|started_at| started_at.to_rfc3339_opts(SecondsFormat::Secs, true));
+	game.duration_secs = started_at.map(|started_at| (ended_at - started_at).num_seconds());
+	Ok(game)
+}
+
+// What makes two records the same game
+fn fingerprint(game: &Game) -> (String, String, String, String, Option<String>, Vec<String>) {
+	(game.gametype.clone(), game.player1.clone(), game.player2.clone(), game.winner.clone(), game.ended_at.clone(), game.moves.clone())
+}
+
+// Whether a game with the same players, result, moves and end time is stored
+async fn is_stored(db: &dyn GameStore, game: &Game) -> Result<bool, StoreError> {
+	let ended_at = game.ended_at.as_deref().and_then(|ended_at| DateTime::parse_from_rfc3339(ended_at).ok());
+	let filter = GameFilter {
+		gametype: Some(game.gametype.clone()),
+		player: Some(game.player1.clone()),
+		player_id: None,
+		opponent: None,
+		winner: Some(game.winner.clone()),
+		from: game.ended_at.clone(),
+		to: ended_at.map(|ended_at| (ended_at + Duration::seconds(1)).to_rfc3339_opts(SecondsFormat::Secs, true)),
+		voided: None,
+		newest_first: true,
+		after: None,
+		limit: MAX_LIMIT,
+	};
+	let key = fingerprint(game);
+	Ok(db.find_games(&filter).await?.iter().any(|stored| fingerprint(stored) == key))
+}
+
+// Store the records that pass the checks and are not stored yet, then rate
+// every game again once, since imported games may be older than stored ones.
+pub async fn import(db: &dyn GameStore, records: Vec<Result<Game, String>>) -> Result<ImportReport, StoreError> {
+	let mut report = ImportReport::default();
+	let mut seen = HashSet::new();
+	let mut accepted = Vec::new();
+	for (i, record) in records.into_iter().enumerate() {
+		let game = match record.and_then(prepare) {
+			Ok(game) => game,
+			Err(error) => {
+				report.rejected.push(ImportProblem { record: i + 1, error });
+				continue;
+			}
+		};
+		if !seen.insert(fingerprint(&game)) || is_stored(db, &game).await? {
+			report.duplicates += 1;
+			continue;
+		}
+		accepted.push(game);
+	}
+	accepted.sort_by(|a, b| a.ended_at.cmp(&b.ended_at));
+	for game in accepted {
+		db.insert_game(game).await?;
+		report.imported += 1;
+	}
+	// the games count once stored, so ratings that fail are only logged
+	if report.imported > 0 {
+		if let Err(err) = Ratings::recompute(db).await {
+			error!("cannot rate imported games: {}", err);
+		}
+	}
+	Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::Query::PlayerKey;
+	use crate::Storage::fixtures::{game, stores};
+
+	#[rocket::async_test]
+	async fn rates_imported_games_in_the_order_played() {
+		for db in stores().await {
+			let db = db.as_ref();
+			let stored = db.insert_game(game("Connect4", "ann", "bob", "ann", 5)).await.unwrap();
+			Ratings::rate_game(db, &stored, &Ratings::writing().await).await.unwrap();
+
+			let records = vec![
+				Ok(game("Connect4", "bob", "ann", "bob", 3)),
+				Ok(game("Connect4", "ann", "bob", "", 1)),
+				Ok(game("Connect4", "ann", "bob", "", 1)),
+				Ok(game("Connect4", "ann", "ann", "ann", 2)),
+				Err("not a game".to_string()),
+			];
+			let report = import(db, records).await.unwrap();
+			assert_eq!((report.imported, report.duplicates, report.rejected.len()), (2, 1, 2));
+
+			let history = db.rating_history("Connect4", &PlayerKey::Guest("ann".to_string())).await.unwrap();
+			let days: Vec<&str> = history.iter().map(|entry| &entry.at[8..10]).collect();
+			assert_eq!(days, ["01", "03", "05"]);
+			assert_eq!(history.last().unwrap().games, 3);
+		}
+	}
+}
@@ -0,0 +1,322 @@
+use std::collections::HashMap;
+
+use chrono::prelude::*;
+use chrono::Duration;
+use common::Backend::{standard_variant, NewMatch, QueueRequest, QueueStatus, Room, User};
+use common::Position::GameType;
+use rand::Rng;
+use rocket::tokio::sync::Mutex;
+
+use crate::Matches::{MatchError, MatchTable};
+use crate::Query::PlayerKey;
+use crate::Storage::GameStore;
+
+// a queued player who has not asked how they stand for this long has left
+const QUEUE_TIMEOUT_SECS: i64 = 30;
+// unused room codes are forgotten after this, and their matches with them
+const ROOM_MINUTES: i64 = 10;
+// players this far apart in rating are paired at once; the gap allowed
+// grows by RATING_STEP for every STEP_SECS the longer waiting player waits
+const RATING_WINDOW: f64 = 100.0;
+const RATING_STEP: f64 = 50.0;
+const STEP_SECS: i64 = 10;
+const CODE_LENGTH: usize = 6;
+// no 0/O or 1/I, codes are read out loud
+const CODE_LETTERS: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+// unrated players are paired as if they had Glicko-2's starting rating
+const DEFAULT_RATING: f64 = 1500.0;
+
+pub enum LobbyError {
+	NotQueued,
+	NoRoom,
+	Invalid(String),
+	Forbidden(String),
+	// already paired, too late to leave
+	Matched,
+	Match(MatchError),
+}
+
+impl From<MatchError> for LobbyError {
+	fn from(err: MatchError) -> Self {
+		LobbyError::Match(err)
+	}
+}
+
+struct Ticket {
+	user: User,
+	gametype: GameType,
+	variant: String,
+	rating: f64,
+	joined_at: DateTime<Utc>,
+	seen_at: DateTime<Utc>,
+	match_id: Option<String>,
+}
+
+impl Ticket {
+	fn window(&self, now: DateTime<Utc>) -> f64 {
+		RATING_WINDOW + RATING_STEP * ((now - self.joined_at).num_seconds() / STEP_SECS) as f64
+	}
+
+	fn fits(&self, other: &Ticket) -> bool {
+		self.user.id != other.user.id && self.gametype == other.gametype && self.variant == other.variant
+	}
+}
+
+struct RoomEntry {
+	room: Room,
+	owner_id: String,
+	expires_at: DateTime<Utc>,
+}
+
+#[derive(Default)]
+struct Lobby {
+	// by user id, a player waits for one game at a time
+	queue: HashMap<String, Ticket>,
+	rooms: HashMap<String, RoomEntry>,
+}
+
+impl Lobby {
+	async fn prune(&mut self, now: DateTime<Utc>, matches: &MatchTable) -> Result<(), LobbyError> {
+		let cutoff = now - Duration::seconds(QUEUE_TIMEOUT_SECS);
+		self.queue.retain(|_, ticket| ticket.seen_at > cutoff);
+		let expired: Vec<String> = self.rooms.iter().filter(|(_, entry)| entry.expires_at <= now).map(|(code, _)| code.clone()).collect();
+		for code in expired {
+			if let Some(entry) = self.rooms.remove(&code) {
+				matches.cancel(&entry.room.match_id).await?;
+			}
+		}
+		Ok(())
+	}
+
+	// Pair waiting players, those waiting longest first, each with the
+	// closest rated player within the wider of their two windows
+	async fn pair(&mut self, now: DateTime<Utc>, matches: &MatchTable, db: &dyn GameStore) -> Result<(), LobbyError> {
+		let mut waiting: Vec<&Ticket> = self.queue.values().filter(|ticket| ticket.match_id.is_none()).collect();
+		waiting.sort_by_key(|ticket| ticket.joined_at);
+		let mut pairs: Vec<(User, User)> = Vec::new();
+		let mut taken: Vec<&str> = Vec::new();
+		for (i, first) in waiting.iter().enumerate() {
+			if taken.contains(&first.user.id.as_str()) {
+				continue;
+			}
+			let second = waiting[i + 1..]
+				.iter()
+				.filter(|other| !taken.contains(&other.user.id.as_str()) && first.fits(other))
+				.filter(|other| (first.rating - other.rating).abs() <= first.window(now).max(other.window(now)))
+				.min_by(|a, b| (first.rating - a.rating).abs().total_cmp(&(first.rating - b.rating).abs()));
+			if let Some(second) = second {
+				taken.push(&first.user.id);
+				taken.push(&second.user.id);
+				pairs.push((first.user.clone(), second.user.clone()));
+			}
+		}
+		for (first, second) in pairs {
+			let gametype = self.queue[&first.id].gametype;
+			// whoever waited longest opens the match and moves first
+			let request = NewMatch {
+				gametype,
+				player1: first.name.clone(),
+				player2: String::new(),
+				first_mover: None,
+				player1_word: None,
+				difficulty: None,
+				online: true,
+			};
+			let state = matches.create(request, &first, db).await?;
+			matches.join(&state.id, &second).await?;
+			for user in [&first, &second] {
+				if let Some(ticket) = self.queue.get_mut(&user.id) {
+					ticket.match_id = Some(state.id.clone());
+				}
+			}
+		}
+		Ok(())
+	}
+
+	fn status(&self, ticket: &Ticket, now: DateTime<Utc>) -> QueueStatus {
+		QueueStatus {
+			gametype: ticket.gametype,
+			variant: ticket.variant.clone(),
+			rating: ticket.rating,
+			waiting_secs: (now - ticket.joined_at).num_seconds().max(0) as u64,
+			waiting: self.queue.values().filter(|other| other.match_id.is_none() && ticket.fits(other)).count() + 1,
+			match_id: ticket.match_id.clone(),
+		}
+	}
+}
+
+// Matchmaking queue and private rooms. Both only hand out online matches
+// from the `MatchTable`; playing them works as for any other online match.
+#[derive(Default)]
+pub struct LobbyTable {
+	lobby: Mutex<Lobby>,
+}
+
+impl LobbyTable {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	// Wait for an opponent. Queueing again replaces the old place in the
+	// queue, unless the player has been paired already.
+	pub async fn enqueue(&self, request: QueueRequest, user: &User, matches: &MatchTable, db: &dyn GameStore) -> Result<QueueStatus, LobbyError> {
+		if request.variant != standard_variant() {
+			return Err(LobbyError::Invalid(format!("unknown variant {:?}, only {:?} is played", request.variant, standard_variant())));
+		}
+		let gametype = match request.gametype {
+			GameType::Connect4 => "Connect4",
+			GameType::TootOtto => "TootOtto",
+		};
+		let rating = db
+			.latest_rating(gametype, &PlayerKey::Account(user.id.clone()))
+			.await
+			.map_err(|err| LobbyError::Match(MatchError::Storage(err.to_string())))?
+			.map_or(DEFAULT_RATING, |entry| entry.rating);
+		let now = Utc::now();
+		let mut lobby = self.lobby.lock().await;
+		lobby.prune(now, matches).await?;
+		if lobby.queue.get(&user.id).is_none_or(|ticket| ticket.match_id.is_none()) {
+			let ticket = Ticket {
+				user: user.clone(),
+				gametype: request.gametype,
+				variant: request.variant,
+				rating,
+				joined_at: now,
+				seen_at: now,
+				match_id: None,
+			};
+			lobby.queue.insert(user.id.clone(), ticket);
+		}
+		lobby.pair(now, matches, db).await?;
+		Ok(lobby.status(&lobby.queue[&user.id], now))
+	}
+
+	// How the player stands; asking keeps them in the queue
+	pub async fn poll(&self, user: &User, matches: &MatchTable, db: &dyn GameStore) -> Result<QueueStatus, LobbyError> {
+		let now = Utc::now();
+		let mut lobby = self.lobby.lock().await;
+		lobby.prune(now, matches).await?;
+		lobby.queue.get_mut(&user.id).ok_or(LobbyError::NotQueued)?.seen_at = now;
+		lobby.pair(now, matches, db).await?;
+		Ok(lobby.status(&lobby.queue[&user.id], now))
+	}
+
+	pub async fn leave(&self, user: &User) -> Result<(), LobbyError> {
+		let mut lobby = self.lobby.lock().await;
+		match lobby.queue.get(&user.id) {
+			None => Err(LobbyError::NotQueued),
+			Some(ticket) if ticket.match_id.is_some() => Err(LobbyError::Matched),
+			Some(_) => {
+				lobby.queue.remove(&user.id);
+				Ok(())
+			}
+		}
+	}
+
+	// Open an online match and a code for the friend who should join it
+	pub async fn open_room(&self, mut request: NewMatch, owner: &User, matches: &MatchTable, db: &dyn GameStore) -> Result<Room, LobbyError> {
+		request.online = true;
+		let state = matches.create(request, owner, db).await?;
+		let now = Utc::now();
+		let mut lobby = self.lobby.lock().await;
+		lobby.prune(now, matches).await?;
+		let code = loop {
+			let rng = &mut rand::thread_rng();
+			let code: String = (0..CODE_LENGTH).map(|_| CODE_LETTERS[rng.gen_range(0..CODE_LETTERS.len())] as char).collect();
+			if !lobby.rooms.contains_key(&code) {
+				break code;
+			}
+		};
+		let expires_at = now + Duration::minutes(ROOM_MINUTES);
+		let room = Room {
+			code: code.clone(),
+			match_id: state.id,
+			gametype: state.gametype,
+			expires_at: expires_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+		};
+		lobby.rooms.insert(code, RoomEntry { room: room.clone(), owner_id: owner.id.clone(), expires_at });
+		Ok(room)
+	}
+
+	// Take the free seat of a room's match; each code lets one player in
+	pub async fn join_room(&self, code: &str, user: &User, matches: &MatchTable) -> Result<Room, LobbyError> {
+		let code = code.trim().to_uppercase();
+		let mut lobby = self.lobby.lock().await;
+		lobby.prune(Utc::now(), matches).await?;
+		let room = lobby.rooms.get(&code).ok_or(LobbyError::NoRoom)?.room.clone();
+		matches.join(&room.match_id, user).await?;
+		lobby.rooms.remove(&code);
+		Ok(room)
+	}
+
+	// Forget rooms that timed out, and cancel their matches
+	pub async fn expire(&self, matches: &MatchTable) -> Result<(), LobbyError> {
+		self.lobby.lock().await.prune(Utc::now(), matches).await
+	}
+
+	// Close a room nobody has joined, and cancel the match it offered
+	pub async fn close_room(&self, code: &str, user: &User, matches: &MatchTable) -> Result<(), LobbyError> {
+		let code = code.trim().to_uppercase();
+		let mut lobby = self.lobby.lock().await;
+		match lobby.rooms.get(&code) {
+			None => Err(LobbyError::NoRoom),
+			Some(entry) if entry.owner_id != user.id => Err(LobbyError::Forbidden("only the player who opened the room may close it".to_string())),
+			Some(entry) => {
+				matches.cancel(&entry.room.match_id).await?;
+				lobby.rooms.remove(&code);
+				Ok(())
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use common::Backend::Role;
+
+	use super::*;
+	use crate::Storage::fixtures::stores;
+
+	fn user(id: &str, name: &str) -> User {
+		User { id: id.to_string(), name: name.to_string(), created_at: String::new(), role: Role::Player }
+	}
+
+	fn room_request() -> NewMatch {
+		NewMatch {
+			gametype: GameType::Connect4,
+			player1: String::new(),
+			player2: String::new(),
+			first_mover: None,
+			player1_word: None,
+			difficulty: None,
+			online: true,
+		}
+	}
+
+	// a room's match cannot be joined by its id once the room is gone
+	#[rocket::async_test]
+	async fn cancels_the_matches_of_closed_and_expired_rooms() {
+		for db in stores().await {
+			let db = db.as_ref();
+			let (ann, bob) = (user("1", "ann"), user("2", "bob"));
+			let (lobby, matches) = (LobbyTable::new(), MatchTable::new());
+
+			let Ok(closed) = lobby.open_room(room_request(), &ann, &matches, db).await else { panic!("no room") };
+			assert!(matches!(lobby.close_room(&closed.code, &bob, &matches).await, Err(LobbyError::Forbidden(_))));
+			assert!(lobby.close_room(&closed.code, &ann, &matches).await.is_ok());
+			assert!(matches!(matches.join(&closed.match_id, &bob).await, Err(MatchError::NotFound)));
+
+			let Ok(expired) = lobby.open_room(room_request(), &ann, &matches, db).await else { panic!("no room") };
+			let later = Utc::now() + Duration::minutes(ROOM_MINUTES + 1);
+			assert!(lobby.lobby.lock().await.prune(later, &matches).await.is_ok());
+			assert!(matches!(lobby.join_room(&expired.code, &bob, &matches).await, Err(LobbyError::NoRoom)));
+			assert!(matches!(matches.join(&expired.match_id, &bob).await, Err(MatchError::NotFound)));
+
+			// a room someone joined leaves its match alone
+			let Ok(joined) = lobby.open_room(room_request(), &ann, &matches, db).await else { panic!("no room") };
+			assert!(lobby.join_room(&joined.code, &bob, &matches).await.is_ok());
+			assert!(matches!(matches.cancel(&joined.match_id).await, Ok(false)));
+			assert!(matches.get(&joined.match_id).await.is_ok());
+		}
+	}
+}
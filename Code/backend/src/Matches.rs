@@ -0,0 +1,473 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use chrono::prelude::*;
+use chrono::Duration;
+use common::Backend::{check_player_name, standard_variant, Game, LiveMatch, MatchState, MatchStatus, NewMatch, User};
+use common::Position::{GameType, Move, Position};
+use common::Search;
+use common::Skill::{self, SkillLevel, LEVELS};
+use rand::Rng;
+use rocket::tokio::sync::{broadcast, Mutex as AsyncMutex};
+use rocket::tokio::task;
+
+use crate::Accounts::name_key;
+use crate::Query::COMPUTER;
+use crate::Storage::{store_game, GameStore};
+
+// matches nobody has moved in for this long are forgotten
+const IDLE_MINUTES: i64 = 60;
+// states waiting for slow listeners; each is complete, so losing some is harmless
+const EVENT_BUFFER: usize = 16;
+
+pub enum MatchError {
+	NotFound,
+	Over,
+	Invalid(String),
+	// someone moved who may not, or not yet
+	Forbidden(String),
+	// an online match nobody has joined yet
+	Waiting,
+	// an online match someone else has already joined
+	Full,
+	// the computer's search failed
+	Engine(String),
+	Storage(String),
+}
+
+// A game in progress. Every move is replayed on the server's own board, so
+// the result that ends up in the games collection is the one the rules give.
+pub struct Match {
+	id: String,
+	gametype: GameType,
+	player1: String,
+	player2: String,
+	// the logged in user who opened the match always sits in player1's seat
+	player1_id: String,
+	// online matches only, the user who joined
+	player2_id: Option<String>,
+	online: bool,
+	first_mover: String,
+	// TOOT-OTTO: true if player1 spells TOOT
+	player1_toot: bool,
+	difficulty: Option<usize>,
+	position: Position,
+	moves: Vec<Move>,
+	started_at: DateTime<Utc>,
+	updated_at: DateTime<Utc>,
+	game_id: Option<String>,
+	// every accepted change goes out to whoever follows the match
+	events: broadcast::Sender<MatchState>,
+}
+
+impl Match {
+	pub fn new(id: String, request: NewMatch, owner: &User) -> Result<Match, String> {
+		let player1 = owner.name.clone();
+		// online, the second seat is filled in when someone joins
+		let player2 = if request.online { String::new() } else { request.player2.trim().to_string() };
+		if player1.is_empty() || (player2.is_empty() && !request.online) {
+			return Err("both players need a name".to_string());
+		}
+		if !player2.is_empty() {
+			check_player_name(&player2)?;
+		}
+		if player1 == player2 {
+			return Err("the players need different names".to_string());
+		}
+		// the server plays the computer's seat, at this level
+		if player2 == COMPUTER && !request.difficulty.is_some_and(|level| (1..=LEVELS.len()).contains(&level)) {
+			return Err(format!("a match against {} needs a difficulty of 1 to {}", COMPUTER, LEVELS.len()));
+		}
+		let first_mover = match request.first_mover {
+			Some(name) if name == player1 || (!player2.is_empty() && name == player2) => name,
+			Some(name) => return Err(format!("first mover {:?} is not playing in this match", name)),
+			None => player1.clone(),
+		};
+		let player1_toot = match request.player1_word.as_deref() {
+			None => true,
+			Some(word) if request.gametype == GameType::TootOtto => match word.to_uppercase().as_str() {
+				"TOOT" => true,
+				"OTTO" => false,
+				_ => return Err(format!("player1_word must be TOOT or OTTO, not {:?}", word)),
+			},
+			Some(_) => return Err("player1_word only applies to TOOT-OTTO".to_string()),
+		};
+		let now = Utc::now();
+		Ok(Match {
+			id,
+			gametype: request.gametype,
+			player1,
+			player2,
+			player1_id: owner.id.clone(),
+			player2_id: None,
+			online: request.online,
+			first_mover,
+			player1_toot,
+			difficulty: request.difficulty,
+			position: Position::new(request.gametype),
+			moves: Vec::new(),
+			started_at: now,
+			updated_at: now,
+			game_id: None,
+			events: broadcast::channel(EVENT_BUFFER).0,
+		})
+	}
+
+	fn is_waiting(&self) -> bool {
+		self.online && self.player2_id.is_none()
+	}
+
+	// Whether the user may make the next move: the owner plays both sides
+	// of a match on one device, apart from the computer's, online each
+	// player only their own
+	fn may_move(&self, user: &User) -> Result<(), MatchError> {
+		if !self.online {
+			if self.player1_id != user.id {
+				return Err(MatchError::Forbidden("only the player who opened the match may move".to_string()));
+			}
+			if self.computer_to_move().is_some() {
+				return Err(MatchError::Forbidden(format!("{} plays its own moves", COMPUTER)));
+			}
+			return Ok(());
+		}
+		if self.is_waiting() {
+			return Err(MatchError::Waiting);
+		}
+		let seat_id = match self.to_move() {
+			Some(name) if name == self.player1 => Some(&self.player1_id),
+			Some(_) => self.player2_id.as_ref(),
+			// let `play` say the game is over
+			None => return Ok(()),
+		};
+		match seat_id == Some(&user.id) {
+			true => Ok(()),
+			false => Err(MatchError::Forbidden("it is not your turn".to_string())),
+		}
+	}
+
+	// The computer's level when it is the computer's turn
+	fn computer_to_move(&self) -> Option<SkillLevel> {
+		match self.to_move() {
+			Some(COMPUTER) if !self.online => self.difficulty.map(Skill::level),
+			_ => None,
+		}
+	}
+
+	// Play the computer's move if it is its turn, searched off the async
+	// workers since the deeper levels take a while
+	async fn play_computer(&mut self) -> Result<(), MatchError> {
+		let skill = match self.computer_to_move() {
+			Some(skill) => skill,
+			None => return Ok(()),
+		};
+		let position = self.position.clone();
+		let mv = task::spawn_blocking(move || Skill::choose_move(&position, &skill, Search::available_threads(), &mut rand::thread_rng()))
+			.await
+			.map_err(|err| MatchError::Engine(err.to_string()))?
+			.ok_or_else(|| MatchError::Engine("no legal moves".to_string()))?;
+		self.play(&mv.to_string())
+	}
+
+	fn second_mover(&self) -> &str {
+		if self.first_mover == self.player1 { &self.player2 } else { &self.player1 }
+	}
+
+	// Name of the player who makes the next move
+	fn to_move(&self) -> Option<&str> {
+		if self.position.is_over() || self.is_waiting() {
+			return None;
+		}
+		if self.moves.len().is_multiple_of(2) {
+			Some(&self.first_mover)
+		} else {
+			Some(self.second_mover())
+		}
+	}
+
+	// Play a move written as in `Move::parse`
+	pub fn play(&mut self, text: &str) -> Result<(), MatchError> {
+		if self.position.is_over() {
+			return Err(MatchError::Over);
+		}
+		let mv = Move::parse(self.gametype, text).map_err(MatchError::Invalid)?;
+		self.position.play(mv).map_err(MatchError::Invalid)?;
+		self.moves.push(mv);
+		self.updated_at = Utc::now();
+		Ok(())
+	}
+
+	// The record that goes into the games collection once the game is over
+	pub fn to_game(&self) -> Game {
+		let mut game = Game {
+			id: None,
+			gametype: match self.gametype {
+				GameType::Connect4 => "Connect4".to_string(),
+				GameType::TootOtto => "TootOtto".to_string(),
+			},
+			player1: self.player1.clone(),
+			player2: self.player2.clone(),
+			winner: String::new(),
+			started_at: Some(self.started_at.to_rfc3339_opts(SecondsFormat::Secs, true)),
+			ended_at: None,
+			duration_secs: None,
+			move_count: Some(self.moves.len() as u32),
+			difficulty: self.difficulty,
+			variant: standard_variant(),
+			first_mover: Some(self.first_mover.clone()),
+			player1_word: self.player1_word(),
+			moves: self.moves.iter().map(|mv| mv.to_string()).collect(),
+			player1_id: Some(self.player1_id.clone()),
+			// none for the computer or a guest on the same device
+			player2_id: self.player2_id.clone(),
+			voided: false,
+		};
+		if let Some(side) = self.position.winner() {
+			game.winner = game.player_on(side).to_string();
+		}
+		game
+	}
+
+	fn player1_word(&self) -> Option<String> {
+		match self.gametype {
+			GameType::TootOtto => Some(if self.player1_toot { "TOOT" } else { "OTTO" }.to_string()),
+			GameType::Connect4 => None,
+		}
+	}
+
+	pub fn state(&self) -> MatchState {
+		let winner = self.to_game().winner;
+		MatchState {
+			id: self.id.clone(),
+			gametype: self.gametype,
+			player1: self.player1.clone(),
+			player2: self.player2.clone(),
+			first_mover: self.first_mover.clone(),
+			player1_word: self.player1_word(),
+			position: self.position.to_string(),
+			moves: self.moves.iter().map(|mv| mv.to_string()).collect(),
+			to_move: self.to_move().map(|name| name.to_string()),
+			status: if self.is_waiting() {
+				MatchStatus::Waiting
+			} else if self.position.winner().is_some() {
+				MatchStatus::Won
+			} else if self.position.is_draw() {
+				MatchStatus::Draw
+			} else {
+				MatchStatus::Playing
+			},
+			winner: if winner.is_empty() { None } else { Some(winner) },
+			game_id: self.game_id.clone(),
+			online: self.online,
+		}
+	}
+
+	// Tell whoever follows the match, and the caller, how it stands now
+	fn publish(&self) -> MatchState {
+		let state = self.state();
+		// an error only means nobody is listening
+		let _ = self.events.send(state.clone());
+		state
+	}
+}
+
+// one match, locked while a request works on it
+type Entry = Arc<AsyncMutex<Match>>;
+
+// Matches in progress, kept in memory; only finished games are stored.
+// Each match has its own lock, so storing one finished game does not hold
+// up moves in any other.
+#[derive(Default)]
+pub struct MatchTable {
+	matches: Mutex<HashMap<String, Entry>>,
+}
+
+impl MatchTable {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	fn table(&self) -> Result<MutexGuard<'_, HashMap<String, Entry>>, MatchError> {
+		self.matches.lock().map_err(|err| MatchError::Storage(err.to_string()))
+	}
+
+	fn entry(&self, id: &str) -> Result<Entry, MatchError> {
+		self.table()?.get(id).cloned().ok_or(MatchError::NotFound)
+	}
+
+	// A guest in the second seat may not use a registered player's name
+	pub async fn create(&self, request: NewMatch, owner: &User, db: &dyn GameStore) -> Result<MatchState, MatchError> {
+		let guest = request.player2.trim();
+		if !request.online && guest != COMPUTER && name_key(guest) != name_key(&owner.name) {
+			let registered = db.find_account(&name_key(guest)).await.map_err(|err| MatchError::Storage(err.to_string()))?;
+			if registered.is_some() {
+				return Err(MatchError::Invalid(format!("{:?} is a registered player's name", guest)));
+			}
+		}
+		// ids are hard to guess, since knowing one is enough to follow the match
+		let id = format!("{:032x}", rand::thread_rng().gen::<u128>());
+		let mut new_match = Match::new(id.clone(), request, owner).map_err(MatchError::Invalid)?;
+		new_match.play_computer().await?;
+		let state = new_match.state();
+		let mut matches = self.table()?;
+		let cutoff = Utc::now() - Duration::minutes(IDLE_MINUTES);
+		// a match someone holds right now is in use
+		matches.retain(|_, old| old.try_lock().map_or(true, |old| old.updated_at > cutoff));
+		matches.insert(id, Arc::new(AsyncMutex::new(new_match)));
+		Ok(state)
+	}
+
+	pub async fn get(&self, id: &str) -> Result<MatchState, MatchError> {
+		Ok(self.entry(id)?.lock().await.state())
+	}
+
+	// Online matches under way, the most recently moved in first
+	pub async fn live(&self) -> Result<Vec<LiveMatch>, MatchError> {
+		let entries: Vec<Entry> = self.table()?.values().cloned().collect();
+		let mut live = Vec::new();
+		for entry in entries {
+			let found = entry.lock().await;
+			if found.online && found.to_move().is_some() {
+				let started_at = found.started_at.to_rfc3339_opts(SecondsFormat::Secs, true);
+				live.push((found.updated_at, LiveMatch { state: found.state(), watching: found.events.receiver_count(), started_at }));
+			}
+		}
+		live.sort_by_key(|(updated_at, _)| std::cmp::Reverse(*updated_at));
+		Ok(live.into_iter().map(|(_, found)| found).collect())
+	}
+
+	// The state now and every state after it
+	pub async fn subscribe(&self, id: &str) -> Result<(MatchState, broadcast::Receiver<MatchState>), MatchError> {
+		let entry = self.entry(id)?;
+		let found = entry.lock().await;
+		Ok((found.state(), found.events.subscribe()))
+	}
+
+	// Forget an online match nobody has joined yet, when the room that
+	// offered it closes; false if someone has joined or it is gone
+	pub async fn cancel(&self, id: &str) -> Result<bool, MatchError> {
+		let entry = match self.entry(id) {
+			Ok(entry) => entry,
+			Err(MatchError::NotFound) => return Ok(false),
+			Err(err) => return Err(err),
+		};
+		let found = entry.lock().await;
+		if !found.is_waiting() {
+			return Ok(false);
+		}
+		// removed while locked, so a join waiting for the lock finds it gone
+		self.table()?.remove(id);
+		Ok(true)
+	}
+
+	// Take the second seat of an online match. Joining again as the same
+	// user just returns the state, for clients that lost theirs.
+	pub async fn join(&self, id: &str, user: &User) -> Result<MatchState, MatchError> {
+		let entry = self.entry(id)?;
+		let mut current = entry.lock().await;
+		if !self.table()?.contains_key(id) {
+			return Err(MatchError::NotFound);
+		}
+		if !current.online {
+			return Err(MatchError::Invalid("only online matches can be joined".to_string()));
+		}
+		if current.player1_id == user.id {
+			return Err(MatchError::Invalid("you opened this match, send its link to your opponent".to_string()));
+		}
+		match &current.player2_id {
+			Some(id) if *id == user.id => return Ok(current.state()),
+			Some(_) => return Err(MatchError::Full),
+			None => {}
+		}
+		current.player2 = user.name.clone();
+		current.player2_id = Some(user.id.clone());
+		// the clock starts once both players are there
+		current.started_at = Utc::now();
+		current.updated_at = current.started_at;
+		Ok(current.publish())
+	}
+
+	// Play a move for a player of the match, then the computer's reply in a
+	// match against it, and if that ends the game, store the result. The
+	// moves only count once the game has been stored, so a failed write can
+	// be retried.
+	pub async fn play(&self, id: &str, text: &str, user: &User, db: &dyn GameStore) -> Result<MatchState, MatchError> {
+		let entry = self.entry(id)?;
+		let mut current = entry.lock().await;
+		current.may_move(user)?;
+		let previous = (current.position.clone(), current.moves.len(), current.updated_at);
+		current.play(text)?;
+		let mut result = current.play_computer().await;
+		if result.is_ok() && current.position.is_over() {
+			result = match store_game(db, current.to_game()).await {
+				Ok(game) => {
+					current.game_id = game.id;
+					Ok(())
+				}
+				Err(err) => Err(MatchError::Storage(err.to_string())),
+			};
+		}
+		if let Err(err) = result {
+			current.moves.truncate(previous.1);
+			(current.position, current.updated_at) = (previous.0, previous.2);
+			return Err(err);
+		}
+		Ok(current.publish())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use common::Backend::Role;
+
+	use super::*;
+	use crate::Storage::fixtures::stores;
+
+	fn owner() -> User {
+		User { id: "1".to_string(), name: "ann".to_string(), created_at: String::new(), role: Role::Player }
+	}
+
+	fn against_computer(gametype: GameType, first_mover: Option<&str>) -> NewMatch {
+		NewMatch {
+			gametype,
+			player1: "ann".to_string(),
+			player2: COMPUTER.to_string(),
+			first_mover: first_mover.map(str::to_string),
+			player1_word: None,
+			difficulty: Some(1),
+			online: false,
+		}
+	}
+
+	#[test]
+	fn leaves_the_computers_moves_to_the_server() {
+		let found = Match::new("1".to_string(), against_computer(GameType::Connect4, Some(COMPUTER)), &owner()).unwrap();
+		assert!(matches!(found.may_move(&owner()), Err(MatchError::Forbidden(_))));
+		let no_level = NewMatch { difficulty: None, ..against_computer(GameType::Connect4, None) };
+		assert!(Match::new("2".to_string(), no_level, &owner()).is_err());
+	}
+
+	// the owner only ever sends their own moves, and the stored game is one
+	// the computer really played
+	#[rocket::async_test]
+	async fn plays_the_computers_seat() {
+		for db in stores().await {
+			let db = db.as_ref();
+			let table = MatchTable::new();
+			for gametype in [GameType::Connect4, GameType::TootOtto] {
+				let Ok(mut state) = table.create(against_computer(gametype, Some(COMPUTER)), &owner(), db).await else { panic!("no match") };
+				assert_eq!(state.moves.len(), 1);
+				while state.to_move.is_some() {
+					assert_eq!(state.to_move.as_deref(), Some("ann"));
+					let position = Position::parse(gametype, &state.position).unwrap();
+					let mv = Skill::ordered_moves(&position)[0];
+					let Ok(next) = table.play(&state.id, &mv.to_string(), &owner(), db).await else { panic!("{} was turned away", mv) };
+					state = next;
+					assert!(state.moves.len() % 2 == 1 || state.to_move.is_none());
+				}
+				let game = db.get_game(state.game_id.as_deref().unwrap()).await.unwrap().unwrap();
+				assert!(game.check().is_ok());
+				assert_eq!((game.player2.as_str(), game.difficulty, game.moves), (COMPUTER, Some(1), state.moves));
+			}
+		}
+	}
+}
@@ -0,0 +1,187 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{Data, Request, Response};
+
+// upper bounds of the latency histogram buckets, in seconds
+const BUCKETS: [f64; 12] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+#[derive(Clone, Default)]
+struct Histogram {
+	// observations at or under each bound
+	counts: [u64; BUCKETS.len()],
+	sum: f64,
+	count: u64,
+}
+
+impl Histogram {
+	fn observe(&mut self, elapsed: Duration) {
+		let secs = elapsed.as_secs_f64();
+		for (bound, count) in BUCKETS.iter().zip(self.counts.iter_mut()) {
+			if secs <= *bound {
+				*count += 1;
+			}
+		}
+		self.sum += secs;
+		self.count += 1;
+	}
+
+	fn write(&self, out: &mut String, name: &str, labels: &str) {
+		let separator = if labels.is_empty() { "" } else { "," };
+		for (bound, count) in BUCKETS.iter().zip(self.counts) {
+			let _ = writeln!(out, "{}_bucket{{{}{}le=\"{}\"}} {}", name, labels, separator, bound, count);
+		}
+		let _ = writeln!(out, "{}_bucket{{{}{}le=\"+Inf\"}} {}", name, labels, separator, self.count);
+		let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, self.sum);
+		let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, self.count);
+	}
+}
+
+#[derive(Default)]
+struct Recorded {
+	// by method, route and status
+	requests: BTreeMap<(String, String, u16), u64>,
+	// by method and route
+	latencies: BTreeMap<(String, String), Histogram>,
+	// by game type
+	ai_moves: BTreeMap<String, Histogram>,
+}
+
+// What the server has done since it started, for `GET /metrics`
+pub struct Metrics {
+	started_at: Instant,
+	recorded: Mutex<Recorded>,
+	store: StoreGauges,
+}
+
+// The store's side of the metrics, as the database monitor last found it
+#[derive(Clone, Default)]
+pub struct StoreMetrics {
+	pub up: bool,
+	// games that count, by game type
+	pub games: Vec<(&'static str, usize)>,
+}
+
+// Where the database monitor leaves `StoreMetrics` for `GET /metrics`, so
+// scrapes never wait on the database
+#[derive(Clone, Default)]
+pub struct StoreGauges(Arc<Mutex<StoreMetrics>>);
+
+impl StoreGauges {
+	fn get(&self) -> MutexGuard<'_, StoreMetrics> {
+		self.0.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+
+	pub fn set(&self, store: StoreMetrics) {
+		*self.get() = store;
+	}
+}
+
+impl Metrics {
+	pub fn new() -> Self {
+		Metrics { started_at: Instant::now(), recorded: Mutex::default(), store: StoreGauges::default() }
+	}
+
+	pub fn store_gauges(&self) -> StoreGauges {
+		self.store.clone()
+	}
+
+	pub fn uptime(&self) -> Duration {
+		self.started_at.elapsed()
+	}
+
+	fn recorded(&self) -> MutexGuard<'_, Recorded> {
+		self.recorded.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+
+	pub fn observe_ai_move(&self, gametype: &str, elapsed: Duration) {
+		self.recorded().ai_moves.entry(gametype.to_string()).or_default().observe(elapsed);
+	}
+
+	fn observe_request(&self, method: &str, route: &str, status: u16, elapsed: Duration) {
+		let mut recorded = self.recorded();
+		*recorded.requests.entry((method.to_string(), route.to_string(), status)).or_default() += 1;
+		recorded.latencies.entry((method.to_string(), route.to_string())).or_default().observe(elapsed);
+	}
+
+	// Everything in the Prometheus text format
+	pub fn render(&self, live_matches: Option<usize>) -> String {
+		let mut out = String::new();
+		let recorded = self.recorded();
+		let store = self.store.get().clone();
+		let _ = writeln!(out, "# HELP uptime_seconds Seconds since the server started.");
+		let _ = writeln!(out, "# TYPE uptime_seconds gauge");
+		let _ = writeln!(out, "uptime_seconds {}", self.uptime().as_secs());
+
+		let _ = writeln!(out, "# HELP http_requests_total Requests answered, by method, route and status.");
+		let _ = writeln!(out, "# TYPE http_requests_total counter");
+		for ((method, route, status), count) in &recorded.requests {
+			let _ = writeln!(out, "http_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}", method, escape(route), status, count);
+		}
+		let _ = writeln!(out, "# HELP http_request_duration_seconds Time to answer requests, by method and route.");
+		let _ = writeln!(out, "# TYPE http_request_duration_seconds histogram");
+		for ((method, route), histogram) in &recorded.latencies {
+			histogram.write(&mut out, "http_request_duration_seconds", &format!("method=\"{}\",route=\"{}\"", method, escape(route)));
+		}
+
+		let _ = writeln!(out, "# HELP ai_move_duration_seconds Time the engine took for POST /ai/move, by game type.");
+		let _ = writeln!(out, "# TYPE ai_move_duration_seconds histogram");
+		for (gametype, histogram) in &recorded.ai_moves {
+			histogram.write(&mut out, "ai_move_duration_seconds", &format!("gametype=\"{}\"", escape(gametype)));
+		}
+
+		if let Some(live) = live_matches {
+			let _ = writeln!(out, "# HELP live_matches Online matches being played.");
+			let _ = writeln!(out, "# TYPE live_matches gauge");
+			let _ = writeln!(out, "live_matches {}", live);
+		}
+
+		let _ = writeln!(out, "# HELP storage_up Whether the database answered the last check, 1 or 0.");
+		let _ = writeln!(out, "# TYPE storage_up gauge");
+		let _ = writeln!(out, "storage_up {}", store.up as u8);
+		let _ = writeln!(out, "# HELP games_recorded Stored games that count, by game type.");
+		let _ = writeln!(out, "# TYPE games_recorded gauge");
+		for (gametype, games) in &store.games {
+			let _ = writeln!(out, "games_recorded{{gametype=\"{}\"}} {}", gametype, games);
+		}
+		out
+	}
+}
+
+fn escape(label: &str) -> String {
+	label.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+// when the request came in, cached on the request
+struct Started(Instant);
+
+// Counts and times every request by the route that answered it; requests
+// no route answered are counted under "unmatched"
+pub struct RequestMetrics;
+
+#[rocket::async_trait]
+impl Fairing for RequestMetrics {
+	fn info(&self) -> Info {
+		Info {
+			name: "Request metrics",
+			kind: Kind::Request | Kind::Response,
+		}
+	}
+
+	async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+		request.local_cache(|| Started(Instant::now()));
+	}
+
+	async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+		let metrics = match request.rocket().state::<Metrics>() {
+			Some(metrics) => metrics,
+			None => return,
+		};
+		let Started(started) = request.local_cache(|| Started(Instant::now()));
+		let route = request.route().map(|route| route.uri.origin.path().as_str()).unwrap_or("unmatched");
+		metrics.observe_request(request.method().as_str(), route, response.status().code, started.elapsed());
+	}
+}
@@ -0,0 +1,440 @@
+
+use std::time::Duration;
+
+use common::Backend::{Game, RatingEntry};
+use mongodb::bson::{self, doc, oid::ObjectId, Bson, Document};
+use serde::de::DeserializeOwned;
+use mongodb::options::{ClientOptions, FindOneOptions, FindOptions};
+use mongodb::error::{ErrorKind, WriteFailure};
+use mongodb::options::IndexOptions;
+use mongodb::IndexModel;
+use mongodb::{Client, Cursor, Database};
+
+use crate::Accounts::{Account, NameTaken, SessionRecord};
+use crate::Config::AppConfig;
+use crate::Query::{GameFilter, InvalidCursor, Opponent, PlayerKey, COMPUTER};
+use crate::Stats::Tally;
+use crate::Storage::{legacy_date, GameStore, StoreError};
+
+#[derive(Clone, Debug)]
+pub struct DB {
+	database: Database,
+	games: String,
+	ratings: String,
+	accounts: String,
+	sessions: String,
+}
+
+impl DB {
+	// The client keeps a pool of connections and replaces broken ones by
+	// itself; the timeouts make a missing server fail requests quickly
+	// rather than hold them up.
+	pub async fn new(config: &AppConfig) -> Result<Self, StoreError> {
+		let mut client_options = ClientOptions::parse(&config.mongo_uri).await?;
+		let timeout = Duration::from_secs(config.mongo_timeout_secs);
+		client_options.server_selection_timeout = Some(timeout);
+		client_options.connect_timeout = Some(timeout);
+		client_options.heartbeat_freq = Some(Duration::from_secs(config.health_check_secs));
+		let client = Client::with_options(client_options)?;
+		let db = client.database(&config.mongo_database);
+		Ok(Self {
+			database: db,
+			games: config.games_collection.clone(),
+			ratings: config.ratings_collection.clone(),
+			accounts: config.accounts_collection.clone(),
+			sessions: config.sessions_collection.clone(),
+		})
+	}
+
+	fn doc_to_account(&self, doc: Document) -> Result<Account, StoreError> {
+		let id = doc.get_object_id("_id")?.to_hex();
+		let mut account: Account = bson::from_document(doc)?;
+		account.id = Some(id);
+		Ok(account)
+	}
+
+	fn doc_to_game(&self, doc: &Document) -> Result<Game, StoreError> {
+		let mut result: Game = bson::from_document(doc.clone())?;
+		result.id = Some(doc.get_object_id("_id")?.to_hex());
+		Ok(result)
+	}
+}
+
+#[rocket::async_trait]
+impl GameStore for DB {
+	async fn ping(&self) -> Result<(), StoreError> {
+		self.database.run_command(doc! { "ping": 1 }, None).await?;
+		Ok(())
+	}
+
+	async fn insert_game(&self, mut game: Game) -> Result<Game, StoreError> {
+		let coll = self.database.collection::<Game>(&self.games);
+		let result = coll.insert_one(&game, None).await?;
+		game.id = result.inserted_id.as_object_id().map(|id| id.to_hex());
+		Ok(game)
+	}
+
+	async fn find_games(&self, filter: &GameFilter) -> Result<Vec<Game>, StoreError> {
+		let mut conditions = conditions(filter);
+		if let Some((ended_at, id)) = &filter.after {
+			conditions.push(after_cursor(ended_at, id, filter.newest_first)?);
+		}
+		let order = if filter.newest_first { -1 } else { 1 };
+		let options = FindOptions::builder()
+			.sort(doc! { "ended_at": order, "_id": order })
+			.limit((filter.limit + 1) as i64)
+			.build();
+		let mut result = Vec::new();
+		for doc in collect(self.database.collection::<Document>(&self.games).find(query(conditions), options).await?).await? {
+			result.push(self.doc_to_game(&doc)?);
+		}
+		Ok(result)
+	}
+
+	async fn tally(&self, filter: &GameFilter) -> Result<Vec<Tally>, StoreError> {
+		let pipeline = vec![
+			doc! { "$match": query(conditions(filter)) },
+			// one document for each seat of each game
+			doc! { "$project": {
+				"winner": 1,
+				"difficulty": 1,
+				"seats": [
+					{ "player": "$player1", "player_id": "$player1_id", "opponent": "$player2" },
+					{ "player": "$player2", "player_id": "$player2_id", "opponent": "$player1" },
+				],
+			} },
+			doc! { "$unwind": "$seats" },
+			doc! { "$group": {
+				"_id": {
+					"player": "$seats.player",
+					"player_id": { "$ifNull": ["$seats.player_id", Bson::Null] },
+					"computer": { "$eq": ["$seats.opponent", COMPUTER] },
+					"difficulty": { "$ifNull": ["$difficulty", Bson::Null] },
+				},
+				"games": { "$sum": 1 },
+				"wins": { "$sum": { "$cond": [{ "$eq": ["$winner", "$seats.player"] }, 1, 0] } },
+				"draws": { "$sum": { "$cond": [{ "$eq": ["$winner", ""] }, 1, 0] } },
+			} },
+		];
+		let mut result = Vec::new();
+		for doc in collect(self.database.collection::<Document>(&self.games).aggregate(pipeline, None).await?).await? {
+			let group = doc.get_document("_id")?;
+			result.push(Tally {
+				player: group.get_str("player")?.to_string(),
+				player_id: group.get_str("player_id").ok().map(|id| id.to_string()),
+				computer: group.get_bool("computer")?,
+				difficulty: match group.get("difficulty") {
+					Some(Bson::Int32(level)) => Some(*level as usize),
+					Some(Bson::Int64(level)) => Some(*level as usize),
+					_ => None,
+				},
+				games: count(&doc, "games")?,
+				wins: count(&doc, "wins")?,
+				draws: count(&doc, "draws")?,
+			});
+		}
+		Ok(result)
+	}
+
+	async fn get_game(&self, id: &str) -> Result<Option<Game>, StoreError> {
+		// ids that are not ObjectIds cannot match any game
+		let id = match ObjectId::parse_str(id) {
+			Ok(id) => id,
+			Err(_) => return Ok(None),
+		};
+		match self.database.collection::<Document>(&self.games).find_one(doc! { "_id": id }, None).await? {
+			Some(doc) => Ok(Some(self.doc_to_game(&doc)?)),
+			None => Ok(None),
+		}
+	}
+
+	async fn delete_game(&self, id: &str) -> Result<bool, StoreError> {
+		let id = match ObjectId::parse_str(id) {
+			Ok(id) => id,
+			Err(_) => return Ok(false),
+		};
+		let result = self.database.collection::<Document>(&self.games).delete_one(doc! { "_id": id }, None).await?;
+		Ok(result.deleted_count > 0)
+	}
+
+	async fn current_ratings(&self, gametype: &str) -> Result<Vec<RatingEntry>, StoreError> {
+		// entries are only ever appended, so the highest _id is the latest
+		let pipeline = vec![
+			doc! { "$match": { "gametype": gametype } },
+			doc! { "$sort": { "_id": -1 } },
+			// accounts by id, guests by name
+			doc! { "$group": {
+				"_id": {
+					"player_id": { "$ifNull": ["$player_id", Bson::Null] },
+					"player": { "$cond": [{ "$eq": [{ "$ifNull": ["$player_id", Bson::Null] }, Bson::Null] }, "$player", Bson::Null] },
+				},
+				"latest": { "$first": "$$ROOT" },
+			} },
+			doc! { "$replaceRoot": { "newRoot": "$latest" } },
+		];
+		let mut result = Vec::new();
+		for doc in collect(self.database.collection::<Document>(&self.ratings).aggregate(pipeline, None).await?).await? {
+			result.push(bson::from_document(doc)?);
+		}
+		Ok(result)
+	}
+
+	async fn latest_rating(&self, gametype: &str, player: &PlayerKey) -> Result<Option<RatingEntry>, StoreError> {
+		let options = FindOneOptions::builder().sort(doc! { "_id": -1 }).build();
+		let coll = self.database.collection::<RatingEntry>(&self.ratings);
+		Ok(coll.find_one(rated_player(gametype, player), options).await?)
+	}
+
+	async fn rating_history(&self, gametype: &str, player: &PlayerKey) -> Result<Vec<RatingEntry>, StoreError> {
+		let options = FindOptions::builder().sort(doc! { "_id": 1 }).build();
+		let coll = self.database.collection::<RatingEntry>(&self.ratings);
+		let mut result = Vec::new();
+		for entry in collect(coll.find(rated_player(gametype, player), options).await?).await? {
+			result.push(entry);
+		}
+		Ok(result)
+	}
+
+	async fn insert_ratings(&self, entries: &[RatingEntry]) -> Result<(), StoreError> {
+		self.database.collection::<RatingEntry>(&self.ratings).insert_many(entries, None).await?;
+		Ok(())
+	}
+
+	async fn insert_account(&self, mut account: Account) -> Result<Account, StoreError> {
+		let coll = self.database.collection::<Account>(&self.accounts);
+		let result = match coll.insert_one(&account, None).await {
+			Ok(result) => result,
+			// the unique index on `key` turns away a second account with the same name
+			Err(err) if matches!(&*err.kind, ErrorKind::Write(WriteFailure::WriteError(write)) if write.code == 11000) => {
+				return Err(NameTaken.into());
+			}
+			Err(err) => return Err(err.into()),
+		};
+		account.id = result.inserted_id.as_object_id().map(|id| id.to_hex());
+		Ok(account)
+	}
+
+	async fn find_account(&self, key: &str) -> Result<Option<Account>, StoreError> {
+		match self.database.collection::<Document>(&self.accounts).find_one(doc! { "key": key }, None).await? {
+			Some(doc) => Ok(Some(self.doc_to_account(doc)?)),
+			None => Ok(None),
+		}
+	}
+
+	async fn get_account(&self, id: &str) -> Result<Option<Account>, StoreError> {
+		let id = match ObjectId::parse_str(id) {
+			Ok(id) => id,
+			Err(_) => return Ok(None),
+		};
+		match self.database.collection::<Document>(&self.accounts).find_one(doc! { "_id": id }, None).await? {
+			Some(doc) => Ok(Some(self.doc_to_account(doc)?)),
+			None => Ok(None),
+		}
+	}
+
+	async fn insert_session(&self, session: SessionRecord) -> Result<(), StoreError> {
+		self.database.collection::<SessionRecord>(&self.sessions).insert_one(session, None).await?;
+		Ok(())
+	}
+
+	async fn find_session(&self, token: &str) -> Result<Option<SessionRecord>, StoreError> {
+		Ok(self.database.collection::<SessionRecord>(&self.sessions).find_one(doc! { "token": token }, None).await?)
+	}
+
+	async fn delete_session(&self, token: &str) -> Result<bool, StoreError> {
+		let result = self.database.collection::<SessionRecord>(&self.sessions).delete_one(doc! { "token": token }, None).await?;
+		Ok(result.deleted_count > 0)
+	}
+
+	async fn update_game(&self, game: &Game) -> Result<bool, StoreError> {
+		let id = match game.id.as_deref().map(ObjectId::parse_str) {
+			Some(Ok(id)) => id,
+			_ => return Ok(false),
+		};
+		let stored = Game { id: None, ..game.clone() };
+		let result = self.database.collection::<Game>(&self.games).replace_one(doc! { "_id": id }, stored, None).await?;
+		Ok(result.matched_count > 0)
+	}
+
+	async fn rename_player(&self, from: &str, into: &str, into_id: Option<&str>) -> Result<usize, StoreError> {
+		let games = self.database.collection::<Document>(&self.games);
+		// the winner and first mover first, while the guest seats still say who they are
+		let guest = doc! { "$or": [{ "player1": from, "player1_id": null }, { "player2": from, "player2_id": null }] };
+		for field in ["winner", "first_mover"] {
+			let mut filter = guest.clone();
+			filter.insert(field, from);
+			games.update_many(filter, doc! { "$set": { field: into } }, None).await?;
+		}
+		let mut count = 0;
+		for field in ["player1", "player2"] {
+			let id_field = format!("{}_id", field);
+			let mut set = doc! { field: into };
+			if let Some(into_id) = into_id {
+				set.insert(id_field.clone(), into_id);
+			}
+			count += games.update_many(doc! { field: from, id_field: null }, doc! { "$set": set }, None).await?.matched_count as usize;
+		}
+		Ok(count)
+	}
+
+	async fn clear_ratings(&self) -> Result<(), StoreError> {
+		self.database.collection::<Document>(&self.ratings).delete_many(doc! {}, None).await?;
+		Ok(())
+	}
+
+	async fn delete_ratings(&self, game_ids: &[String]) -> Result<(), StoreError> {
+		self.database.collection::<Document>(&self.ratings).delete_many(doc! { "game_id": { "$in": game_ids } }, None).await?;
+		Ok(())
+	}
+
+	async fn update_account(&self, account: &Account) -> Result<bool, StoreError> {
+		let id = match account.id.as_deref().map(ObjectId::parse_str) {
+			Some(Ok(id)) => id,
+			_ => return Ok(false),
+		};
+		let stored = Account { id: None, ..account.clone() };
+		let result = self.database.collection::<Account>(&self.accounts).replace_one(doc! { "_id": id }, stored, None).await?;
+		Ok(result.matched_count > 0)
+	}
+
+	async fn delete_sessions(&self, user_id: &str) -> Result<usize, StoreError> {
+		let result = self.database.collection::<SessionRecord>(&self.sessions).delete_many(doc! { "user_id": user_id }, None).await?;
+		Ok(result.deleted_count as usize)
+	}
+
+	// Documents from before the metadata fields have a display `date` instead.
+	// Ratings from before they were kept by account are dropped, so the games
+	// are rated again at startup. Also makes sure the indexes exist.
+	async fn migrate(&self) -> Result<usize, StoreError> {
+		let coll = self.database.collection::<Document>(&self.games);
+		let indexes = [
+			doc! { "ended_at": -1, "_id": -1 },
+			doc! { "gametype": 1, "ended_at": -1 },
+			doc! { "player1": 1, "ended_at": -1 },
+			doc! { "player2": 1, "ended_at": -1 },
+			doc! { "player1_id": 1, "ended_at": -1 },
+			doc! { "player2_id": 1, "ended_at": -1 },
+			doc! { "winner": 1 },
+		];
+		coll.create_indexes(indexes.into_iter().map(|keys| IndexModel::builder().keys(keys).build()), None).await?;
+		let ratings = self.database.collection::<Document>(&self.ratings);
+		let rating_indexes = [
+			doc! { "gametype": 1, "player": 1, "_id": -1 },
+			doc! { "gametype": 1, "player_id": 1, "_id": -1 },
+			doc! { "game_id": 1 },
+		];
+		ratings.create_indexes(rating_indexes.into_iter().map(|keys| IndexModel::builder().keys(keys).build()), None).await?;
+		if ratings.find_one(doc! { "player_id": { "$exists": false } }, None).await?.is_some() {
+			ratings.delete_many(doc! {}, None).await?;
+		}
+		let unique = || IndexOptions::builder().unique(true).build();
+		self.database.collection::<Document>(&self.accounts).create_index(
+			IndexModel::builder().keys(doc! { "key": 1 }).options(unique()).build(),
+			None,
+		).await?;
+		self.database.collection::<Document>(&self.sessions).create_index(
+			IndexModel::builder().keys(doc! { "token": 1 }).options(unique()).build(),
+			None,
+		).await?;
+		let mut count = 0;
+		for doc in collect(coll.find(doc! { "date": { "$exists": true } }, None).await?).await? {
+			let ended_at = doc.get_str("date").ok().and_then(legacy_date);
+			coll.update_one(
+				doc! { "_id": doc.get_object_id("_id")? },
+				doc! { "$set": { "ended_at": ended_at, "variant": "standard" }, "$unset": { "date": "" } },
+				None,
+			).await?;
+			count += 1;
+		}
+		Ok(count)
+	}
+}
+
+// Every document left in the cursor
+async fn collect<T: DeserializeOwned>(mut cursor: Cursor<T>) -> Result<Vec<T>, StoreError> {
+	let mut result = Vec::new();
+	while cursor.advance().await? {
+		result.push(cursor.deserialize_current()?);
+	}
+	Ok(result)
+}
+
+// The query conditions for everything in the filter but its cursor
+fn conditions(filter: &GameFilter) -> Vec<Document> {
+	let mut conditions: Vec<Document> = Vec::new();
+	if let Some(gametype) = &filter.gametype {
+		conditions.push(doc! { "gametype": gametype });
+	}
+	if let Some(player) = &filter.player {
+		conditions.push(doc! { "$or": [{ "player1": player }, { "player2": player }] });
+	}
+	if let Some(id) = &filter.player_id {
+		conditions.push(doc! { "$or": [{ "player1_id": id }, { "player2_id": id }] });
+	}
+	match filter.opponent {
+		Some(Opponent::Computer) => conditions.push(doc! { "$or": [{ "player1": COMPUTER }, { "player2": COMPUTER }] }),
+		Some(Opponent::Human) => conditions.push(doc! { "player1": { "$ne": COMPUTER }, "player2": { "$ne": COMPUTER } }),
+		None => {}
+	}
+	if let Some(winner) = &filter.winner {
+		conditions.push(doc! { "winner": winner });
+	}
+	if let Some(from) = &filter.from {
+		conditions.push(doc! { "ended_at": { "$gte": from } });
+	}
+	if let Some(to) = &filter.to {
+		conditions.push(doc! { "ended_at": { "$lt": to } });
+	}
+	// documents from before voiding have no `voided` field
+	match filter.voided {
+		Some(true) => conditions.push(doc! { "voided": true }),
+		Some(false) => conditions.push(doc! { "voided": { "$ne": true } }),
+		None => {}
+	}
+	conditions
+}
+
+// One player's rating entries; guests have no account on theirs
+fn rated_player(gametype: &str, player: &PlayerKey) -> Document {
+	match player {
+		PlayerKey::Account(id) => doc! { "gametype": gametype, "player_id": id },
+		PlayerKey::Guest(name) => doc! { "gametype": gametype, "player": name, "player_id": Bson::Null },
+	}
+}
+
+fn query(conditions: Vec<Document>) -> Document {
+	if conditions.is_empty() { doc! {} } else { doc! { "$and": conditions } }
+}
+
+// Counts come back as whichever integer type fits
+fn count(doc: &Document, key: &str) -> Result<u32, StoreError> {
+	match doc.get(key) {
+		Some(Bson::Int32(n)) => Ok(*n as u32),
+		Some(Bson::Int64(n)) => Ok(*n as u32),
+		_ => Err(format!("missing count {:?}", key).into()),
+	}
+}
+
+// Games after the cursor in (end time, id) order. Records without an end
+// time (the cursor says "") sort before every string in Mongo.
+fn after_cursor(ended_at: &str, id: &str, newest_first: bool) -> Result<Document, StoreError> {
+	let id = ObjectId::parse_str(id).map_err(|_| InvalidCursor)?;
+	let (beyond, id_beyond) = if newest_first { ("$lt", "$lt") } else { ("$gt", "$gt") };
+	let missing = doc! { "$in": [Bson::Null] };
+	Ok(match (ended_at.is_empty(), newest_first) {
+		(true, true) => doc! { "ended_at": missing, "_id": { id_beyond: id } },
+		(true, false) => doc! { "$or": [
+			{ "ended_at": { "$type": "string" } },
+			{ "ended_at": missing, "_id": { id_beyond: id } },
+		] },
+		(false, true) => doc! { "$or": [
+			{ "ended_at": { beyond: ended_at } },
+			{ "ended_at": missing },
+			{ "ended_at": ended_at, "_id": { id_beyond: id } },
+		] },
+		(false, false) => doc! { "$or": [
+			{ "ended_at": { beyond: ended_at } },
+			{ "ended_at": ended_at, "_id": { id_beyond: id } },
+		] },
+	})
+}
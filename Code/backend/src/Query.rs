@@ -0,0 +1,186 @@
+use std::error::Error;
+use std::fmt;
+
+use chrono::prelude::*;
+use chrono::Duration;
+use common::Backend::{Game, GamePage};
+
+// the name the frontend records for the AI
+pub const COMPUTER: &str = "Computer";
+
+const DEFAULT_LIMIT: usize = 50;
+pub const MAX_LIMIT: usize = 500;
+
+// Who sat in a seat: the account when the seat has one, the name otherwise,
+// so a guest playing under an account's name is someone else
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PlayerKey {
+	Account(String),
+	Guest(String),
+}
+
+impl PlayerKey {
+	pub fn new(name: &str, id: Option<&str>) -> Self {
+		match id {
+			Some(id) => PlayerKey::Account(id.to_string()),
+			None => PlayerKey::Guest(name.to_string()),
+		}
+	}
+
+	// The name this player had in the game, None if they did not play it
+	pub fn seat<'a>(&self, game: &'a Game) -> Option<&'a str> {
+		[(&game.player1, &game.player1_id), (&game.player2, &game.player2_id)]
+			.into_iter()
+			.find(|(name, id)| PlayerKey::new(name, id.as_deref()) == *self)
+			.map(|(name, _)| name.as_str())
+	}
+}
+
+// Query string of `GET /games`, as sent by the client
+#[derive(FromForm, Default, Debug)]
+pub struct GameQuery {
+	pub gametype: Option<String>,
+	// games where this name sat in either seat
+	pub player: Option<String>,
+	// "human" or "computer"
+	pub opponent: Option<String>,
+	// an empty winner matches draws
+	pub winner: Option<String>,
+	// RFC 3339 times or YYYY-MM-DD dates on the end time, `from` inclusive
+	// and `to` exclusive; a date for `to` includes that whole day
+	pub from: Option<String>,
+	pub to: Option<String>,
+	// "newest" (the default) or "oldest"
+	pub sort: Option<String>,
+	pub cursor: Option<String>,
+	pub limit: Option<usize>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Opponent {
+	Human,
+	Computer,
+}
+
+// A checked query the stores can run as is. Games come back ordered by end
+// time and then id, and `after` is the (end time, id) of the last game on the
+// previous page, with "" standing for a missing end time.
+#[derive(Clone, Debug)]
+pub struct GameFilter {
+	pub gametype: Option<String>,
+	pub player: Option<String>,
+	// games where this account sat in either seat
+	pub player_id: Option<String>,
+	pub opponent: Option<Opponent>,
+	pub winner: Option<String>,
+	pub from: Option<String>,
+	pub to: Option<String>,
+	// Some(false) leaves out voided games, only admins see them
+	pub voided: Option<bool>,
+	pub newest_first: bool,
+	pub after: Option<(String, String)>,
+	pub limit: usize,
+}
+
+// A cursor one store cannot have produced, e.g. an id from another backend
+#[derive(Debug)]
+pub struct InvalidCursor;
+
+impl fmt::Display for InvalidCursor {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "invalid cursor")
+	}
+}
+
+impl Error for InvalidCursor {}
+
+pub fn check_gametype(gametype: &str) -> Result<(), String> {
+	if gametype != "Connect4" && gametype != "TootOtto" {
+		return Err(format!("`gametype` must be Connect4 or TootOtto, not {:?}", gametype));
+	}
+	Ok(())
+}
+
+fn time_bound(key: &str, value: &str, end_of_day: bool) -> Result<String, String> {
+	if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+		return Ok(time.with_timezone(&Utc).to_rfc3339_opts(SecondsFormat::Secs, true));
+	}
+	match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+		Ok(date) => {
+			let date = if end_of_day { date + Duration::days(1) } else { date };
+			let time = Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap());
+			Ok(time.to_rfc3339_opts(SecondsFormat::Secs, true))
+		}
+		Err(_) => Err(format!("`{}` must be an RFC 3339 time or a YYYY-MM-DD date, not {:?}", key, value)),
+	}
+}
+
+impl GameQuery {
+	pub fn validate(self) -> Result<GameFilter, String> {
+		if let Some(gametype) = &self.gametype {
+			check_gametype(gametype)?;
+		}
+		let opponent = match self.opponent.as_deref() {
+			None => None,
+			Some("human") => Some(Opponent::Human),
+			Some("computer") => Some(Opponent::Computer),
+			Some(other) => return Err(format!("`opponent` must be human or computer, not {:?}", other)),
+		};
+		let newest_first = match self.sort.as_deref() {
+			None | Some("newest") => true,
+			Some("oldest") => false,
+			Some(other) => return Err(format!("`sort` must be newest or oldest, not {:?}", other)),
+		};
+		let after = match &self.cursor {
+			None => None,
+			Some(cursor) => match cursor.rsplit_once('_') {
+				Some((ended_at, id)) if !id.is_empty() => Some((ended_at.to_string(), id.to_string())),
+				_ => return Err(InvalidCursor.to_string()),
+			},
+		};
+		let limit = self.limit.unwrap_or(DEFAULT_LIMIT);
+		if limit == 0 || limit > MAX_LIMIT {
+			return Err(format!("`limit` must be between 1 and {}", MAX_LIMIT));
+		}
+		Ok(GameFilter {
+			gametype: self.gametype,
+			player: self.player,
+			player_id: None,
+			opponent,
+			winner: self.winner,
+			from: self.from.map(|from| time_bound("from", &from, false)).transpose()?,
+			to: self.to.map(|to| time_bound("to", &to, to.len() == 10)).transpose()?,
+			voided: Some(false),
+			newest_first,
+			after,
+			limit,
+		})
+	}
+}
+
+impl GameFilter {
+	// Everything but the cursor, for stores that filter in memory
+	pub fn matches(&self, game: &Game) -> bool {
+		let ended_at = game.ended_at.as_deref().unwrap_or("");
+		let against_computer = game.player1 == COMPUTER || game.player2 == COMPUTER;
+		self.gametype.as_ref().is_none_or(|gametype| game.gametype == *gametype)
+			&& self.player.as_ref().is_none_or(|player| game.player1 == *player || game.player2 == *player)
+			&& self.player_id.as_ref().is_none_or(|id| [&game.player1_id, &game.player2_id].iter().any(|seat| seat.as_ref() == Some(id)))
+			&& self.opponent.is_none_or(|opponent| against_computer == (opponent == Opponent::Computer))
+			&& self.winner.as_ref().is_none_or(|winner| game.winner == *winner)
+			&& self.from.as_ref().is_none_or(|from| !ended_at.is_empty() && ended_at >= from.as_str())
+			&& self.to.as_ref().is_none_or(|to| !ended_at.is_empty() && ended_at < to.as_str())
+			&& self.voided.is_none_or(|voided| game.voided == voided)
+	}
+
+	// Stores fetch one game more than the limit, so a full page knows there is another
+	pub fn page(&self, mut games: Vec<Game>) -> GamePage {
+		let next_cursor = if games.len() > self.limit {
+			games.truncate(self.limit);
+			games.last().map(|game| format!("{}_{}", game.ended_at.as_deref().unwrap_or(""), game.id.as_deref().unwrap_or("")))
+		} else {
+			None
+		};
+		GamePage { games, next_cursor }
+	}
+}
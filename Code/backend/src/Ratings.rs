@@ -0,0 +1,272 @@
+use std::f64::consts::PI;
+
+use chrono::prelude::*;
+use common::Backend::{Game, RatingEntry};
+use rocket::tokio::sync::{Mutex, MutexGuard};
+
+use crate::Query::{GameFilter, GameQuery, PlayerKey, COMPUTER, MAX_LIMIT};
+use crate::Storage::{GameStore, StoreError};
+
+// Glicko-2 as in Glickman's "Example of the Glicko-2 system", with every game
+// its own rating period so ratings move as soon as a result is stored
+const DEFAULT_RATING: f64 = 1500.0;
+const DEFAULT_DEVIATION: f64 = 350.0;
+const DEFAULT_VOLATILITY: f64 = 0.06;
+// how much the volatility may change, smaller is steadier
+const TAU: f64 = 0.5;
+const SCALE: f64 = 173.7178;
+const EPSILON: f64 = 0.000001;
+
+// Ratings are written one game at a time, in the order played. A game
+// stored while the ratings are rebuilt waits, so it is rated once, after
+// the games before it.
+static WRITING: Mutex<()> = Mutex::const_new(());
+
+// Proof the ratings are not being written elsewhere, from `writing`
+pub struct Writing {
+	_guard: MutexGuard<'static, ()>,
+}
+
+pub async fn writing() -> Writing {
+	Writing { _guard: WRITING.lock().await }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Glicko {
+	rating: f64,
+	deviation: f64,
+	volatility: f64,
+}
+
+impl Glicko {
+	fn of(entry: Option<&RatingEntry>) -> Glicko {
+		match entry {
+			Some(entry) => Glicko { rating: entry.rating, deviation: entry.deviation, volatility: entry.volatility },
+			None => Glicko { rating: DEFAULT_RATING, deviation: DEFAULT_DEVIATION, volatility: DEFAULT_VOLATILITY },
+		}
+	}
+}
+
+fn g(phi: f64) -> f64 {
+	1.0 / (1.0 + 3.0 * phi * phi / (PI * PI)).sqrt()
+}
+
+// The player's new rating after one game, `score` 1 for a win, 0.5 for a draw and 0 for a loss
+fn update(player: Glicko, opponent: Glicko, score: f64) -> Glicko {
+	let mu = (player.rating - DEFAULT_RATING) / SCALE;
+	let phi = player.deviation / SCALE;
+	let mu_j = (opponent.rating - DEFAULT_RATING) / SCALE;
+	let g_j = g(opponent.deviation / SCALE);
+	let expected = 1.0 / (1.0 + (-g_j * (mu - mu_j)).exp());
+	let v = 1.0 / (g_j * g_j * expected * (1.0 - expected));
+	let delta = v * g_j * (score - expected);
+
+	// the new volatility is the root of f, found by the Illinois method
+	let a = (player.volatility * player.volatility).ln();
+	let f = |x: f64| {
+		let ex = x.exp();
+		ex * (delta * delta - phi * phi - v - ex) / (2.0 * (phi * phi + v + ex).powi(2)) - (x - a) / (TAU * TAU)
+	};
+	let mut low = a;
+	let mut high = if delta * delta > phi * phi + v {
+		(delta * delta - phi * phi - v).ln()
+	} else {
+		let mut k = 1.0;
+		while f(a - k * TAU) < 0.0 {
+			k += 1.0;
+		}
+		a - k * TAU
+	};
+	let (mut f_low, mut f_high) = (f(low), f(high));
+	while (high - low).abs() > EPSILON {
+		let c = low + (low - high) * f_low / (f_high - f_low);
+		let f_c = f(c);
+		if f_c * f_high <= 0.0 {
+			low = high;
+			f_low = f_high;
+		} else {
+			f_low /= 2.0;
+		}
+		high = c;
+		f_high = f_c;
+	}
+	let volatility = (low / 2.0).exp();
+
+	let phi_star = (phi * phi + volatility * volatility).sqrt();
+	let phi_new = 1.0 / (1.0 / (phi_star * phi_star) + 1.0 / v).sqrt();
+	let mu_new = mu + phi_new * phi_new * g_j * (score - expected);
+	Glicko { rating: mu_new * SCALE + DEFAULT_RATING, deviation: phi_new * SCALE, volatility }
+}
+
+// The name and account a seat is rated under; each AI difficulty is a
+// player of its own
+fn rated_seat(game: &Game, player: &str, player_id: &Option<String>) -> (String, Option<String>) {
+	match (player == COMPUTER, game.difficulty) {
+		(true, Some(level)) => (format!("{} (level {})", COMPUTER, level), None),
+		_ => (player.to_string(), player_id.clone()),
+	}
+}
+
+// Update both players' ratings for a game just stored, which must be newer
+// than every rated game; store and rate it under the same `writing`
+pub async fn rate_game(db: &dyn GameStore, game: &Game, _writing: &Writing) -> Result<(), StoreError> {
+	rate(db, game).await
+}
+
+async fn rate(db: &dyn GameStore, game: &Game) -> Result<(), StoreError> {
+	let seats = [rated_seat(game, &game.player1, &game.player1_id), rated_seat(game, &game.player2, &game.player2_id)];
+	let keys = seats.clone().map(|(name, id)| PlayerKey::new(&name, id.as_deref()));
+	let before = [
+		db.latest_rating(&game.gametype, &keys[0]).await?,
+		db.latest_rating(&game.gametype, &keys[1]).await?,
+	];
+	let score = |player: &str| {
+		if game.winner == player {
+			1.0
+		} else if game.winner.is_empty() {
+			0.5
+		} else {
+			0.0
+		}
+	};
+	let scores = [score(&game.player1), score(&game.player2)];
+	let at = game.ended_at.clone().unwrap_or_else(|| Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true));
+	let entries: Vec<RatingEntry> = (0..2)
+		.map(|seat| {
+			let rating = update(Glicko::of(before[seat].as_ref()), Glicko::of(before[1 - seat].as_ref()), scores[seat]);
+			RatingEntry {
+				player: seats[seat].0.clone(),
+				player_id: seats[seat].1.clone(),
+				gametype: game.gametype.clone(),
+				game_id: game.id.clone(),
+				rating: rating.rating,
+				deviation: rating.deviation,
+				volatility: rating.volatility,
+				games: before[seat].as_ref().map_or(0, |entry| entry.games) + 1,
+				at: at.clone(),
+			}
+		})
+		.collect();
+	db.insert_ratings(&entries).await
+}
+
+// The games that count from `from` on, oldest first, a page at a time
+fn counted_from(from: Option<String>) -> Result<GameFilter, StoreError> {
+	Ok(GameQuery { from, sort: Some("oldest".to_string()), limit: Some(MAX_LIMIT), ..Default::default() }.validate()?)
+}
+
+fn next_page(filter: &mut GameFilter, next_cursor: Option<String>, last: Option<&Game>) -> bool {
+	match (next_cursor, last) {
+		(Some(_), Some(last)) => {
+			filter.after = Some((last.ended_at.clone().unwrap_or_default(), last.id.clone().unwrap_or_default()));
+			true
+		}
+		_ => false,
+	}
+}
+
+// Rate every game that counts from `from` on, oldest first; returns how many
+async fn rate_from(db: &dyn GameStore, from: Option<String>) -> Result<usize, StoreError> {
+	let mut filter = counted_from(from)?;
+	let mut count = 0;
+	loop {
+		let page = filter.page(db.find_games(&filter).await?);
+		for game in &page.games {
+			rate(db, game).await?;
+			count += 1;
+		}
+		if !next_page(&mut filter, page.next_cursor, page.games.last()) {
+			return Ok(count);
+		}
+	}
+}
+
+// Rate the games stored before there were ratings, oldest first. Does
+// nothing once any game type has ratings, returns how many games it rated.
+pub async fn backfill(db: &dyn GameStore) -> Result<usize, StoreError> {
+	let _writing = writing().await;
+	for gametype in ["Connect4", "TootOtto"] {
+		if !db.current_ratings(gametype).await?.is_empty() {
+			return Ok(0);
+		}
+	}
+	rate_from(db, None).await
+}
+
+// Throw every rating away and rate all the games that count again, oldest first
+pub async fn recompute(db: &dyn GameStore) -> Result<usize, StoreError> {
+	let _writing = writing().await;
+	db.clear_ratings().await?;
+	rate_from(db, None).await
+}
+
+// Rate again the games from `changed` on, for when it was edited, voided or
+// deleted; the ratings before it stay as they are. Games without an end time
+// come before all others, so changing one rates everything again.
+pub async fn recompute_from(db: &dyn GameStore, changed: &Game) -> Result<usize, StoreError> {
+	let from = match &changed.ended_at {
+		Some(ended_at) => ended_at.clone(),
+		None => return recompute(db).await,
+	};
+	let _writing = writing().await;
+	let mut ids: Vec<String> = changed.id.iter().cloned().collect();
+	let mut filter = counted_from(Some(from.clone()))?;
+	loop {
+		let page = filter.page(db.find_games(&filter).await?);
+		ids.extend(page.games.iter().filter_map(|game| game.id.clone()));
+		if !next_page(&mut filter, page.next_cursor, page.games.last()) {
+			break;
+		}
+	}
+	db.delete_ratings(&ids).await?;
+	rate_from(db, Some(from)).await
+}
+
+// Current ratings of a game type, highest first
+pub async fn ranking(db: &dyn GameStore, gametype: &str) -> Result<Vec<RatingEntry>, StoreError> {
+	let mut ratings = db.current_ratings(gametype).await?;
+	ratings.sort_by(|a, b| b.rating.total_cmp(&a.rating).then(a.player.cmp(&b.player)));
+	Ok(ratings)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::Storage::fixtures::{game, stores};
+
+	async fn ratings(db: &dyn GameStore) -> Vec<(String, f64, u32)> {
+		let mut ratings: Vec<_> = ranking(db, "Connect4").await.unwrap().into_iter().map(|entry| (entry.player, entry.rating, entry.games)).collect();
+		ratings.sort_by(|a, b| a.0.cmp(&b.0));
+		ratings
+	}
+
+	// rating from the changed game on ends where rating everything does
+	#[rocket::async_test]
+	async fn recomputes_from_a_changed_game() {
+		for db in stores().await {
+			let db = db.as_ref();
+			let games = [game("Connect4", "ann", "bob", "ann", 1), game("Connect4", "bob", "cat", "bob", 2), game("Connect4", "cat", "ann", "", 3), game("Connect4", "ann", "bob", "bob", 4)];
+			let mut stored = Vec::new();
+			for game in games {
+				let game = db.insert_game(game).await.unwrap();
+				rate_game(db, &game, &writing().await).await.unwrap();
+				stored.push(game);
+			}
+
+			let mut voided = stored[1].clone();
+			voided.voided = true;
+			db.update_game(&voided).await.unwrap();
+			assert_eq!(recompute_from(db, &voided).await.unwrap(), 2);
+			let partial = ratings(db).await;
+			assert_eq!(recompute(db).await.unwrap(), 3);
+			assert_eq!(partial, ratings(db).await);
+			assert_eq!(partial.iter().map(|(_, _, games)| *games).collect::<Vec<_>>(), [3, 2, 1]);
+
+			db.delete_game(stored[0].id.as_deref().unwrap()).await.unwrap();
+			recompute_from(db, &stored[0]).await.unwrap();
+			let partial = ratings(db).await;
+			recompute(db).await.unwrap();
+			assert_eq!(partial, ratings(db).await);
+		}
+	}
+}
@@ -0,0 +1,37 @@
+use common::Backend::{Game, ReplayFrame};
+use common::Position::{Move, Position};
+use common::Render::{self, RenderOptions};
+
+// Every board of a stored game from the empty one to the last, and the
+// replayed positions they were made from
+pub fn frames(game: &Game) -> Result<(Vec<ReplayFrame>, Vec<Position>), String> {
+	let positions = game.replay()?;
+	let frames = positions.iter().enumerate().map(|(ply, position)| {
+		let text = position.to_string();
+		let board = text.split(' ').next().unwrap_or("");
+		ReplayFrame {
+			ply,
+			last_move: if ply == 0 { None } else { Some(game.moves[ply - 1].clone()) },
+			position: text.clone(),
+			rows: board.split('/').map(|row| row.to_string()).collect(),
+			winner: position.winner().map(|side| game.player_on(side).to_string()),
+		}
+	}).collect();
+	Ok((frames, positions))
+}
+
+// The board after `ply` moves as SVG or PNG, with a ring on the last move
+// when `marker` is set
+pub fn image(game: &Game, positions: &[Position], ply: usize, marker: bool, mut options: RenderOptions, png: bool) -> Result<Vec<u8>, String> {
+	let position = &positions[ply];
+	options.last_move = match ply {
+		0 => None,
+		_ if !marker => None,
+		_ => Some(Move::parse(position.gametype(), &game.moves[ply - 1])?.column()),
+	};
+	if png {
+		Render::png(position, &options)
+	} else {
+		Ok(Render::svg(position, &options).into_bytes())
+	}
+}
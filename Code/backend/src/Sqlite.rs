@@ -0,0 +1,592 @@
+use std::sync::{Arc, Mutex};
+
+use common::Backend::{Game, RatingEntry, Role};
+use rusqlite::{params, params_from_iter, Connection, ErrorCode, OptionalExtension, Row};
+use rusqlite::types::Value;
+
+use crate::Accounts::{Account, NameTaken, SessionRecord};
+use crate::Query::{GameFilter, InvalidCursor, Opponent, PlayerKey, COMPUTER};
+use crate::Stats::Tally;
+use crate::Storage::{GameStore, StoreError};
+
+const COLUMNS: &str = "id, gametype, player1, player2, winner, started_at, ended_at, duration_secs, move_count, difficulty, variant, first_mover, player1_word, moves, player1_id, player2_id, voided";
+const ACCOUNT_COLUMNS: &str = "id, name, key, password_hash, created_at, role, banned";
+const RATING_COLUMNS: &str = "player, gametype, game_id, rating, deviation, volatility, games, at, player_id";
+
+// Columns added after the first version of the table, with their types
+const ADDED_COLUMNS: [(&str, &str); 12] = [
+	("started_at", "TEXT"),
+	("ended_at", "TEXT"),
+	("duration_secs", "INTEGER"),
+	("move_count", "INTEGER"),
+	("difficulty", "INTEGER"),
+	("variant", "TEXT NOT NULL DEFAULT 'standard'"),
+	("first_mover", "TEXT"),
+	("player1_word", "TEXT"),
+	// space separated, moves never contain spaces
+	("moves", "TEXT NOT NULL DEFAULT ''"),
+	("player1_id", "TEXT"),
+	("player2_id", "TEXT"),
+	("voided", "INTEGER NOT NULL DEFAULT 0"),
+];
+
+// Columns added to the accounts table after its first version
+const ADDED_ACCOUNT_COLUMNS: [(&str, &str); 2] = [
+	("role", "TEXT NOT NULL DEFAULT 'player'"),
+	("banned", "INTEGER NOT NULL DEFAULT 0"),
+];
+
+// Games in a single SQLite file, no server needed
+pub struct SqliteStore {
+	connection: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStore {
+	pub fn new(path: &str) -> Result<Self, StoreError> {
+		let connection = Connection::open(path)?;
+		connection.execute(
+			"CREATE TABLE IF NOT EXISTS games (
+				id INTEGER PRIMARY KEY AUTOINCREMENT,
+				gametype TEXT NOT NULL,
+				player1 TEXT NOT NULL,
+				player2 TEXT NOT NULL,
+				winner TEXT NOT NULL,
+				started_at TEXT,
+				ended_at TEXT,
+				duration_secs INTEGER,
+				move_count INTEGER,
+				difficulty INTEGER,
+				variant TEXT NOT NULL DEFAULT 'standard',
+				first_mover TEXT,
+				player1_word TEXT,
+				moves TEXT NOT NULL DEFAULT '',
+				player1_id TEXT,
+				player2_id TEXT,
+				voided INTEGER NOT NULL DEFAULT 0
+			)",
+			[],
+		)?;
+		// one row per player and rated game, the highest id is the current rating
+		connection.execute(
+			"CREATE TABLE IF NOT EXISTS ratings (
+				id INTEGER PRIMARY KEY AUTOINCREMENT,
+				player TEXT NOT NULL,
+				gametype TEXT NOT NULL,
+				game_id TEXT,
+				rating REAL NOT NULL,
+				deviation REAL NOT NULL,
+				volatility REAL NOT NULL,
+				games INTEGER NOT NULL,
+				at TEXT NOT NULL,
+				player_id TEXT
+			)",
+			[],
+		)?;
+		connection.execute(
+			"CREATE TABLE IF NOT EXISTS accounts (
+				id INTEGER PRIMARY KEY AUTOINCREMENT,
+				name TEXT NOT NULL,
+				key TEXT NOT NULL UNIQUE,
+				password_hash TEXT NOT NULL,
+				created_at TEXT NOT NULL,
+				role TEXT NOT NULL DEFAULT 'player',
+				banned INTEGER NOT NULL DEFAULT 0
+			)",
+			[],
+		)?;
+		connection.execute(
+			"CREATE TABLE IF NOT EXISTS sessions (
+				token TEXT PRIMARY KEY,
+				user_id TEXT NOT NULL,
+				expires_at TEXT NOT NULL
+			)",
+			[],
+		)?;
+		Ok(Self {
+			connection: Arc::new(Mutex::new(connection)),
+		})
+	}
+
+	// rusqlite blocks the thread it runs on, so queries run on the blocking
+	// pool, one at a time, rather than on the request threads
+	async fn run<T, F>(&self, query: F) -> Result<T, StoreError>
+	where
+		T: Send + 'static,
+		F: FnOnce(&mut Connection) -> Result<T, StoreError> + Send + 'static,
+	{
+		let connection = self.connection.clone();
+		rocket::tokio::task::spawn_blocking(move || {
+			let mut connection = connection.lock().map_err(|err| err.to_string())?;
+			query(&mut connection)
+		}).await?
+	}
+}
+
+// The WHERE conditions for everything in the filter but its cursor
+fn conditions(filter: &GameFilter) -> (Vec<&'static str>, Vec<Value>) {
+	let mut conditions: Vec<&str> = Vec::new();
+	let mut values: Vec<Value> = Vec::new();
+	if let Some(gametype) = &filter.gametype {
+		conditions.push("gametype = ?");
+		values.push(gametype.clone().into());
+	}
+	if let Some(player) = &filter.player {
+		conditions.push("(player1 = ? OR player2 = ?)");
+		values.push(player.clone().into());
+		values.push(player.clone().into());
+	}
+	if let Some(id) = &filter.player_id {
+		conditions.push("(player1_id = ? OR player2_id = ?)");
+		values.push(id.clone().into());
+		values.push(id.clone().into());
+	}
+	match filter.opponent {
+		Some(Opponent::Computer) => conditions.push("(player1 = ? OR player2 = ?)"),
+		Some(Opponent::Human) => conditions.push("player1 <> ? AND player2 <> ?"),
+		None => {}
+	}
+	if filter.opponent.is_some() {
+		values.push(COMPUTER.to_string().into());
+		values.push(COMPUTER.to_string().into());
+	}
+	if let Some(winner) = &filter.winner {
+		conditions.push("winner = ?");
+		values.push(winner.clone().into());
+	}
+	if let Some(from) = &filter.from {
+		conditions.push("ended_at >= ?");
+		values.push(from.clone().into());
+	}
+	if let Some(to) = &filter.to {
+		conditions.push("ended_at < ?");
+		values.push(to.clone().into());
+	}
+	if let Some(voided) = filter.voided {
+		conditions.push("voided = ?");
+		values.push(voided.into());
+	}
+	(conditions, values)
+}
+
+fn where_clause(conditions: &[&str]) -> String {
+	if conditions.is_empty() {
+		String::new()
+	} else {
+		format!(" WHERE {}", conditions.join(" AND "))
+	}
+}
+
+#[rocket::async_trait]
+impl GameStore for SqliteStore {
+	async fn ping(&self) -> Result<(), StoreError> {
+		self.run(|connection| Ok(connection.query_row("SELECT 1", [], |_| Ok(()))?)).await
+	}
+
+	async fn insert_game(&self, mut game: Game) -> Result<Game, StoreError> {
+		self.run(move |connection| {
+			connection.execute(
+				"INSERT INTO games (gametype, player1, player2, winner, started_at, ended_at, duration_secs, move_count, difficulty, variant, first_mover, player1_word, moves, player1_id, player2_id, voided)
+				VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
+				params![
+					game.gametype, game.player1, game.player2, game.winner,
+					game.started_at, game.ended_at, game.duration_secs, game.move_count,
+					game.difficulty.map(|level| level as i64), game.variant, game.first_mover,
+					game.player1_word, game.moves.join(" "), game.player1_id, game.player2_id, game.voided,
+				],
+			)?;
+			game.id = Some(connection.last_insert_rowid().to_string());
+			Ok(game)
+		}).await
+	}
+
+	async fn find_games(&self, filter: &GameFilter) -> Result<Vec<Game>, StoreError> {
+		let (mut conditions, mut values) = conditions(filter);
+		if let Some((ended_at, id)) = &filter.after {
+			let id = id.parse::<i64>().map_err(|_| InvalidCursor)?;
+			conditions.push(if filter.newest_first {
+				"(COALESCE(ended_at, '') < ? OR (COALESCE(ended_at, '') = ? AND id < ?))"
+			} else {
+				"(COALESCE(ended_at, '') > ? OR (COALESCE(ended_at, '') = ? AND id > ?))"
+			});
+			values.push(ended_at.clone().into());
+			values.push(ended_at.clone().into());
+			values.push(id.into());
+		}
+		let mut sql = format!("SELECT {} FROM games{}", COLUMNS, where_clause(&conditions));
+		let order = if filter.newest_first { "DESC" } else { "ASC" };
+		sql += &format!(" ORDER BY COALESCE(ended_at, '') {}, id {} LIMIT {}", order, order, filter.limit + 1);
+		self.run(move |connection| {
+			let mut statement = connection.prepare(&sql)?;
+			let rows = statement.query_map(params_from_iter(values), row_to_game)?;
+			Ok(rows.collect::<Result<Vec<_>, _>>()?)
+		}).await
+	}
+
+	async fn tally(&self, filter: &GameFilter) -> Result<Vec<Tally>, StoreError> {
+		let (conditions, filter_values) = conditions(filter);
+		let filtered = where_clause(&conditions);
+		// each game once from either seat
+		let sql = format!(
+			"SELECT player, player_id, computer, difficulty, COUNT(*), SUM(winner = player), SUM(winner = '') FROM (\
+				SELECT player1 AS player, player1_id AS player_id, player2 = ? AS computer, difficulty, winner FROM games{} \
+				UNION ALL \
+				SELECT player2, player2_id, player1 = ?, difficulty, winner FROM games{}\
+			) GROUP BY player, player_id, computer, difficulty",
+			filtered, filtered
+		);
+		let mut values: Vec<Value> = vec![COMPUTER.to_string().into()];
+		values.extend(filter_values.iter().cloned());
+		values.push(COMPUTER.to_string().into());
+		values.extend(filter_values);
+		self.run(move |connection| {
+			let mut statement = connection.prepare(&sql)?;
+			let rows = statement.query_map(params_from_iter(values), |row| {
+				Ok(Tally {
+					player: row.get(0)?,
+					player_id: row.get(1)?,
+					computer: row.get(2)?,
+					difficulty: row.get::<_, Option<i64>>(3)?.map(|level| level as usize),
+					games: row.get(4)?,
+					wins: row.get(5)?,
+					draws: row.get(6)?,
+				})
+			})?;
+			Ok(rows.collect::<Result<Vec<_>, _>>()?)
+		}).await
+	}
+
+	async fn get_game(&self, id: &str) -> Result<Option<Game>, StoreError> {
+		let id = id.to_string();
+		self.run(move |connection| {
+			Ok(connection.query_row(&format!("SELECT {} FROM games WHERE id = ?1", COLUMNS), params![id], row_to_game).optional()?)
+		}).await
+	}
+
+	async fn delete_game(&self, id: &str) -> Result<bool, StoreError> {
+		let id = id.to_string();
+		self.run(move |connection| Ok(connection.execute("DELETE FROM games WHERE id = ?1", params![id])? > 0)).await
+	}
+
+	async fn current_ratings(&self, gametype: &str) -> Result<Vec<RatingEntry>, StoreError> {
+		let gametype = gametype.to_string();
+		self.run(move |connection| {
+			let mut statement = connection.prepare(&format!(
+				"SELECT {} FROM ratings WHERE id IN (\
+					SELECT MAX(id) FROM ratings WHERE gametype = ?1 GROUP BY player_id, CASE WHEN player_id IS NULL THEN player END\
+				)",
+				RATING_COLUMNS
+			))?;
+			let rows = statement.query_map(params![gametype], row_to_rating)?;
+			Ok(rows.collect::<Result<Vec<_>, _>>()?)
+		}).await
+	}
+
+	async fn latest_rating(&self, gametype: &str, player: &PlayerKey) -> Result<Option<RatingEntry>, StoreError> {
+		let (gametype, (rated, value)) = (gametype.to_string(), rated_player(player));
+		self.run(move |connection| {
+			Ok(connection
+				.query_row(
+					&format!("SELECT {} FROM ratings WHERE gametype = ?1 AND {} ORDER BY id DESC LIMIT 1", RATING_COLUMNS, rated),
+					params![gametype, value],
+					row_to_rating,
+				)
+				.optional()?)
+		}).await
+	}
+
+	async fn rating_history(&self, gametype: &str, player: &PlayerKey) -> Result<Vec<RatingEntry>, StoreError> {
+		let (gametype, (rated, value)) = (gametype.to_string(), rated_player(player));
+		self.run(move |connection| {
+			let mut statement = connection.prepare(&format!(
+				"SELECT {} FROM ratings WHERE gametype = ?1 AND {} ORDER BY id",
+				RATING_COLUMNS, rated
+			))?;
+			let rows = statement.query_map(params![gametype, value], row_to_rating)?;
+			Ok(rows.collect::<Result<Vec<_>, _>>()?)
+		}).await
+	}
+
+	async fn insert_ratings(&self, entries: &[RatingEntry]) -> Result<(), StoreError> {
+		let entries = entries.to_vec();
+		self.run(move |connection| {
+			let transaction = connection.transaction()?;
+			for entry in &entries {
+				transaction.execute(
+					&format!("INSERT INTO ratings ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)", RATING_COLUMNS),
+					params![
+						entry.player, entry.gametype, entry.game_id, entry.rating,
+						entry.deviation, entry.volatility, entry.games, entry.at, entry.player_id,
+					],
+				)?;
+			}
+			transaction.commit()?;
+			Ok(())
+		}).await
+	}
+
+	async fn insert_account(&self, mut account: Account) -> Result<Account, StoreError> {
+		self.run(move |connection| {
+			let inserted = connection.execute(
+				"INSERT INTO accounts (name, key, password_hash, created_at, role, banned) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+				params![account.name, account.key, account.password_hash, account.created_at, role_name(account.role), account.banned],
+			);
+			match inserted {
+				Err(rusqlite::Error::SqliteFailure(err, _)) if err.code == ErrorCode::ConstraintViolation => return Err(NameTaken.into()),
+				other => other?,
+			};
+			account.id = Some(connection.last_insert_rowid().to_string());
+			Ok(account)
+		}).await
+	}
+
+	async fn find_account(&self, key: &str) -> Result<Option<Account>, StoreError> {
+		let key = key.to_string();
+		self.run(move |connection| {
+			Ok(connection.query_row(&format!("SELECT {} FROM accounts WHERE key = ?1", ACCOUNT_COLUMNS), params![key], row_to_account).optional()?)
+		}).await
+	}
+
+	async fn get_account(&self, id: &str) -> Result<Option<Account>, StoreError> {
+		let id = id.to_string();
+		self.run(move |connection| {
+			Ok(connection.query_row(&format!("SELECT {} FROM accounts WHERE id = ?1", ACCOUNT_COLUMNS), params![id], row_to_account).optional()?)
+		}).await
+	}
+
+	async fn insert_session(&self, session: SessionRecord) -> Result<(), StoreError> {
+		self.run(move |connection| {
+			connection.execute(
+				"INSERT INTO sessions (token, user_id, expires_at) VALUES (?1, ?2, ?3)",
+				params![session.token, session.user_id, session.expires_at],
+			)?;
+			Ok(())
+		}).await
+	}
+
+	async fn find_session(&self, token: &str) -> Result<Option<SessionRecord>, StoreError> {
+		let token = token.to_string();
+		self.run(move |connection| {
+			Ok(connection
+				.query_row("SELECT token, user_id, expires_at FROM sessions WHERE token = ?1", params![token], |row| {
+					Ok(SessionRecord { token: row.get(0)?, user_id: row.get(1)?, expires_at: row.get(2)? })
+				})
+				.optional()?)
+		}).await
+	}
+
+	async fn delete_session(&self, token: &str) -> Result<bool, StoreError> {
+		let token = token.to_string();
+		self.run(move |connection| Ok(connection.execute("DELETE FROM sessions WHERE token = ?1", params![token])? > 0)).await
+	}
+
+	async fn update_game(&self, game: &Game) -> Result<bool, StoreError> {
+		let id = match game.id.as_deref().and_then(|id| id.parse::<i64>().ok()) {
+			Some(id) => id,
+			None => return Ok(false),
+		};
+		let game = game.clone();
+		self.run(move |connection| {
+			let updated = connection.execute(
+				"UPDATE games SET gametype = ?1, player1 = ?2, player2 = ?3, winner = ?4, started_at = ?5, ended_at = ?6,
+					duration_secs = ?7, move_count = ?8, difficulty = ?9, variant = ?10, first_mover = ?11,
+					player1_word = ?12, moves = ?13, player1_id = ?14, player2_id = ?15, voided = ?16
+				WHERE id = ?17",
+				params![
+					game.gametype, game.player1, game.player2, game.winner,
+					game.started_at, game.ended_at, game.duration_secs, game.move_count,
+					game.difficulty.map(|level| level as i64), game.variant, game.first_mover,
+					game.player1_word, game.moves.join(" "), game.player1_id, game.player2_id, game.voided, id,
+				],
+			)?;
+			Ok(updated > 0)
+		}).await
+	}
+
+	async fn rename_player(&self, from: &str, into: &str, into_id: Option<&str>) -> Result<usize, StoreError> {
+		let (from, into, into_id) = (from.to_string(), into.to_string(), into_id.map(str::to_string));
+		self.run(move |connection| {
+			let transaction = connection.transaction()?;
+			// the winner and first mover first, while the guest seats still say who they are
+			let guest = "((player1 = ?1 AND player1_id IS NULL) OR (player2 = ?1 AND player2_id IS NULL))";
+			transaction.execute(&format!("UPDATE games SET winner = ?2 WHERE winner = ?1 AND {}", guest), params![from, into])?;
+			transaction.execute(&format!("UPDATE games SET first_mover = ?2 WHERE first_mover = ?1 AND {}", guest), params![from, into])?;
+			let count = transaction.execute(
+				"UPDATE games SET player1 = ?2, player1_id = ?3 WHERE player1 = ?1 AND player1_id IS NULL",
+				params![from, into, into_id],
+			)? + transaction.execute(
+				"UPDATE games SET player2 = ?2, player2_id = ?3 WHERE player2 = ?1 AND player2_id IS NULL",
+				params![from, into, into_id],
+			)?;
+			transaction.commit()?;
+			Ok(count)
+		}).await
+	}
+
+	async fn clear_ratings(&self) -> Result<(), StoreError> {
+		self.run(|connection| {
+			connection.execute("DELETE FROM ratings", [])?;
+			Ok(())
+		}).await
+	}
+
+	async fn delete_ratings(&self, game_ids: &[String]) -> Result<(), StoreError> {
+		let game_ids = game_ids.to_vec();
+		self.run(move |connection| {
+			let transaction = connection.transaction()?;
+			for id in &game_ids {
+				transaction.execute("DELETE FROM ratings WHERE game_id = ?1", params![id])?;
+			}
+			transaction.commit()?;
+			Ok(())
+		}).await
+	}
+
+	async fn update_account(&self, account: &Account) -> Result<bool, StoreError> {
+		let account = account.clone();
+		self.run(move |connection| {
+			let updated = connection.execute(
+				"UPDATE accounts SET name = ?1, key = ?2, password_hash = ?3, role = ?4, banned = ?5 WHERE id = ?6",
+				params![account.name, account.key, account.password_hash, role_name(account.role), account.banned, account.id],
+			)?;
+			Ok(updated > 0)
+		}).await
+	}
+
+	async fn delete_sessions(&self, user_id: &str) -> Result<usize, StoreError> {
+		let user_id = user_id.to_string();
+		self.run(move |connection| Ok(connection.execute("DELETE FROM sessions WHERE user_id = ?1", params![user_id])?)).await
+	}
+
+	// Tables from older versions lack some columns. Ratings from before they
+	// were kept by account are dropped, so the games are rated again at
+	// startup. Also makes sure the indexes exist.
+	async fn migrate(&self) -> Result<usize, StoreError> {
+		self.run(|connection| {
+			add_account_columns(connection)?;
+			add_rating_account(connection)?;
+			let columns = {
+				let mut statement = connection.prepare("SELECT name FROM pragma_table_info('games')")?;
+				let names = statement.query_map([], |row| row.get::<_, String>(0))?;
+				names.collect::<Result<Vec<_>, _>>()?
+			};
+			let missing: Vec<_> = ADDED_COLUMNS.iter().filter(|(column, _)| !columns.iter().any(|existing| existing == column)).collect();
+			let mut count = 0;
+			if !missing.is_empty() {
+				let transaction = connection.transaction()?;
+				for (column, kind) in missing {
+					transaction.execute(&format!("ALTER TABLE games ADD COLUMN {} {}", column, kind), [])?;
+				}
+				count = transaction.query_row("SELECT COUNT(*) FROM games", [], |row| row.get(0))?;
+				transaction.commit()?;
+			}
+			create_indexes(connection)?;
+			Ok(count)
+		}).await
+	}
+}
+
+fn add_account_columns(connection: &Connection) -> rusqlite::Result<()> {
+	let columns = {
+		let mut statement = connection.prepare("SELECT name FROM pragma_table_info('accounts')")?;
+		let names = statement.query_map([], |row| row.get::<_, String>(0))?;
+		names.collect::<Result<Vec<_>, _>>()?
+	};
+	for (column, kind) in ADDED_ACCOUNT_COLUMNS {
+		if !columns.iter().any(|existing| existing == column) {
+			connection.execute(&format!("ALTER TABLE accounts ADD COLUMN {} {}", column, kind), [])?;
+		}
+	}
+	Ok(())
+}
+
+fn add_rating_account(connection: &Connection) -> rusqlite::Result<()> {
+	let has_account = connection.query_row(
+		"SELECT COUNT(*) FROM pragma_table_info('ratings') WHERE name = 'player_id'",
+		[],
+		|row| row.get::<_, i64>(0),
+	)? > 0;
+	if !has_account {
+		connection.execute_batch("ALTER TABLE ratings ADD COLUMN player_id TEXT; DELETE FROM ratings;")?;
+	}
+	Ok(())
+}
+
+// The condition on the ratings table for one player and the value it takes
+fn rated_player(player: &PlayerKey) -> (&'static str, String) {
+	match player {
+		PlayerKey::Account(id) => ("player_id = ?2", id.clone()),
+		PlayerKey::Guest(name) => ("player = ?2 AND player_id IS NULL", name.clone()),
+	}
+}
+
+// For the filters and the (end time, id) order of `find_games`, and for
+// looking up a player's latest rating
+fn create_indexes(connection: &Connection) -> rusqlite::Result<()> {
+	connection.execute_batch(
+		"CREATE INDEX IF NOT EXISTS games_ended ON games (ended_at, id);
+		CREATE INDEX IF NOT EXISTS games_gametype ON games (gametype, ended_at);
+		CREATE INDEX IF NOT EXISTS games_player1 ON games (player1, ended_at);
+		CREATE INDEX IF NOT EXISTS games_player2 ON games (player2, ended_at);
+		CREATE INDEX IF NOT EXISTS games_player1_id ON games (player1_id, ended_at);
+		CREATE INDEX IF NOT EXISTS games_player2_id ON games (player2_id, ended_at);
+		CREATE INDEX IF NOT EXISTS games_winner ON games (winner);
+		CREATE INDEX IF NOT EXISTS ratings_player ON ratings (gametype, player, id);
+		CREATE INDEX IF NOT EXISTS ratings_account ON ratings (gametype, player_id, id);
+		CREATE INDEX IF NOT EXISTS ratings_game ON ratings (game_id);",
+	)
+}
+
+fn row_to_game(row: &Row) -> rusqlite::Result<Game> {
+	Ok(Game{
+		id: Some(row.get::<_, i64>(0)?.to_string()),
+		gametype: row.get(1)?,
+		player1: row.get(2)?,
+		player2: row.get(3)?,
+		winner: row.get(4)?,
+		started_at: row.get(5)?,
+		ended_at: row.get(6)?,
+		duration_secs: row.get(7)?,
+		move_count: row.get(8)?,
+		difficulty: row.get::<_, Option<i64>>(9)?.map(|level| level as usize),
+		variant: row.get(10)?,
+		first_mover: row.get(11)?,
+		player1_word: row.get(12)?,
+		moves: row.get::<_, String>(13)?.split_whitespace().map(|mv| mv.to_string()).collect(),
+		player1_id: row.get(14)?,
+		player2_id: row.get(15)?,
+		voided: row.get(16)?,
+	})
+}
+
+fn row_to_rating(row: &Row) -> rusqlite::Result<RatingEntry> {
+	Ok(RatingEntry {
+		player: row.get(0)?,
+		gametype: row.get(1)?,
+		game_id: row.get(2)?,
+		rating: row.get(3)?,
+		deviation: row.get(4)?,
+		volatility: row.get(5)?,
+		games: row.get(6)?,
+		at: row.get(7)?,
+		player_id: row.get(8)?,
+	})
+}
+
+fn row_to_account(row: &Row) -> rusqlite::Result<Account> {
+	Ok(Account {
+		id: Some(row.get::<_, i64>(0)?.to_string()),
+		name: row.get(1)?,
+		key: row.get(2)?,
+		password_hash: row.get(3)?,
+		created_at: row.get(4)?,
+		role: if row.get::<_, String>(5)? == "admin" { Role::Admin } else { Role::Player },
+		banned: row.get(6)?,
+	})
+}
+
+fn role_name(role: Role) -> &'static str {
+	match role {
+		Role::Player => "player",
+		Role::Admin => "admin",
+	}
+}
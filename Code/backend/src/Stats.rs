@@ -0,0 +1,220 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::prelude::*;
+use chrono::Duration;
+use common::Backend::{DifficultyRecord, Game, GameResult, LeaderboardEntry, PlayerStats, Record, Streak};
+
+use crate::Accounts::name_key;
+use crate::Query::{GameFilter, GameQuery, PlayerKey, COMPUTER, MAX_LIMIT};
+use crate::Storage::{GameStore, StoreError};
+
+// Query string of `GET /leaderboard` and `GET /players/<name>/stats`
+#[derive(FromForm, Default, Debug)]
+pub struct StatsQuery {
+	pub gametype: Option<String>,
+	// "all" (the default), or "day", "week", "month" or "year" back from now
+	pub period: Option<String>,
+}
+
+// Games, wins and draws of one player against one kind of opponent at one
+// difficulty. Each game counts once for each seat.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tally {
+	pub player: String,
+	pub player_id: Option<String>,
+	pub computer: bool,
+	pub difficulty: Option<usize>,
+	pub games: u32,
+	pub wins: u32,
+	pub draws: u32,
+}
+
+impl Tally {
+	pub fn key(&self) -> PlayerKey {
+		PlayerKey::new(&self.player, self.player_id.as_deref())
+	}
+}
+
+impl StatsQuery {
+	pub fn filter(self, player: Option<&PlayerKey>) -> Result<GameFilter, String> {
+		let days = match self.period.as_deref() {
+			None | Some("all") => None,
+			Some("day") => Some(1),
+			Some("week") => Some(7),
+			Some("month") => Some(30),
+			Some("year") => Some(365),
+			Some(other) => return Err(format!("`period` must be all, day, week, month or year, not {:?}", other)),
+		};
+		let mut filter = GameQuery {
+			gametype: self.gametype,
+			from: days.map(|days| (Utc::now() - Duration::days(days)).to_rfc3339_opts(SecondsFormat::Secs, true)),
+			..Default::default()
+		}
+		.validate()?;
+		match player {
+			Some(PlayerKey::Account(id)) => filter.player_id = Some(id.clone()),
+			Some(PlayerKey::Guest(name)) => filter.player = Some(name.clone()),
+			None => {}
+		}
+		Ok(filter)
+	}
+}
+
+// games, wins and draws
+type Counts = (u32, u32, u32);
+
+// The tally for stores that keep their games in memory
+pub fn tally_games<'a>(games: impl Iterator<Item = &'a Game>) -> Vec<Tally> {
+	let mut counts: BTreeMap<(String, Option<String>, bool, Option<usize>), Counts> = BTreeMap::new();
+	for game in games {
+		let seats = [(&game.player1, &game.player1_id, &game.player2), (&game.player2, &game.player2_id, &game.player1)];
+		for (player, player_id, opponent) in seats {
+			let count = counts.entry((player.clone(), player_id.clone(), opponent == COMPUTER, game.difficulty)).or_default();
+			count.0 += 1;
+			if game.winner == *player {
+				count.1 += 1;
+			} else if game.winner.is_empty() {
+				count.2 += 1;
+			}
+		}
+	}
+	counts
+		.into_iter()
+		.map(|((player, player_id, computer, difficulty), (games, wins, draws))| Tally { player, player_id, computer, difficulty, games, wins, draws })
+		.collect()
+}
+
+fn add(total: &mut Counts, tally: &Tally) {
+	total.0 += tally.games;
+	total.1 += tally.wins;
+	total.2 += tally.draws;
+}
+
+// How many games of one type count, voided ones left out
+pub async fn games_recorded(db: &dyn GameStore, gametype: &str) -> Result<usize, StoreError> {
+	let filter = GameQuery { gametype: Some(gametype.to_string()), ..Default::default() }.validate()?;
+	let seats: u32 = db.tally(&filter).await?.iter().map(|tally| tally.games).sum();
+	Ok(seats as usize / 2)
+}
+
+// Whose games a name stands for: the account with that name if there is
+// one, the guests who played under it otherwise
+pub async fn player_key(db: &dyn GameStore, name: &str) -> Result<PlayerKey, StoreError> {
+	Ok(match db.find_account(&name_key(name)).await?.and_then(|account| account.id) {
+		Some(id) => PlayerKey::Account(id),
+		None => PlayerKey::Guest(name.to_string()),
+	})
+}
+
+// Human players ranked by rating when the filter has a game type, then by
+// wins, win rate and games played. An account is one player whatever its
+// games call it, and guests are told apart from accounts of the same name.
+pub async fn leaderboard(db: &dyn GameStore, filter: &GameFilter) -> Result<Vec<LeaderboardEntry>, StoreError> {
+	let mut totals: BTreeMap<PlayerKey, (String, Counts)> = BTreeMap::new();
+	for tally in db.tally(filter).await? {
+		if tally.player != COMPUTER {
+			let (name, counts) = totals.entry(tally.key()).or_default();
+			name.clone_from(&tally.player);
+			add(counts, &tally);
+		}
+	}
+	let ratings: HashMap<PlayerKey, f64> = match &filter.gametype {
+		Some(gametype) => db.current_ratings(gametype).await?
+			.into_iter()
+			.map(|entry| (PlayerKey::new(&entry.player, entry.player_id.as_deref()), entry.rating))
+			.collect(),
+		None => HashMap::new(),
+	};
+	let mut entries: Vec<LeaderboardEntry> = totals
+		.into_iter()
+		.map(|(key, (player, (games, wins, draws)))| LeaderboardEntry {
+			rank: 0,
+			rating: ratings.get(&key).copied(),
+			player,
+			player_id: match key {
+				PlayerKey::Account(id) => Some(id),
+				PlayerKey::Guest(_) => None,
+			},
+			record: Record::new(games, wins, draws),
+		})
+		.collect();
+	entries.sort_by(|a, b| {
+		b.rating.unwrap_or(f64::MIN).total_cmp(&a.rating.unwrap_or(f64::MIN))
+			.then(b.record.wins.cmp(&a.record.wins))
+			.then(b.record.win_rate.total_cmp(&a.record.win_rate))
+			.then(b.record.games.cmp(&a.record.games))
+			.then(a.player.cmp(&b.player))
+	});
+	for (i, entry) in entries.iter_mut().enumerate() {
+		entry.rank = i as u32 + 1;
+	}
+	Ok(entries)
+}
+
+fn result_for(game: &Game, player: &str) -> GameResult {
+	if game.winner == player {
+		GameResult::Win
+	} else if game.winner.is_empty() {
+		GameResult::Draw
+	} else {
+		GameResult::Loss
+	}
+}
+
+// `filter` should be limited to the player's games. A player without games
+// in it gets all zeros rather than an error, since the period may be short.
+pub async fn player_stats(db: &dyn GameStore, name: &str, player: &PlayerKey, filter: &GameFilter) -> Result<PlayerStats, StoreError> {
+	let mut total = (0, 0, 0);
+	let mut human = (0, 0, 0);
+	let mut computer: BTreeMap<Option<usize>, Counts> = BTreeMap::new();
+	for tally in db.tally(filter).await?.iter().filter(|tally| tally.key() == *player) {
+		add(&mut total, tally);
+		if tally.computer {
+			add(computer.entry(tally.difficulty).or_default(), tally);
+		} else {
+			add(&mut human, tally);
+		}
+	}
+
+	// streaks need the results in order, so walk the games oldest first
+	let mut current: Option<Streak> = None;
+	let (mut longest_win, mut longest_loss) = (0, 0);
+	let mut pages = GameFilter { newest_first: false, after: None, limit: MAX_LIMIT, ..filter.clone() };
+	loop {
+		let page = pages.page(db.find_games(&pages).await?);
+		// a guest's name also finds the games of an account with that name
+		for (game, seat) in page.games.iter().filter_map(|game| Some((game, player.seat(game)?))) {
+			let result = result_for(game, seat);
+			match &mut current {
+				Some(streak) if streak.result == result => streak.length += 1,
+				_ => current = Some(Streak { result, length: 1 }),
+			}
+			if let Some(streak) = &current {
+				match streak.result {
+					GameResult::Win => longest_win = longest_win.max(streak.length),
+					GameResult::Loss => longest_loss = longest_loss.max(streak.length),
+					GameResult::Draw => {}
+				}
+			}
+		}
+		match (page.next_cursor, page.games.last()) {
+			(Some(_), Some(last)) => {
+				pages.after = Some((last.ended_at.clone().unwrap_or_default(), last.id.clone().unwrap_or_default()));
+			}
+			_ => break,
+		}
+	}
+
+	Ok(PlayerStats {
+		player: name.to_string(),
+		record: Record::new(total.0, total.1, total.2),
+		vs_human: Record::new(human.0, human.1, human.2),
+		vs_computer: computer
+			.into_iter()
+			.map(|(difficulty, (games, wins, draws))| DifficultyRecord { difficulty, record: Record::new(games, wins, draws) })
+			.collect(),
+		current_streak: current,
+		longest_win_streak: longest_win,
+		longest_loss_streak: longest_loss,
+	})
+}
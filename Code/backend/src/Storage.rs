@@ -0,0 +1,531 @@
+use std::error::Error;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use chrono::prelude::*;
+use common::Backend::{Game, RatingEntry};
+
+use crate::Accounts::{Account, NameTaken, SessionRecord};
+use crate::Query::{GameFilter, InvalidCursor, PlayerKey};
+use crate::Ratings;
+use crate::Stats::{tally_games, Tally};
+
+// Errors out of a store; Send, so async handlers can hold them across awaits
+pub type StoreError = Box<dyn Error + Send + Sync>;
+
+// Where finished games are kept. The Mongo store is what runs in production,
+// the memory and SQLite stores let the server run without a mongod.
+#[rocket::async_trait]
+pub trait GameStore: Send + Sync {
+	// store a finished game and return it with its new id
+	async fn insert_game(&self, game: Game) -> Result<Game, StoreError>;
+	// games matching the filter in its order, up to one more than its limit
+	async fn find_games(&self, filter: &GameFilter) -> Result<Vec<Game>, StoreError>;
+	// games, wins and draws per player, opponent kind and difficulty over the
+	// games the filter matches, ignoring its cursor and limit
+	async fn tally(&self, filter: &GameFilter) -> Result<Vec<Tally>, StoreError>;
+	async fn get_game(&self, id: &str) -> Result<Option<Game>, StoreError>;
+	// false if there was no game with this id
+	async fn delete_game(&self, id: &str) -> Result<bool, StoreError>;
+	// the latest rating entry of each player of a game type, accounts and
+	// guests told apart as by `PlayerKey`
+	async fn current_ratings(&self, gametype: &str) -> Result<Vec<RatingEntry>, StoreError>;
+	async fn latest_rating(&self, gametype: &str, player: &PlayerKey) -> Result<Option<RatingEntry>, StoreError>;
+	// a player's rating entries for a game type, oldest first
+	async fn rating_history(&self, gametype: &str, player: &PlayerKey) -> Result<Vec<RatingEntry>, StoreError>;
+	async fn insert_ratings(&self, entries: &[RatingEntry]) -> Result<(), StoreError>;
+	// store a new account and return it with its id, fails with `NameTaken`
+	// if another account has the same key
+	async fn insert_account(&self, account: Account) -> Result<Account, StoreError>;
+	async fn find_account(&self, key: &str) -> Result<Option<Account>, StoreError>;
+	async fn get_account(&self, id: &str) -> Result<Option<Account>, StoreError>;
+	async fn insert_session(&self, session: SessionRecord) -> Result<(), StoreError>;
+	async fn find_session(&self, token: &str) -> Result<Option<SessionRecord>, StoreError>;
+	async fn delete_session(&self, token: &str) -> Result<bool, StoreError>;
+	// replace the stored game with the same id, false if there is none
+	async fn update_game(&self, game: &Game) -> Result<bool, StoreError>;
+	// give every seat of the guest `from` to `into`, and to the account
+	// `into_id` when there is one; seats of accounts are left as they are.
+	// Returns how many games changed.
+	async fn rename_player(&self, from: &str, into: &str, into_id: Option<&str>) -> Result<usize, StoreError>;
+	// forget every rating entry, before rating all games again
+	async fn clear_ratings(&self) -> Result<(), StoreError>;
+	// forget the rating entries of these games, before rating them again
+	async fn delete_ratings(&self, game_ids: &[String]) -> Result<(), StoreError>;
+	// replace the stored account with the same id, false if there is none
+	async fn update_account(&self, account: &Account) -> Result<bool, StoreError>;
+	// log a user out everywhere, returns how many sessions ended
+	async fn delete_sessions(&self, user_id: &str) -> Result<usize, StoreError>;
+	// check the database answers; stores without a server always do
+	async fn ping(&self) -> Result<(), StoreError> {
+		Ok(())
+	}
+	// bring records written by older versions up to date, returns how many changed
+	async fn migrate(&self) -> Result<usize, StoreError> {
+		Ok(0)
+	}
+}
+
+// Stamp a game the client says has just ended. The end time comes from the
+// server clock; a start time that does not parse or lies in the future is dropped.
+pub fn finish_game(mut game: Game) -> Game {
+	let now = Utc::now();
+	let started = game.started_at.as_deref()
+		.and_then(|started| DateTime::parse_from_rfc3339(started).ok())
+		.map(|started| started.with_timezone(&Utc))
+		.filter(|started| *started <= now);
+	game.id = None;
+	game.started_at = started.map(|started| started.to_rfc3339_opts(SecondsFormat::Secs, true));
+	game.ended_at = Some(now.to_rfc3339_opts(SecondsFormat::Secs, true));
+	game.duration_secs = started.map(|started| (now - started).num_seconds());
+	game
+}
+
+// Check a finished game by replaying its moves, then stamp, store and rate
+// it. The game counts once stored, so a rating that fails is only logged.
+pub async fn store_game(db: &dyn GameStore, game: Game) -> Result<Game, StoreError> {
+	game.check()?;
+	let writing = Ratings::writing().await;
+	let game = db.insert_game(finish_game(game)).await?;
+	if let Err(err) = Ratings::rate_game(db, &game, &writing).await {
+		error!("cannot rate game {}: {}", game.id.as_deref().unwrap_or(""), err);
+	}
+	Ok(game)
+}
+
+// Older records only have a display string like "03:15PM on Apr 02, 2023",
+// written from the server clock in UTC
+pub fn legacy_date(date: &str) -> Option<String> {
+	NaiveDateTime::parse_from_str(date, "%I:%M%p on %b %d, %Y")
+		.ok()
+		.map(|date| Utc.from_utc_datetime(&date).to_rfc3339_opts(SecondsFormat::Secs, true))
+}
+
+// Keeps games for the life of the process, for local runs and tests
+#[derive(Default)]
+pub struct MemoryStore {
+	games: Mutex<Vec<Game>>,
+	next_id: Mutex<u64>,
+	// every rating entry in the order written
+	ratings: Mutex<Vec<RatingEntry>>,
+	accounts: Mutex<Vec<Account>>,
+	sessions: Mutex<HashMap<String, SessionRecord>>,
+}
+
+impl MemoryStore {
+	pub fn new() -> Self {
+		Self::default()
+	}
+}
+
+fn rated(entry: &RatingEntry) -> PlayerKey {
+	PlayerKey::new(&entry.player, entry.player_id.as_deref())
+}
+
+#[rocket::async_trait]
+impl GameStore for MemoryStore {
+	async fn insert_game(&self, mut game: Game) -> Result<Game, StoreError> {
+		let mut next_id = self.next_id.lock().map_err(|err| err.to_string())?;
+		*next_id += 1;
+		game.id = Some(next_id.to_string());
+		self.games.lock().map_err(|err| err.to_string())?.push(game.clone());
+		Ok(game)
+	}
+
+	async fn find_games(&self, filter: &GameFilter) -> Result<Vec<Game>, StoreError> {
+		// ids here are counters, so they order like numbers
+		let key = |game: &Game| (game.ended_at.clone().unwrap_or_default(), game.id.as_deref().and_then(|id| id.parse::<u64>().ok()).unwrap_or(0));
+		let after = match &filter.after {
+			Some((ended_at, id)) => Some((ended_at.clone(), id.parse::<u64>().map_err(|_| InvalidCursor)?)),
+			None => None,
+		};
+		let mut games: Vec<Game> = self.games.lock().map_err(|err| err.to_string())?
+			.iter()
+			.filter(|game| filter.matches(game))
+			.filter(|game| match &after {
+				Some(after) if filter.newest_first => key(game) < *after,
+				Some(after) => key(game) > *after,
+				None => true,
+			})
+			.cloned()
+			.collect();
+		games.sort_by_key(key);
+		if filter.newest_first {
+			games.reverse();
+		}
+		games.truncate(filter.limit + 1);
+		Ok(games)
+	}
+
+	async fn tally(&self, filter: &GameFilter) -> Result<Vec<Tally>, StoreError> {
+		let games = self.games.lock().map_err(|err| err.to_string())?;
+		Ok(tally_games(games.iter().filter(|game| filter.matches(game))))
+	}
+
+	async fn current_ratings(&self, gametype: &str) -> Result<Vec<RatingEntry>, StoreError> {
+		let ratings = self.ratings.lock().map_err(|err| err.to_string())?;
+		let mut current: Vec<RatingEntry> = Vec::new();
+		for entry in ratings.iter().rev().filter(|entry| entry.gametype == gametype) {
+			if !current.iter().any(|seen| rated(seen) == rated(entry)) {
+				current.push(entry.clone());
+			}
+		}
+		Ok(current)
+	}
+
+	async fn latest_rating(&self, gametype: &str, player: &PlayerKey) -> Result<Option<RatingEntry>, StoreError> {
+		let ratings = self.ratings.lock().map_err(|err| err.to_string())?;
+		Ok(ratings.iter().rev().find(|entry| entry.gametype == gametype && rated(entry) == *player).cloned())
+	}
+
+	async fn rating_history(&self, gametype: &str, player: &PlayerKey) -> Result<Vec<RatingEntry>, StoreError> {
+		let ratings = self.ratings.lock().map_err(|err| err.to_string())?;
+		Ok(ratings.iter().filter(|entry| entry.gametype == gametype && rated(entry) == *player).cloned().collect())
+	}
+
+	async fn insert_ratings(&self, entries: &[RatingEntry]) -> Result<(), StoreError> {
+		self.ratings.lock().map_err(|err| err.to_string())?.extend_from_slice(entries);
+		Ok(())
+	}
+
+	async fn insert_account(&self, mut account: Account) -> Result<Account, StoreError> {
+		let mut accounts = self.accounts.lock().map_err(|err| err.to_string())?;
+		if accounts.iter().any(|existing| existing.key == account.key) {
+			return Err(NameTaken.into());
+		}
+		account.id = Some((accounts.len() + 1).to_string());
+		accounts.push(account.clone());
+		Ok(account)
+	}
+
+	async fn find_account(&self, key: &str) -> Result<Option<Account>, StoreError> {
+		let accounts = self.accounts.lock().map_err(|err| err.to_string())?;
+		Ok(accounts.iter().find(|account| account.key == key).cloned())
+	}
+
+	async fn get_account(&self, id: &str) -> Result<Option<Account>, StoreError> {
+		let accounts = self.accounts.lock().map_err(|err| err.to_string())?;
+		Ok(accounts.iter().find(|account| account.id.as_deref() == Some(id)).cloned())
+	}
+
+	async fn insert_session(&self, session: SessionRecord) -> Result<(), StoreError> {
+		self.sessions.lock().map_err(|err| err.to_string())?.insert(session.token.clone(), session);
+		Ok(())
+	}
+
+	async fn find_session(&self, token: &str) -> Result<Option<SessionRecord>, StoreError> {
+		Ok(self.sessions.lock().map_err(|err| err.to_string())?.get(token).cloned())
+	}
+
+	async fn delete_session(&self, token: &str) -> Result<bool, StoreError> {
+		Ok(self.sessions.lock().map_err(|err| err.to_string())?.remove(token).is_some())
+	}
+
+	async fn get_game(&self, id: &str) -> Result<Option<Game>, StoreError> {
+		let games = self.games.lock().map_err(|err| err.to_string())?;
+		Ok(games.iter().find(|game| game.id.as_deref() == Some(id)).cloned())
+	}
+
+	async fn delete_game(&self, id: &str) -> Result<bool, StoreError> {
+		let mut games = self.games.lock().map_err(|err| err.to_string())?;
+		let count = games.len();
+		games.retain(|game| game.id.as_deref() != Some(id));
+		Ok(games.len() < count)
+	}
+
+	async fn update_game(&self, game: &Game) -> Result<bool, StoreError> {
+		let mut games = self.games.lock().map_err(|err| err.to_string())?;
+		match games.iter_mut().find(|stored| stored.id == game.id) {
+			Some(stored) => {
+				*stored = game.clone();
+				Ok(true)
+			}
+			None => Ok(false),
+		}
+	}
+
+	async fn rename_player(&self, from: &str, into: &str, into_id: Option<&str>) -> Result<usize, StoreError> {
+		let mut games = self.games.lock().map_err(|err| err.to_string())?;
+		let mut count = 0;
+		let guest = PlayerKey::Guest(from.to_string());
+		for game in games.iter_mut().filter(|game| guest.seat(game).is_some()) {
+			for (name, id) in [(&mut game.player1, &mut game.player1_id), (&mut game.player2, &mut game.player2_id)] {
+				if *name == from && id.is_none() {
+					*name = into.to_string();
+					if let Some(into_id) = into_id {
+						*id = Some(into_id.to_string());
+					}
+				}
+			}
+			if game.winner == from {
+				game.winner = into.to_string();
+			}
+			if game.first_mover.as_deref() == Some(from) {
+				game.first_mover = Some(into.to_string());
+			}
+			count += 1;
+		}
+		Ok(count)
+	}
+
+	async fn clear_ratings(&self) -> Result<(), StoreError> {
+		self.ratings.lock().map_err(|err| err.to_string())?.clear();
+		Ok(())
+	}
+
+	async fn delete_ratings(&self, game_ids: &[String]) -> Result<(), StoreError> {
+		let mut ratings = self.ratings.lock().map_err(|err| err.to_string())?;
+		ratings.retain(|entry| entry.game_id.as_ref().is_none_or(|id| !game_ids.contains(id)));
+		Ok(())
+	}
+
+	async fn update_account(&self, account: &Account) -> Result<bool, StoreError> {
+		let mut accounts = self.accounts.lock().map_err(|err| err.to_string())?;
+		match accounts.iter_mut().find(|stored| stored.id == account.id) {
+			Some(stored) => {
+				*stored = account.clone();
+				Ok(true)
+			}
+			None => Ok(false),
+		}
+	}
+
+	async fn delete_sessions(&self, user_id: &str) -> Result<usize, StoreError> {
+		let mut sessions = self.sessions.lock().map_err(|err| err.to_string())?;
+		let count = sessions.len();
+		sessions.retain(|_, session| session.user_id != user_id);
+		Ok(count - sessions.len())
+	}
+}
+
+// Records the tests of every module build on
+#[cfg(test)]
+pub mod fixtures {
+	use common::Backend::{standard_variant, Game, RatingEntry};
+
+	use super::{GameStore, MemoryStore};
+	use crate::Accounts::Account;
+	use crate::Sqlite::SqliteStore;
+
+	// Every test runs against each store that needs no server
+	pub async fn stores() -> Vec<Box<dyn GameStore>> {
+		let sqlite = SqliteStore::new(":memory:").unwrap();
+		sqlite.migrate().await.unwrap();
+		vec![Box::new(MemoryStore::new()), Box::new(sqlite)]
+	}
+
+	// a finished game that ended at noon on that day of January 2024
+	pub fn game(gametype: &str, player1: &str, player2: &str, winner: &str, day: u32) -> Game {
+		Game {
+			id: None,
+			gametype: gametype.to_string(),
+			player1: player1.to_string(),
+			player2: player2.to_string(),
+			winner: winner.to_string(),
+			started_at: None,
+			ended_at: Some(format!("2024-01-{:02}T12:00:00Z", day)),
+			duration_secs: None,
+			move_count: None,
+			difficulty: None,
+			variant: standard_variant(),
+			first_mover: None,
+			player1_word: None,
+			moves: Vec::new(),
+			player1_id: None,
+			player2_id: None,
+			voided: false,
+		}
+	}
+
+	pub fn account(name: &str) -> Account {
+		Account {
+			id: None,
+			name: name.to_string(),
+			key: name.to_lowercase(),
+			password_hash: "hash".to_string(),
+			created_at: "2024-01-01T00:00:00Z".to_string(),
+			role: Default::default(),
+			banned: false,
+		}
+	}
+
+	pub fn rating(player: &str, rating: f64, games: u32) -> RatingEntry {
+		RatingEntry {
+			player: player.to_string(),
+			player_id: None,
+			gametype: "Connect4".to_string(),
+			game_id: None,
+			rating,
+			deviation: 350.0,
+			volatility: 0.06,
+			games,
+			at: format!("2024-01-{:02}T12:00:00Z", games),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::fixtures::{account, game, rating, stores};
+	use super::*;
+	use crate::Query::{GameQuery, COMPUTER};
+
+	fn filter(query: GameQuery) -> GameFilter {
+		query.validate().unwrap()
+	}
+
+	#[rocket::async_test]
+	async fn stores_and_deletes_games() {
+		for db in stores().await {
+			let stored = db.insert_game(game("Connect4", "ann", "bob", "ann", 1)).await.unwrap();
+			let id = stored.id.clone().unwrap();
+			let found = db.get_game(&id).await.unwrap().unwrap();
+			assert_eq!((found.player1.as_str(), found.winner.as_str(), found.ended_at), ("ann", "ann", stored.ended_at));
+			assert!(db.get_game("12345").await.unwrap().is_none());
+			assert!(db.delete_game(&id).await.unwrap());
+			assert!(!db.delete_game(&id).await.unwrap());
+			assert!(db.get_game(&id).await.unwrap().is_none());
+		}
+	}
+
+	#[rocket::async_test]
+	async fn filters_games() {
+		for db in stores().await {
+			db.insert_game(game("Connect4", "ann", "bob", "ann", 1)).await.unwrap();
+			db.insert_game(game("Connect4", "ann", COMPUTER, "", 2)).await.unwrap();
+			db.insert_game(game("TootOtto", "bob", "cat", "cat", 3)).await.unwrap();
+			let mut voided = game("Connect4", "ann", "cat", "cat", 4);
+			voided.voided = true;
+			db.insert_game(voided).await.unwrap();
+
+			let count = |query: GameQuery| {
+				let filter = filter(query);
+				let db = &db;
+				async move { db.find_games(&filter).await.unwrap().len() }
+			};
+			assert_eq!(count(GameQuery::default()).await, 3);
+			assert_eq!(count(GameQuery { gametype: Some("Connect4".to_string()), ..Default::default() }).await, 2);
+			assert_eq!(count(GameQuery { player: Some("bob".to_string()), ..Default::default() }).await, 2);
+			assert_eq!(count(GameQuery { winner: Some(String::new()), ..Default::default() }).await, 1);
+			assert_eq!(count(GameQuery { opponent: Some("computer".to_string()), ..Default::default() }).await, 1);
+			assert_eq!(count(GameQuery { from: Some("2024-01-02".to_string()), to: Some("2024-01-03".to_string()), ..Default::default() }).await, 2);
+			let mut all = filter(GameQuery::default());
+			all.voided = None;
+			assert_eq!(db.find_games(&all).await.unwrap().len(), 4);
+		}
+	}
+
+	#[rocket::async_test]
+	async fn pages_through_games_in_order() {
+		for db in stores().await {
+			for day in [3, 1, 5, 2, 4] {
+				db.insert_game(game("Connect4", "ann", "bob", "ann", day)).await.unwrap();
+			}
+			for (sort, days) in [("newest", [5, 4, 3, 2, 1]), ("oldest", [1, 2, 3, 4, 5])] {
+				let mut seen = Vec::new();
+				let mut cursor = None;
+				loop {
+					let query = GameQuery { sort: Some(sort.to_string()), limit: Some(2), cursor, ..Default::default() };
+					let filter = filter(query);
+					let page = filter.page(db.find_games(&filter).await.unwrap());
+					seen.extend(page.games.iter().map(|game| game.ended_at.clone().unwrap()));
+					match page.next_cursor {
+						Some(next) => cursor = Some(next),
+						None => break,
+					}
+				}
+				let expected: Vec<String> = days.iter().map(|day| format!("2024-01-{:02}T12:00:00Z", day)).collect();
+				assert_eq!(seen, expected);
+			}
+		}
+	}
+
+	#[rocket::async_test]
+	async fn tallies_both_seats() {
+		for db in stores().await {
+			db.insert_game(game("Connect4", "ann", "bob", "ann", 1)).await.unwrap();
+			db.insert_game(game("Connect4", "bob", "ann", "", 2)).await.unwrap();
+			// an account named ann is not the guest ann
+			db.insert_game(Game { player1_id: Some("7".to_string()), ..game("Connect4", "ann", "bob", "bob", 3) }).await.unwrap();
+			let mut tallies = db.tally(&filter(GameQuery::default())).await.unwrap();
+			tallies.sort_by_key(Tally::key);
+			let counts: Vec<_> = tallies.iter().map(|tally| (tally.player.as_str(), tally.player_id.as_deref(), tally.games, tally.wins, tally.draws)).collect();
+			assert_eq!(counts, [("ann", Some("7"), 1, 0, 0), ("ann", None, 2, 1, 1), ("bob", None, 3, 1, 1)]);
+		}
+	}
+
+	#[rocket::async_test]
+	async fn updates_and_renames_games() {
+		for db in stores().await {
+			let mut first = db.insert_game(game("Connect4", "ann", "bob", "ann", 1)).await.unwrap();
+			db.insert_game(game("Connect4", "cat", "ann", "cat", 2)).await.unwrap();
+			db.insert_game(game("Connect4", "bob", "cat", "bob", 3)).await.unwrap();
+			// the account that once had ann's name keeps its game
+			let account_game = db.insert_game(Game { player1_id: Some("8".to_string()), ..game("Connect4", "ann", "cat", "ann", 4) }).await.unwrap();
+			first.voided = true;
+			assert!(db.update_game(&first).await.unwrap());
+			assert!(db.get_game(first.id.as_deref().unwrap()).await.unwrap().unwrap().voided);
+			assert_eq!(db.rename_player("ann", "anne", None).await.unwrap(), 2);
+			let mut all = filter(GameQuery { player: Some("anne".to_string()), ..Default::default() });
+			all.voided = None;
+			let games = db.find_games(&all).await.unwrap();
+			assert_eq!(games.len(), 2);
+			assert!(games.iter().any(|game| game.winner == "anne"));
+			assert!(games.iter().all(|game| game.player1_id.is_none() && game.player2_id.is_none()));
+			let kept = db.get_game(account_game.id.as_deref().unwrap()).await.unwrap().unwrap();
+			assert_eq!((kept.player1.as_str(), kept.player1_id.as_deref(), kept.winner.as_str()), ("ann", Some("8"), "ann"));
+
+			// merged into an account, the games join it
+			assert_eq!(db.rename_player("bob", "robert", Some("7")).await.unwrap(), 2);
+			let mut account = filter(GameQuery::default());
+			account.player_id = Some("7".to_string());
+			account.voided = None;
+			let games = db.find_games(&account).await.unwrap();
+			assert_eq!(games.len(), 2);
+			assert!(games.iter().all(|game| PlayerKey::Account("7".to_string()).seat(game) == Some("robert")));
+		}
+	}
+
+	#[rocket::async_test]
+	async fn keeps_accounts_and_sessions() {
+		for db in stores().await {
+			let ann = db.insert_account(account("Ann")).await.unwrap();
+			let id = ann.id.clone().unwrap();
+			assert!(db.insert_account(account("ANN")).await.unwrap_err().is::<NameTaken>());
+			assert_eq!(db.find_account("ann").await.unwrap().unwrap().id.as_deref(), Some(id.as_str()));
+			let mut banned = db.get_account(&id).await.unwrap().unwrap();
+			banned.banned = true;
+			assert!(db.update_account(&banned).await.unwrap());
+			assert!(db.get_account(&id).await.unwrap().unwrap().banned);
+
+			for token in ["one", "two"] {
+				let session = SessionRecord { token: token.to_string(), user_id: id.clone(), expires_at: "2999-01-01T00:00:00Z".to_string() };
+				db.insert_session(session).await.unwrap();
+			}
+			assert_eq!(db.find_session("one").await.unwrap().unwrap().user_id, id);
+			assert!(db.delete_session("one").await.unwrap());
+			assert!(db.find_session("one").await.unwrap().is_none());
+			assert_eq!(db.delete_sessions(&id).await.unwrap(), 1);
+			assert!(db.find_session("two").await.unwrap().is_none());
+		}
+	}
+
+	#[rocket::async_test]
+	async fn keeps_rating_history() {
+		for db in stores().await {
+			db.insert_ratings(&[rating("ann", 1500.0, 1), rating("bob", 1400.0, 1)]).await.unwrap();
+			db.insert_ratings(&[rating("ann", 1550.0, 2)]).await.unwrap();
+			db.insert_ratings(&[RatingEntry { player_id: Some("7".to_string()), ..rating("ann", 1600.0, 1) }]).await.unwrap();
+			let mut current = db.current_ratings("Connect4").await.unwrap();
+			current.sort_by(|a, b| (&a.player, &a.player_id).cmp(&(&b.player, &b.player_id)));
+			assert_eq!(current.iter().map(|entry| entry.rating).collect::<Vec<_>>(), [1550.0, 1600.0, 1400.0]);
+			let guest = PlayerKey::Guest("ann".to_string());
+			assert_eq!(db.latest_rating("Connect4", &guest).await.unwrap().unwrap().games, 2);
+			assert_eq!(db.latest_rating("Connect4", &PlayerKey::Account("7".to_string())).await.unwrap().unwrap().rating, 1600.0);
+			let history = db.rating_history("Connect4", &guest).await.unwrap();
+			assert_eq!(history.iter().map(|entry| entry.games).collect::<Vec<_>>(), [1, 2]);
+			db.clear_ratings().await.unwrap();
+			assert!(db.current_ratings("Connect4").await.unwrap().is_empty());
+		}
+	}
+}
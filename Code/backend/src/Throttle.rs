@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+use std::time::Instant;
+
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+
+use crate::Accounts::request_user;
+use crate::Config::{AppConfig, RateLimit};
+
+// past this many buckets, the full ones are dropped; a full bucket is the
+// same as none at all
+const MAX_BUCKETS: usize = 10_000;
+
+struct Bucket {
+	limit: RateLimit,
+	tokens: f64,
+	updated_at: Instant,
+}
+
+impl Bucket {
+	fn new(limit: RateLimit, now: Instant) -> Self {
+		Bucket { limit, tokens: limit.burst as f64, updated_at: now }
+	}
+
+	fn refill(&mut self, now: Instant) {
+		let rate = self.limit.per_minute as f64 / 60.0;
+		let elapsed = now.duration_since(self.updated_at).as_secs_f64();
+		self.tokens = (self.tokens + elapsed * rate).min(self.limit.burst as f64);
+		self.updated_at = now;
+	}
+
+	fn is_full(&self) -> bool {
+		self.tokens >= self.limit.burst as f64
+	}
+
+	// whole seconds until the bucket has a token again
+	fn wait(&self) -> u64 {
+		let rate = self.limit.per_minute as f64 / 60.0;
+		if self.tokens >= 1.0 { 0 } else { ((1.0 - self.tokens) / rate).ceil() as u64 }
+	}
+}
+
+// seconds a turned away request should wait, cached on the request
+#[derive(Default)]
+struct Limited(Option<u64>);
+
+// Token bucket rate limits per route, client address and logged in user, as
+// `rate_limit` and `rate_limits` configure them; kept as managed state for
+// the `Throttled` guard
+#[derive(Default)]
+pub struct RateLimiter {
+	buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	// Take a token from each bucket, or from none if any is empty
+	fn take(&self, keys: &[String], limit: RateLimit) -> Result<(), u64> {
+		let now = Instant::now();
+		let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+		if buckets.len() > MAX_BUCKETS {
+			buckets.retain(|_, bucket| {
+				bucket.refill(now);
+				!bucket.is_full()
+			});
+		}
+		let mut wait = 0;
+		for key in keys {
+			let bucket = buckets.entry(key.clone()).or_insert_with(|| Bucket::new(limit, now));
+			bucket.refill(now);
+			wait = wait.max(bucket.wait());
+		}
+		if wait > 0 {
+			return Err(wait);
+		}
+		for key in keys {
+			if let Some(bucket) = buckets.get_mut(key) {
+				bucket.tokens -= 1.0;
+			}
+		}
+		Ok(())
+	}
+}
+
+// Request guard every route that writes takes first. Requests over the
+// limit fail it with a 429 before any other guard or the body is looked at,
+// and the catcher tells them how long to wait with `Retry-After`.
+pub struct Throttled;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Throttled {
+	type Error = String;
+
+	async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+		let (limiter, config) = match (request.rocket().state::<RateLimiter>(), request.rocket().state::<AppConfig>()) {
+			(Some(limiter), Some(config)) => (limiter, config),
+			_ => return Outcome::Success(Throttled),
+		};
+		let method = request.method().as_str();
+		let (route, limit) = match config.rate_limit_for(method, request.uri().path().as_str()) {
+			Some((route, limit)) => (route.to_string(), limit),
+			// every other route has buckets of its own, so a long game
+			// does not use up what deleting or importing may send
+			None => {
+				let path = request.route().map_or("", |route| route.uri.origin.path().as_str());
+				(format!("{} {}", method, path), config.rate_limit)
+			}
+		};
+		let mut keys = Vec::new();
+		if let Some(ip) = request.client_ip() {
+			keys.push(format!("{}|ip|{}", route, ip));
+		}
+		if let Ok(user) = request_user(request).await {
+			keys.push(format!("{}|user|{}", route, user.user.id));
+		}
+		match limiter.take(&keys, limit) {
+			Ok(()) => Outcome::Success(Throttled),
+			Err(wait) => {
+				request.local_cache(|| Limited(Some(wait)));
+				Outcome::Error((Status::TooManyRequests, format!("too many requests, try again in {} seconds", wait)))
+			}
+		}
+	}
+}
+
+// How long a request `Throttled` turned away should wait, in seconds
+pub fn retry_after(request: &Request<'_>) -> Option<u64> {
+	request.local_cache(Limited::default).0
+}
+
+#[cfg(test)]
+mod tests {
+	use rocket::local::asynchronous::Client;
+	use rocket::serde::json;
+
+	use super::*;
+
+	#[post("/matches/<_id>/moves")]
+	fn play(_limit: Throttled, _id: &str) {}
+
+	#[delete("/games/<_id>")]
+	fn delete(_limit: Throttled, _id: &str) {}
+
+	#[post("/users")]
+	fn register(_limit: Throttled) {}
+
+	// routes share a bucket only through `rate_limits`
+	#[rocket::async_test]
+	async fn keeps_a_bucket_per_route() {
+		let config: AppConfig = json::from_str(r#"{"rate_limit":{"burst":2,"per_minute":1},"rate_limits":{"POST /users":{"burst":1,"per_minute":1}}}"#).unwrap();
+		let rocket = rocket::build().manage(config).manage(RateLimiter::new()).mount("/", routes![play, delete, register]);
+		let client = Client::untracked(rocket).await.unwrap();
+		let status = |method: &str, path: &str| {
+			let request = match method {
+				"POST" => client.post(path.to_string()),
+				_ => client.delete(path.to_string()),
+			};
+			async move { request.remote("127.0.0.1:8000".parse().unwrap()).dispatch().await.status() }
+		};
+		assert_eq!(status("POST", "/matches/1/moves").await, Status::Ok);
+		assert_eq!(status("POST", "/matches/2/moves").await, Status::Ok);
+		assert_eq!(status("POST", "/matches/1/moves").await, Status::TooManyRequests);
+		assert_eq!(status("DELETE", "/games/1").await, Status::Ok);
+		assert_eq!(status("POST", "/users").await, Status::Ok);
+		assert_eq!(status("POST", "/users").await, Status::TooManyRequests);
+	}
+}
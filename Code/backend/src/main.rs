@@ -1,92 +1,467 @@
+#![allow(non_snake_case)]
 #[macro_use]
 extern crate rocket;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
 use rocket::{serde::json::Json, State};
 
-use mongodb::options::ClientOptions;
-use mongodb::sync::{Client, Database};
-use mongodb::bson::{doc, Document};
-use mongodb::results::InsertOneResult;
-use std::error::Error;
-use serde::{Deserialize, Serialize};
-use rocket::http::Header;
+use common::Backend::{AiMove, AiMoveRequest, Credentials, Game, GameEdit, GamePage, GamesChanged, Health, ImportReport, LeaderboardEntry, LiveMatch, MatchMove, MatchState, MergePlayers, NewMatch, PlayerStats, QueueRequest, QueueStatus, RatingEntry, ReplayFrame, Role, RoleChange, Room, Session, User};
+use common::Position::Position;
+use common::Render::{RenderOptions, MAX_CELL, MIN_CELL};
+use rocket::http::{ContentType, Header, Status};
+use rocket::response::status;
+use rocket::data::{Data, Limits, ToByteUnit};
+use rocket::response::stream::{ByteStream, Event, EventStream};
+use rocket::tokio::select;
+use rocket::tokio::sync::broadcast::error::RecvError;
+use rocket::Shutdown;
 use rocket::{Request, Response};
-use rocket::fairing::{Fairing, Info, Kind};
-use chrono::prelude::*;
-
-#[derive(Clone, Debug)]
-pub struct DB {
-	database: Database,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Game {
-	pub gametype: String,
-    pub player1: String,
-    pub player2: String,
-	pub winner: String,
-	pub date: String,
-}
-
-impl DB {
-	pub fn new() -> Result<Self, Box<dyn Error>>{
-		let client_options = ClientOptions::parse("mongodb://localhost:27017").unwrap();
-		let client = Client::with_options(client_options).unwrap();
-		let db = client.database("Project3");
-		Ok(Self {
-			database: db,
-		})
+use rocket::fairing::{AdHoc, Fairing, Info, Kind};
+
+mod Config;
+mod Storage;
+mod Mongo;
+mod Sqlite;
+mod Matches;
+mod Replay;
+mod Query;
+mod Stats;
+mod Ratings;
+mod Accounts;
+mod Lobby;
+mod Engine;
+mod Errors;
+mod Throttle;
+mod Export;
+mod Admin;
+mod Metrics;
+
+use Config::AppConfig;
+use Storage::{GameStore, MemoryStore, StoreError};
+use Mongo::DB;
+use Sqlite::SqliteStore;
+use Matches::MatchTable;
+use Query::{check_gametype, GameQuery, MAX_LIMIT};
+use Stats::StatsQuery;
+use Accounts::{AdminUser, AuthUser};
+use Lobby::LobbyTable;
+use Errors::ApiError;
+use Throttle::{RateLimiter, Throttled};
+use Export::{Download, Format};
+use Metrics::{Metrics as ServerMetrics, RequestMetrics, StoreMetrics};
+
+// One page of games, filtered and sorted as the query string asks
+#[get("/games?<query..>")]
+async fn get_games(query: GameQuery, db: &State<Arc<dyn GameStore>>) -> Result<Json<GamePage>, ApiError> {
+	let filter = query.validate().map_err(ApiError::bad_request)?;
+	Ok(Json(filter.page(db.find_games(&filter).await?)))
+}
+
+// Human players ranked over the games of one type and period
+#[get("/leaderboard?<query..>")]
+async fn get_leaderboard(query: StatsQuery, db: &State<Arc<dyn GameStore>>) -> Result<Json<Vec<LeaderboardEntry>>, ApiError> {
+	let filter = query.filter(None).map_err(ApiError::bad_request)?;
+	Stats::leaderboard(db.as_ref(), &filter)
+		.await
+		.map(Json)
+		.map_err(ApiError::from)
+}
+
+// The stats of the account with this name, or of the guests who played
+// under it when there is no such account
+#[get("/players/<name>/stats?<query..>")]
+async fn get_player_stats(name: &str, query: StatsQuery, db: &State<Arc<dyn GameStore>>) -> Result<Json<PlayerStats>, ApiError> {
+	let player = Stats::player_key(db.as_ref(), name).await?;
+	let filter = query.filter(Some(&player)).map_err(ApiError::bad_request)?;
+	Stats::player_stats(db.as_ref(), name, &player, &filter)
+		.await
+		.map(Json)
+		.map_err(ApiError::from)
+}
+
+// Current Glicko-2 ratings of one game type, the AI levels included, highest first
+#[get("/ratings?<gametype>")]
+async fn get_ratings(gametype: &str, db: &State<Arc<dyn GameStore>>) -> Result<Json<Vec<RatingEntry>>, ApiError> {
+	check_gametype(gametype).map_err(ApiError::bad_request)?;
+	Ratings::ranking(db.as_ref(), gametype)
+		.await
+		.map(Json)
+		.map_err(ApiError::from)
+}
+
+// A player's rating after each rated game, oldest first
+#[get("/players/<name>/ratings?<gametype>")]
+async fn get_rating_history(name: &str, gametype: &str, db: &State<Arc<dyn GameStore>>) -> Result<Json<Vec<RatingEntry>>, ApiError> {
+	check_gametype(gametype).map_err(ApiError::bad_request)?;
+	let player = Stats::player_key(db.as_ref(), name).await?;
+	db.rating_history(gametype, &player)
+		.await
+		.map(Json)
+		.map_err(ApiError::from)
+}
+
+#[get("/games/<id>")]
+async fn get_game(id: &str, db: &State<Arc<dyn GameStore>>) -> Result<Json<Game>, ApiError> {
+	find_game(id, db.as_ref()).await.map(Json)
+}
+
+#[delete("/games/<id>")]
+// Only a player of the game may delete it
+async fn delete_game(_limit: Throttled, id: &str, user: AuthUser, db: &State<Arc<dyn GameStore>>) -> Result<Status, ApiError> {
+	let game = find_game(id, db.as_ref()).await?;
+	let own = [&game.player1_id, &game.player2_id].iter().any(|player| player.as_deref() == Some(user.user.id.as_str()));
+	if !own {
+		return Err(ApiError::forbidden("only the players of a game may delete it"));
+	}
+	if !db.delete_game(id).await? {
+		return Err(ApiError::not_found("no game with this id"));
+	}
+	// the game is gone either way, so ratings that fail are only logged
+	if let Err(err) = Ratings::recompute_from(db.as_ref(), &game).await {
+		error!("cannot rate games after deleting {}: {}", id, err);
 	}
+	Ok(Status::NoContent)
+}
 
-	pub fn insert_game(&self, gametype: &str, player1: &str, player2: &str, winner: &str) -> Result<InsertOneResult, Box<dyn Error>> {
-		// List the names of the collections in that database.
-		let coll = self.database.collection::<Game>("Games");
-		let now: DateTime<Utc> = Utc::now();
-		let game = Game{
-			gametype: gametype.to_string(),
-			player1: player1.to_string(),
-			player2: player2.to_string(),
-			winner: winner.to_string(),
-			date: format!("{}", now.format("%I:%M%p on %b %d, %Y")),
-		};
-		Ok(coll.insert_one(game, None).unwrap())
+async fn find_game(id: &str, db: &dyn GameStore) -> Result<Game, ApiError> {
+	db.get_game(id).await?.ok_or_else(|| ApiError::not_found("no game with this id"))
+}
+
+// The moves of a stored game in order; games stored before moves were kept have none
+#[get("/games/<id>/moves")]
+async fn get_moves(id: &str, db: &State<Arc<dyn GameStore>>) -> Result<Json<Vec<String>>, ApiError> {
+	Ok(Json(find_game(id, db.as_ref()).await?.moves))
+}
+
+fn replay(game: &Game, ply: Option<usize>) -> Result<(Vec<ReplayFrame>, Vec<Position>), ApiError> {
+	let (frames, positions) = Replay::frames(game).map_err(ApiError::internal)?;
+	if let Some(ply) = ply {
+		if ply >= frames.len() {
+			return Err(ApiError::bad_request(format!("ply must be between 0 and {}", frames.len() - 1)));
+		}
 	}
+	Ok((frames, positions))
+}
 
-	pub fn get_games(&self) -> Result<Vec<Game>, Box<dyn Error>> {
-		// List the names of the collections in that database.
-		let mut cursor = self.database.collection("Games").find(None, None)?;
-		// regular Stream uses next() and iterates over Option<Result<T>>
-		let mut result: Vec<Game> = Vec::new();
-		while let Some(doc) = cursor.next() {
-			result.push(self.doc_to_game(&doc?)?)
+// Every board of the game, or only the one after `ply` moves
+#[get("/games/<id>/positions?<ply>")]
+async fn get_positions(id: &str, ply: Option<usize>, db: &State<Arc<dyn GameStore>>) -> Result<Json<Vec<ReplayFrame>>, ApiError> {
+	let game = find_game(id, db.as_ref()).await?;
+	let (mut frames, _) = replay(&game, ply)?;
+	if let Some(ply) = ply {
+		frames = vec![frames.swap_remove(ply)];
+	}
+	Ok(Json(frames))
+}
+
+// The board after `ply` moves (the final one by default) as SVG, or PNG with
+// format=png. `marker` rings the last move and `line` crosses out a winning
+// four, both on by default; `size` is the width of a cell in pixels.
+#[get("/games/<id>/image?<ply>&<format>&<marker>&<line>&<size>")]
+async fn get_image(id: &str, ply: Option<usize>, format: Option<&str>, marker: Option<bool>, line: Option<bool>, size: Option<u32>, db: &State<Arc<dyn GameStore>>) -> Result<(ContentType, Vec<u8>), ApiError> {
+	let game = find_game(id, db.as_ref()).await?;
+	let (_, positions) = replay(&game, ply)?;
+	let ply = ply.unwrap_or(positions.len() - 1);
+	let png = match format.unwrap_or("svg") {
+		"svg" => false,
+		"png" => true,
+		other => return Err(ApiError::bad_request(format!("format must be svg or png, not {:?}", other))),
+	};
+	let mut options = RenderOptions { winning_line: line.unwrap_or(true), ..RenderOptions::default() };
+	if let Some(size) = size {
+		if !(MIN_CELL..=MAX_CELL).contains(&size) {
+			return Err(ApiError::bad_request(format!("size must be between {} and {}", MIN_CELL, MAX_CELL)));
 		}
-        Ok(result)
+		options.cell_size = size;
 	}
+	let image = Replay::image(&game, &positions, ply, marker.unwrap_or(true), options, png).map_err(ApiError::internal)?;
+	Ok((if png { ContentType::PNG } else { ContentType::SVG }, image))
+}
 
-	fn doc_to_game(&self, doc: &Document) -> Result<Game, Box<dyn Error>> {
-		let result = Game{
-			gametype: doc.get_str("gametype")?.to_string(),
-			player1: doc.get_str("player1")?.to_string(),
-			player2: doc.get_str("player2")?.to_string(),
-			winner: doc.get_str("winner")?.to_string(),
-			date: doc.get_str("date")?.to_string(),
-		};
+// Every game the query matches as one CSV, JSON or PGN file, read from the
+// store a page at a time. A store that fails partway ends the file early.
+#[get("/export?<format>&<query..>")]
+async fn export(format: Option<&str>, query: GameQuery, db: &State<Arc<dyn GameStore>>) -> Result<Download<ByteStream![Vec<u8>]>, ApiError> {
+	let format = Format::parse(format).map_err(ApiError::bad_request)?;
+	let mut filter = GameQuery { limit: Some(MAX_LIMIT), ..query }.validate().map_err(ApiError::bad_request)?;
+	let db = db.inner().clone();
+	let stream = ByteStream! {
+		yield Export::header(format);
+		let mut first = true;
+		loop {
+			let games = match db.find_games(&filter).await {
+				Ok(games) => games,
+				Err(err) => {
+					error!("export stopped: {}", err);
+					return;
+				}
+			};
+			yield Export::encode(format, &games[..games.len().min(filter.limit)], first);
+			first = false;
+			match Export::next_page(&filter, &games) {
+				Some(next) => filter = next,
+				None => break,
+			}
+		}
+		yield Export::footer(format);
+	};
+	Ok(Download::new(stream, format))
+}
 
-		Ok(result)
+// Add games in any format `GET /export` writes, up to the `import` size
+// limit. Bad records are reported and skipped, duplicates are counted.
+#[post("/import?<format>", data = "<data>")]
+async fn import(_limit: Throttled, format: Option<&str>, data: Data<'_>, limits: &Limits, admin: AdminUser, db: &State<Arc<dyn GameStore>>) -> Result<Json<ImportReport>, ApiError> {
+	let format = Format::parse(format).map_err(ApiError::bad_request)?;
+	let text = data
+		.open(limits.get("import").unwrap_or(16.mebibytes()))
+		.into_string()
+		.await
+		.map_err(|err| ApiError::bad_request(format!("cannot read the file: {}", err)))?;
+	if !text.is_complete() {
+		return Err(ApiError::new(Status::PayloadTooLarge, "the file is larger than the `import` limit"));
 	}
+	let records = Export::parse(format, &text).map_err(ApiError::bad_request)?;
+	let report = Export::import(db.as_ref(), records).await?;
+	info!("{} imported {} games, {} duplicates, {} rejected", admin.user.name, report.imported, report.duplicates, report.rejected.len());
+	Ok(Json(report))
+}
+
+// Every game the query matches, voided ones too unless `voided` says otherwise
+#[get("/admin/games?<voided>&<query..>")]
+async fn admin_games(voided: Option<bool>, query: GameQuery, _admin: AdminUser, db: &State<Arc<dyn GameStore>>) -> Result<Json<GamePage>, ApiError> {
+	let mut filter = query.validate().map_err(ApiError::bad_request)?;
+	filter.voided = voided;
+	Ok(Json(filter.page(db.find_games(&filter).await?)))
+}
+
+#[patch("/admin/games/<id>", data = "<edit>")]
+async fn edit_game(_limit: Throttled, id: &str, edit: Json<GameEdit>, admin: AdminUser, db: &State<Arc<dyn GameStore>>) -> Result<Json<Game>, ApiError> {
+	let game = Admin::edit_game(db.as_ref(), id, edit.into_inner()).await?;
+	info!("{} edited game {}", admin.user.name, id);
+	Ok(Json(game))
+}
+
+#[put("/admin/games/<id>/void")]
+async fn void_game(_limit: Throttled, id: &str, admin: AdminUser, db: &State<Arc<dyn GameStore>>) -> Result<Json<Game>, ApiError> {
+	let game = Admin::set_voided(db.as_ref(), id, true).await?;
+	info!("{} voided game {}", admin.user.name, id);
+	Ok(Json(game))
+}
+
+#[delete("/admin/games/<id>/void")]
+async fn unvoid_game(_limit: Throttled, id: &str, admin: AdminUser, db: &State<Arc<dyn GameStore>>) -> Result<Json<Game>, ApiError> {
+	let game = Admin::set_voided(db.as_ref(), id, false).await?;
+	info!("{} restored game {}", admin.user.name, id);
+	Ok(Json(game))
+}
+
+// Unlike `DELETE /games/<id>`, any game, and the ratings are recomputed
+#[delete("/admin/games/<id>")]
+async fn admin_delete_game(_limit: Throttled, id: &str, admin: AdminUser, db: &State<Arc<dyn GameStore>>) -> Result<Status, ApiError> {
+	Admin::delete_game(db.as_ref(), id).await?;
+	info!("{} deleted game {}", admin.user.name, id);
+	Ok(Status::NoContent)
+}
+
+#[post("/admin/players/merge", data = "<merge>")]
+async fn merge_players(_limit: Throttled, merge: Json<MergePlayers>, admin: AdminUser, db: &State<Arc<dyn GameStore>>) -> Result<Json<GamesChanged>, ApiError> {
+	let merge = merge.into_inner();
+	let summary = format!("{} merged {:?} into {:?}", admin.user.name, merge.from, merge.into);
+	let changed = Admin::merge_players(db.as_ref(), merge).await?;
+	info!("{}, {} games", summary, changed.games);
+	Ok(Json(changed))
+}
+
+#[put("/admin/users/<name>/ban")]
+async fn ban_user(_limit: Throttled, name: &str, admin: AdminUser, db: &State<Arc<dyn GameStore>>) -> Result<Json<User>, ApiError> {
+	let user = Admin::set_banned(db.as_ref(), &admin.user, name, true).await?;
+	info!("{} banned {}", admin.user.name, user.name);
+	Ok(Json(user))
+}
+
+#[delete("/admin/users/<name>/ban")]
+async fn unban_user(_limit: Throttled, name: &str, admin: AdminUser, db: &State<Arc<dyn GameStore>>) -> Result<Json<User>, ApiError> {
+	let user = Admin::set_banned(db.as_ref(), &admin.user, name, false).await?;
+	info!("{} lifted the ban on {}", admin.user.name, user.name);
+	Ok(Json(user))
+}
+
+#[put("/admin/users/<name>/role", data = "<change>")]
+async fn set_role(_limit: Throttled, name: &str, change: Json<RoleChange>, admin: AdminUser, db: &State<Arc<dyn GameStore>>) -> Result<Json<User>, ApiError> {
+	let user = Admin::set_role(db.as_ref(), name, change.role).await?;
+	info!("{} made {} {}", admin.user.name, user.name, if user.role == Role::Admin { "an admin" } else { "a player" });
+	Ok(Json(user))
+}
+
+// Throw the ratings away and rate every game that counts again
+#[post("/admin/ratings")]
+async fn recompute_ratings(_limit: Throttled, admin: AdminUser, db: &State<Arc<dyn GameStore>>) -> Result<Json<GamesChanged>, ApiError> {
+	let games = Ratings::recompute(db.as_ref()).await?;
+	info!("{} recomputed the ratings over {} games", admin.user.name, games);
+	Ok(Json(GamesChanged { games }))
+}
+
+// Register an account; log in with `POST /sessions` afterwards
+#[post("/users", data = "<credentials>")]
+async fn register(_limit: Throttled, credentials: Json<Credentials>, db: &State<Arc<dyn GameStore>>) -> Result<status::Created<Json<User>>, ApiError> {
+	let user = Accounts::register(db.as_ref(), credentials.into_inner()).await.map_err(ApiError::from)?;
+	Ok(status::Created::new(format!("/users/{}", user.id)).body(Json(user)))
 }
 
-#[get("/games")]
-fn get_games(db: &State<DB>) -> Json<Vec<Game>> {
-	let result = db.get_games();
+#[get("/users/me")]
+fn get_me(user: AuthUser) -> Json<User> {
+	Json(user.user)
+}
+
+#[post("/sessions", data = "<credentials>")]
+async fn login(_limit: Throttled, credentials: Json<Credentials>, db: &State<Arc<dyn GameStore>>) -> Result<Json<Session>, ApiError> {
+	Accounts::login(db.as_ref(), credentials.into_inner()).await.map(Json).map_err(ApiError::from)
+}
+
+// Log out the session the request came with
+#[delete("/sessions")]
+async fn logout(_limit: Throttled, user: AuthUser, db: &State<Arc<dyn GameStore>>) -> Result<Status, ApiError> {
+	Accounts::logout(db.as_ref(), &user.token).await?;
+	Ok(Status::NoContent)
+}
+
+#[post("/matches", data="<request>")]
+async fn create_match(_limit: Throttled, request: Json<NewMatch>, user: AuthUser, matches: &State<MatchTable>, db: &State<Arc<dyn GameStore>>) -> Result<status::Created<Json<MatchState>>, ApiError> {
+	let state = matches.create(request.into_inner(), &user.user, db.as_ref()).await.map_err(ApiError::from)?;
+	Ok(status::Created::new(format!("/matches/{}", state.id)).body(Json(state)))
+}
+
+// Online matches anyone may watch through their events
+#[get("/matches/live")]
+async fn get_live_matches(matches: &State<MatchTable>) -> Result<Json<Vec<LiveMatch>>, ApiError> {
+	matches.live().await.map(Json).map_err(ApiError::from)
+}
+
+#[get("/matches/<id>")]
+async fn get_match(id: &str, matches: &State<MatchTable>) -> Result<Json<MatchState>, ApiError> {
+	matches.get(id).await.map(Json).map_err(ApiError::from)
+}
+
+// Games only reach the store through here, once the server has seen them end.
+// Against the computer the answer already holds its reply.
+#[post("/matches/<id>/moves", data="<mv>")]
+async fn play_move(_limit: Throttled, id: &str, mv: Json<MatchMove>, user: AuthUser, matches: &State<MatchTable>, db: &State<Arc<dyn GameStore>>) -> Result<Json<MatchState>, ApiError> {
+	matches.play(id, &mv.mv, &user.user, db.as_ref()).await.map(Json).map_err(ApiError::from)
+}
+
+// The match of a room that timed out is gone, however its id is known
+#[post("/matches/<id>/join")]
+async fn join_match(_limit: Throttled, id: &str, user: AuthUser, matches: &State<MatchTable>, lobby: &State<LobbyTable>) -> Result<Json<MatchState>, ApiError> {
+	lobby.expire(matches).await?;
+	matches.join(id, &user.user).await.map(Json).map_err(ApiError::from)
+}
+
+// Server-sent events with the state of a match, first as it is and then
+// after every change, so a client that reconnects is in sync at once.
+// Read only: players and any number of spectators follow the same stream.
+#[get("/matches/<id>/events")]
+async fn match_events(id: &str, matches: &State<MatchTable>, mut shutdown: Shutdown) -> Result<EventStream![], ApiError> {
+	let (state, mut updates) = matches.subscribe(id).await.map_err(ApiError::from)?;
+	Ok(EventStream! {
+		yield Event::json(&state);
+		loop {
+			let state = select! {
+				update = updates.recv() => match update {
+					Ok(state) => state,
+					// every state is complete, the next one catches up
+					Err(RecvError::Lagged(_)) => continue,
+					Err(RecvError::Closed) => break,
+				},
+				_ = &mut shutdown => break,
+			};
+			yield Event::json(&state);
+		}
+	})
+}
+
+#[post("/lobby/queue", data = "<request>")]
+async fn join_queue(_limit: Throttled, request: Json<QueueRequest>, user: AuthUser, lobby: &State<LobbyTable>, matches: &State<MatchTable>, db: &State<Arc<dyn GameStore>>) -> Result<Json<QueueStatus>, ApiError> {
+	lobby.enqueue(request.into_inner(), &user.user, matches, db.as_ref()).await.map(Json).map_err(ApiError::from)
+}
+
+// Players who stop asking are dropped from the queue after a while
+#[get("/lobby/queue")]
+async fn get_queue(user: AuthUser, lobby: &State<LobbyTable>, matches: &State<MatchTable>, db: &State<Arc<dyn GameStore>>) -> Result<Json<QueueStatus>, ApiError> {
+	lobby.poll(&user.user, matches, db.as_ref()).await.map(Json).map_err(ApiError::from)
+}
+
+#[delete("/lobby/queue")]
+async fn leave_queue(_limit: Throttled, user: AuthUser, lobby: &State<LobbyTable>) -> Result<Status, ApiError> {
+	lobby.leave(&user.user).await.map(|_| Status::NoContent).map_err(ApiError::from)
+}
+
+#[post("/lobby/rooms", data = "<request>")]
+async fn open_room(_limit: Throttled, request: Json<NewMatch>, user: AuthUser, lobby: &State<LobbyTable>, matches: &State<MatchTable>, db: &State<Arc<dyn GameStore>>) -> Result<status::Created<Json<Room>>, ApiError> {
+	let room = lobby.open_room(request.into_inner(), &user.user, matches, db.as_ref()).await.map_err(ApiError::from)?;
+	Ok(status::Created::new(format!("/matches/{}", room.match_id)).body(Json(room)))
+}
+
+#[post("/lobby/rooms/<code>/join")]
+async fn join_room(_limit: Throttled, code: &str, user: AuthUser, lobby: &State<LobbyTable>, matches: &State<MatchTable>) -> Result<Json<Room>, ApiError> {
+	lobby.join_room(code, &user.user, matches).await.map(Json).map_err(ApiError::from)
+}
+
+#[delete("/lobby/rooms/<code>")]
+async fn close_room(_limit: Throttled, code: &str, user: AuthUser, lobby: &State<LobbyTable>, matches: &State<MatchTable>) -> Result<Status, ApiError> {
+	lobby.close_room(code, &user.user, matches).await.map(|_| Status::NoContent).map_err(ApiError::from)
+}
+
+// The engine's move for a position, searched on the server rather than in
+// the browser. Searches run on the blocking pool, away from the request threads.
+#[post("/ai/move", data = "<request>")]
+async fn ai_move(_limit: Throttled, request: Json<AiMoveRequest>, metrics: &State<ServerMetrics>) -> Result<Json<AiMove>, ApiError> {
+	let request = request.into_inner();
+	let gametype = format!("{:?}", request.gametype);
+	let started = Instant::now();
+	let result = rocket::tokio::task::spawn_blocking(move || Engine::ai_move(request)).await;
+	metrics.observe_ai_move(&gametype, started.elapsed());
 	match result {
-		Ok(v) => Json(v),
-		_=> Json(Vec::new()),
+		Ok(result) => result.map(Json).map_err(ApiError::bad_request),
+		Err(err) => Err(ApiError::internal(err.to_string())),
 	}
 }
 
-#[post("/games", data="<game>")]
-fn add_games(game: Json<Game>, db: &State<DB>) {
-    db.insert_game(&game.gametype,&game.player1,&game.player2,&game.winner);
+// The server is up; says nothing about the database
+#[get("/health")]
+fn health(metrics: &State<ServerMetrics>) -> Json<Health> {
+	Json(Health { status: "ok".to_string(), uptime_secs: metrics.uptime().as_secs() })
+}
+
+// The server can serve requests: 503 while the database does not answer
+#[get("/ready")]
+async fn ready(metrics: &State<ServerMetrics>, db: &State<Arc<dyn GameStore>>) -> Result<Json<Health>, ApiError> {
+	db.ping().await.map_err(ApiError::unavailable)?;
+	Ok(Json(Health { status: "ready".to_string(), uptime_secs: metrics.uptime().as_secs() }))
+}
+
+// Request counts and latencies, games, live matches and engine times in
+// the Prometheus text format. The database gauges are those of the last
+// check by the monitor, game counts are left out while it does not answer.
+#[get("/metrics")]
+async fn metrics(metrics: &State<ServerMetrics>, matches: &State<MatchTable>) -> (ContentType, String) {
+	let live = matches.live().await.map(|live| live.len()).ok();
+	let content_type = ContentType::new("text", "plain").with_params([("version", "0.0.4"), ("charset", "utf-8")]);
+	(content_type, metrics.render(live))
+}
+
+// Whether the database answers and how many games count, for the gauges
+async fn check_store(db: &dyn GameStore) -> Result<StoreMetrics, StoreError> {
+	db.ping().await?;
+	let mut store = StoreMetrics { up: true, games: Vec::new() };
+	for gametype in ["Connect4", "TootOtto"] {
+		match Stats::games_recorded(db, gametype).await {
+			Ok(games) => store.games.push((gametype, games)),
+			Err(err) => error!("cannot count the {} games: {}", gametype, err),
+		}
+	}
+	Ok(store)
 }
 
 pub struct CORS;
@@ -99,20 +474,134 @@ impl Fairing for CORS {
         }
     }
 
-    async fn on_response<'r>(&self, _request: &'r Request<'_>, response: &mut Response<'r>) {
-        response.set_header(Header::new("Access-Control-Allow-Origin", "*"));
-        response.set_header(Header::new("Access-Control-Allow-Methods", "POST, GET, PATCH, OPTIONS"));
-        response.set_header(Header::new("Access-Control-Allow-Headers", "*"));
-        response.set_header(Header::new("Access-Control-Allow-Credentials", "true"));
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        // only answer origins listed in `cors_origins`; browsers refuse
+        // credentials with a wildcard, so "*" allows any origin without them
+        let origin = match request.headers().get_one("Origin") {
+            Some(origin) => origin,
+            None => return,
+        };
+        let config = match request.rocket().state::<AppConfig>() {
+            Some(config) if config.allows_origin(origin) => config,
+            _ => return,
+        };
+        if config.cors_origins.iter().any(|allowed| allowed == origin) {
+            response.set_header(Header::new("Access-Control-Allow-Origin", origin.to_string()));
+            response.set_header(Header::new("Access-Control-Allow-Credentials", "true"));
+            response.set_header(Header::new("Vary", "Origin"));
+        } else {
+            response.set_header(Header::new("Access-Control-Allow-Origin", "*"));
+        }
+        response.set_header(Header::new("Access-Control-Allow-Methods", "POST, GET, PUT, PATCH, DELETE, OPTIONS"));
+        response.set_header(Header::new("Access-Control-Allow-Headers", "Content-Type, Authorization"));
     }
 }
 
+// Answer CORS preflight requests, the fairing above adds the headers
+#[options("/<_..>")]
+fn preflight() {}
+
+async fn open_store(config: &AppConfig) -> Result<Arc<dyn GameStore>, String> {
+	match config.storage.as_str() {
+		"memory" => Ok(Arc::new(MemoryStore::new())),
+		"sqlite" => match SqliteStore::new(&config.sqlite_path) {
+			Ok(store) => Ok(Arc::new(store)),
+			Err(err) => Err(format!("cannot open SQLite database {:?}: {}", config.sqlite_path, err)),
+		},
+		_ => match DB::new(config).await {
+			Ok(store) => Ok(Arc::new(store)),
+			Err(err) => Err(format!("cannot connect to MongoDB at {:?}: {}", config.mongo_uri, err)),
+		},
+	}
+}
+
+// Read and check the configuration, then open the game store it names.
+// Any problem stops the launch with an error saying what to fix.
+fn configure() -> AdHoc {
+	AdHoc::try_on_ignite("Configuration", |rocket| async move {
+		let config: AppConfig = match rocket.figment().extract() {
+			Ok(config) => config,
+			Err(err) => {
+				error!("invalid configuration: {}", err);
+				return Err(rocket);
+			}
+		};
+		if let Err(err) = config.validate() {
+			error!("invalid configuration: {}", err);
+			return Err(rocket);
+		}
+		let store = match open_store(&config).await {
+			Ok(store) => store,
+			Err(err) => {
+				error!("{}", err);
+				return Err(rocket);
+			}
+		};
+		match store.migrate().await {
+			Ok(0) => {}
+			Ok(count) => info!("migrated {} stored games to the current format", count),
+			Err(err) => {
+				error!("cannot migrate stored games: {}", err);
+				return Err(rocket);
+			}
+		}
+		match Ratings::backfill(store.as_ref()).await {
+			Ok(0) => {}
+			Ok(count) => info!("rated {} stored games", count),
+			Err(err) => {
+				error!("cannot rate stored games: {}", err);
+				return Err(rocket);
+			}
+		}
+		Ok(rocket.manage(config).manage(store))
+	})
+}
+
+// Check the database every `health_check_secs`, from liftoff on, and log
+// when it stops or starts answering again. The stores reconnect by
+// themselves, this makes an outage visible in the log instead of only in
+// failed requests, and keeps the database gauges of `GET /metrics` current.
+fn monitor() -> AdHoc {
+	AdHoc::on_liftoff("Database monitor", |rocket| Box::pin(async move {
+		let (store, config, gauges) = match (rocket.state::<Arc<dyn GameStore>>(), rocket.state::<AppConfig>(), rocket.state::<ServerMetrics>()) {
+			(Some(store), Some(config), Some(metrics)) => (store.clone(), config.clone(), metrics.store_gauges()),
+			_ => return,
+		};
+		let mut shutdown = rocket.shutdown();
+		rocket::tokio::spawn(async move {
+			let mut interval = rocket::tokio::time::interval(Duration::from_secs(config.health_check_secs));
+			let mut healthy = true;
+			loop {
+				select! {
+					_ = interval.tick() => {}
+					_ = &mut shutdown => break,
+				}
+				let result = check_store(store.as_ref()).await;
+				match &result {
+					Ok(_) if !healthy => info!("the database is answering again"),
+					Err(err) if healthy => error!("lost the database: {}", err),
+					_ => {}
+				}
+				healthy = result.is_ok();
+				gauges.set(result.unwrap_or_default());
+			}
+		});
+	}))
+}
+
 #[launch]
 fn rocket() -> _ {
-	let db = DB::new().unwrap();
+	// settings in a .env file end up in the environment like any other ROCKET_ variable
+	dotenv::dotenv().ok();
     rocket::build()
-	.manage(db)
-	.mount("/", routes![get_games])
-	.mount("/post", routes![add_games])
+	.attach(configure())
+	.attach(RequestMetrics)
+	.attach(monitor())
+	.manage(ServerMetrics::new())
+	.manage(MatchTable::new())
+	.manage(LobbyTable::new())
+	.manage(RateLimiter::new())
+	.mount("/", routes![get_games, get_leaderboard, get_player_stats, get_ratings, get_rating_history, register, get_me, login, logout, get_game, delete_game, get_moves, get_positions, get_image, export, import, admin_games, edit_game, void_game, unvoid_game, admin_delete_game, merge_players, ban_user, unban_user, set_role, recompute_ratings, create_match, get_live_matches, get_match, play_move, join_match, match_events, join_queue, get_queue, leave_queue, open_room, join_room, close_room, ai_move, health, ready, metrics, preflight])
+	.register("/", catchers![Errors::catch_all])
 	.attach(CORS)
-}
\ No newline at end of file
+}
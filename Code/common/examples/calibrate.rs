@@ -0,0 +1,25 @@
+// Measure the Elo of every skill level and print it next to the numbers in
+// `Skill::LEVELS`. The seed makes a run repeatable:
+//
+//     cargo run --release --example calibrate -- [games per pair] [seed]
+//
+// Defaults to 100 games and seed 1, the run the committed numbers come from.
+use common::Position::GameType;
+use common::Skill::{calibrate, LEVELS};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let games = args.next().map(|arg| arg.parse().expect("games must be a number")).unwrap_or(100);
+    let seed = args.next().map(|arg| arg.parse().expect("seed must be a number")).unwrap_or(1);
+    println!("{} games between neighbouring levels, seed {}", games, seed);
+    for gametype in [GameType::Connect4, GameType::TootOtto] {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let ratings = calibrate(gametype, &LEVELS, games, &mut rng);
+        println!("{:?}", gametype);
+        for (skill, rating) in LEVELS.iter().zip(ratings) {
+            println!("  level {} {:<13} measured {:>5.0}  listed {:>5}", skill.level, skill.name, rating, skill.elo(gametype));
+        }
+    }
+}
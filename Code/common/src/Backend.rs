@@ -1,10 +1,504 @@
 use serde::{Deserialize, Serialize};
 
+use crate::Position::{GameType, Move, Position, Side};
+
+// A finished game as the server stores it. Times are RFC 3339 strings in UTC
+// so they sort as text; turning them into something readable is up to the
+// frontend. The metadata fields are optional because records written before
+// they existed only carry what the migration could recover.
 #[derive(Clone,Debug, Serialize, Deserialize)]
 pub struct Game {
+	// assigned by the server when the game is stored
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub id: Option<String>,
 	pub gametype: String,
     pub player1: String,
     pub player2: String,
 	pub winner: String,
-	pub date: String,
-}
\ No newline at end of file
+	// when the match was opened
+	#[serde(default)]
+	pub started_at: Option<String>,
+	// set by the server when the game is stored
+	#[serde(default)]
+	pub ended_at: Option<String>,
+	#[serde(default)]
+	pub duration_secs: Option<i64>,
+	#[serde(default)]
+	pub move_count: Option<u32>,
+	// skill level of the computer, None for games between two people
+	#[serde(default)]
+	pub difficulty: Option<usize>,
+	// rules the game was played with, "standard" for the 6x7 board
+	#[serde(default = "standard_variant")]
+	pub variant: String,
+	// name of the player who made the first move
+	#[serde(default)]
+	pub first_mover: Option<String>,
+	// TOOT-OTTO only: the word player1 was spelling, "TOOT" or "OTTO"
+	#[serde(default)]
+	pub player1_word: Option<String>,
+	// every move in order, written as in `Move::parse`; empty for old records
+	#[serde(default)]
+	pub moves: Vec<String>,
+	// accounts of the players, none for guests, the computer and old records
+	#[serde(default)]
+	pub player1_id: Option<String>,
+	#[serde(default)]
+	pub player2_id: Option<String>,
+	// set by an admin: the game is kept but counts for nothing, and only
+	// admins see it
+	#[serde(default)]
+	pub voided: bool,
+}
+
+impl Game {
+	pub fn game_type(&self) -> Result<GameType, String> {
+		match self.gametype.as_str() {
+			"Connect4" => Ok(GameType::Connect4),
+			"TootOtto" => Ok(GameType::TootOtto),
+			other => Err(format!("unknown game type {:?}", other)),
+		}
+	}
+
+	// The board before the first move and after every move
+	pub fn replay(&self) -> Result<Vec<Position>, String> {
+		let gametype = self.game_type()?;
+		let mut position = Position::new(gametype);
+		let mut positions = vec![position.clone()];
+		for (ply, text) in self.moves.iter().enumerate() {
+			let mv = Move::parse(gametype, text).map_err(|err| format!("move {}: {}", ply + 1, err))?;
+			position.play(mv).map_err(|err| format!("move {}: {}", ply + 1, err))?;
+			positions.push(position.clone());
+		}
+		Ok(positions)
+	}
+
+	// Name of the player on a side: Connect4 sides are seats in move order,
+	// TOOT-OTTO sides are the words
+	pub fn player_on(&self, side: Side) -> &str {
+		let first = self.first_mover.as_deref().unwrap_or(&self.player1);
+		let second = if first == self.player1 { &self.player2 } else { &self.player1 };
+		match self.game_type() {
+			Ok(GameType::TootOtto) => {
+				let player1_toot = self.player1_word.as_deref() != Some("OTTO");
+				if (side == Side::First) == player1_toot { &self.player1 } else { &self.player2 }
+			}
+			_ => if side == Side::First { first } else { second },
+		}
+	}
+
+	// Check the recorded names and result, then replay the moves and check
+	// they end the game with that result
+	pub fn check(&self) -> Result<(), String> {
+		self.check_record()?;
+		let positions = self.replay()?;
+		let last = positions.last().unwrap();
+		if !last.is_over() {
+			return Err("the moves do not finish the game".to_string());
+		}
+		let winner = last.winner().map(|side| self.player_on(side)).unwrap_or("");
+		if winner != self.winner {
+			return Err(format!("the moves give the game to {:?}, not {:?}", winner, self.winner));
+		}
+		Ok(())
+	}
+
+	// The checks that do not need the moves, for records kept without them
+	pub fn check_record(&self) -> Result<(), String> {
+		self.game_type()?;
+		check_player_name(&self.player1)?;
+		check_player_name(&self.player2)?;
+		if self.player1 == self.player2 {
+			return Err("the players need different names".to_string());
+		}
+		if !self.winner.is_empty() && self.winner != self.player1 && self.winner != self.player2 {
+			return Err(format!("the winner must be one of the players, or empty for a draw, not {:?}", self.winner));
+		}
+		Ok(())
+	}
+}
+
+// Names as games record them: 1 to 32 characters, without control
+// characters or spaces at either end
+pub fn check_player_name(name: &str) -> Result<(), String> {
+	if name.trim() != name {
+		return Err(format!("player name {:?} starts or ends with a space", name));
+	}
+	if !(1..=32).contains(&name.chars().count()) {
+		return Err(format!("player names must be 1 to 32 characters long, not {:?}", name));
+	}
+	if name.chars().any(char::is_control) {
+		return Err(format!("player name {:?} has control characters", name));
+	}
+	Ok(())
+}
+
+pub fn standard_variant() -> String {
+	"standard".to_string()
+}
+
+// Body of `POST /matches`. The server keeps the board and only records the
+// game once its own replay of the moves says it is over.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NewMatch {
+	pub gametype: GameType,
+	// the server puts the logged in user here, whatever the client sends
+	pub player1: String,
+	pub player2: String,
+	// must be one of the two players, player1 if left out
+	#[serde(default)]
+	pub first_mover: Option<String>,
+	// TOOT-OTTO only: the word player1 is trying to spell, "TOOT" if left out
+	#[serde(default)]
+	pub player1_word: Option<String>,
+	// skill level of the computer, for games against it; the server plays its moves
+	#[serde(default)]
+	pub difficulty: Option<usize>,
+	// played from two browsers: player2 is left empty until another logged
+	// in user joins with `POST /matches/<id>/join`
+	#[serde(default)]
+	pub online: bool,
+}
+
+// Body of `POST /matches/<id>/moves`, a move written as in `Position::Move::parse`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MatchMove {
+	#[serde(rename = "move")]
+	pub mv: String,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum MatchStatus {
+	// an online match nobody has joined yet
+	Waiting,
+	Playing,
+	Won,
+	Draw,
+}
+
+// A match as the server sees it after the last accepted move
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MatchState {
+	pub id: String,
+	pub gametype: GameType,
+	pub player1: String,
+	pub player2: String,
+	pub first_mover: String,
+	#[serde(default)]
+	pub player1_word: Option<String>,
+	// position string, see `Position::Position::parse`
+	pub position: String,
+	pub moves: Vec<String>,
+	// name of the player whose turn it is, None once the game is over
+	pub to_move: Option<String>,
+	pub status: MatchStatus,
+	pub winner: Option<String>,
+	// id of the stored game once the match has finished
+	pub game_id: Option<String>,
+	#[serde(default)]
+	pub online: bool,
+}
+
+// An online match being played right now, as listed by `GET /matches/live`.
+// Watch it through `GET /matches/<id>/events` like the players do.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LiveMatch {
+	#[serde(flatten)]
+	pub state: MatchState,
+	// clients following the match, the players' own pages included
+	pub watching: usize,
+	pub started_at: String,
+}
+
+// One step of a stored game, for replay viewers
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReplayFrame {
+	// number of moves played, 0 is the empty board
+	pub ply: usize,
+	pub last_move: Option<String>,
+	// position string, see `Position::Position::parse`
+	pub position: String,
+	// the board top row first, one character per cell, '.' for empty
+	pub rows: Vec<String>,
+	pub winner: Option<String>,
+}
+
+// One page of `GET /games`; pass `next_cursor` back as `cursor` for the next
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GamePage {
+	pub games: Vec<Game>,
+	pub next_cursor: Option<String>,
+}
+
+// Results of one player over some set of games; draws count as games but
+// neither as wins nor losses
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+	pub games: u32,
+	pub wins: u32,
+	pub losses: u32,
+	pub draws: u32,
+	// wins over games, 0 when there are none
+	pub win_rate: f64,
+}
+
+impl Record {
+	pub fn new(games: u32, wins: u32, draws: u32) -> Record {
+		Record {
+			games,
+			wins,
+			losses: games - wins - draws,
+			draws,
+			win_rate: if games == 0 { 0.0 } else { wins as f64 / games as f64 },
+		}
+	}
+}
+
+// One row of `GET /leaderboard`. With a game type the rows are ranked by
+// the player's current rating for it, otherwise there is none.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LeaderboardEntry {
+	pub rank: u32,
+	pub player: String,
+	// the player's account, None for guests
+	#[serde(default)]
+	pub player_id: Option<String>,
+	#[serde(flatten)]
+	pub record: Record,
+	#[serde(default)]
+	pub rating: Option<f64>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GameResult {
+	Win,
+	Loss,
+	Draw,
+}
+
+// The same result in a row, ending with the player's latest game
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Streak {
+	pub result: GameResult,
+	pub length: u32,
+}
+
+// Results against the computer at one difficulty; games stored without
+// one are grouped under `None`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DifficultyRecord {
+	pub difficulty: Option<usize>,
+	#[serde(flatten)]
+	pub record: Record,
+}
+
+// `GET /players/<name>/stats`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlayerStats {
+	pub player: String,
+	#[serde(flatten)]
+	pub record: Record,
+	pub vs_human: Record,
+	pub vs_computer: Vec<DifficultyRecord>,
+	pub current_streak: Option<Streak>,
+	pub longest_win_streak: u32,
+	pub longest_loss_streak: u32,
+}
+
+// A player's Glicko-2 rating for one game type after one rated game; the
+// latest entry is the current rating. The AI at each difficulty is rated as
+// a player of its own, named like "Computer (level 3)".
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RatingEntry {
+	pub player: String,
+	// the player's account, None for guests and the computer
+	#[serde(default)]
+	pub player_id: Option<String>,
+	pub gametype: String,
+	// the game that led to this rating
+	#[serde(default)]
+	pub game_id: Option<String>,
+	pub rating: f64,
+	pub deviation: f64,
+	pub volatility: f64,
+	// rated games so far, this one included
+	pub games: u32,
+	pub at: String,
+}
+
+// Body of `POST /users` and `POST /sessions`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Credentials {
+	pub name: String,
+	pub password: String,
+}
+
+// A registered player, as anyone may see it
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct User {
+	pub id: String,
+	pub name: String,
+	pub created_at: String,
+	#[serde(default)]
+	pub role: Role,
+}
+
+// What an account may do; admins also get the `/admin` routes
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+	#[default]
+	Player,
+	Admin,
+}
+
+// Answer to a login; send the token back as `Authorization: Bearer <token>`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+	pub token: String,
+	pub user: User,
+	pub expires_at: String,
+}
+
+// Body of `POST /lobby/queue`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct QueueRequest {
+	pub gametype: GameType,
+	#[serde(default = "standard_variant")]
+	pub variant: String,
+}
+
+// Where a player stands in the matchmaking queue. Poll `GET /lobby/queue`
+// until `match_id` is set, then play that online match.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct QueueStatus {
+	pub gametype: GameType,
+	pub variant: String,
+	// the rating the player is paired by
+	pub rating: f64,
+	pub waiting_secs: u64,
+	// players waiting for the same game type and variant, this one included
+	pub waiting: usize,
+	#[serde(default)]
+	pub match_id: Option<String>,
+}
+
+// A private room: whoever enters the code with `POST /lobby/rooms/<code>/join`
+// takes the free seat of the online match
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Room {
+	pub code: String,
+	pub match_id: String,
+	pub gametype: GameType,
+	pub expires_at: String,
+}
+
+// Body of `POST /ai/move`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AiMoveRequest {
+	pub gametype: GameType,
+	// position string, see `Position::Position::parse`
+	pub position: String,
+	// skill level, see `Skill::LEVELS`; the strongest if left out
+	#[serde(default)]
+	pub difficulty: Option<usize>,
+	// play at full strength instead, searching deeper for about this long
+	#[serde(default)]
+	pub time_ms: Option<u64>,
+	// also send the score of every legal move
+	#[serde(default)]
+	pub analysis: bool,
+}
+
+// One legal move and how good it is for the side to move; see `Skill::WIN`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MoveScore {
+	#[serde(rename = "move")]
+	pub mv: String,
+	pub score: i32,
+}
+
+// Answer to `POST /ai/move`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AiMove {
+	#[serde(rename = "move")]
+	pub mv: String,
+	// the position after the move
+	pub position: String,
+	// plies searched
+	pub depth: usize,
+	pub elapsed_ms: u64,
+	// best first, when asked for
+	#[serde(default)]
+	pub analysis: Option<Vec<MoveScore>>,
+}
+
+// Body of every error the server answers with
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ErrorBody {
+	// the HTTP status code again, for clients that only keep the body
+	pub status: u16,
+	pub error: String,
+}
+
+// Answer of `GET /health` and `GET /ready`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Health {
+	pub status: String,
+	pub uptime_secs: u64,
+}
+
+// Answer of `POST /import`
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImportReport {
+	pub imported: usize,
+	// already stored, or earlier in the same file
+	pub duplicates: usize,
+	pub rejected: Vec<ImportProblem>,
+}
+
+// A record that was not imported and why
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ImportProblem {
+	// counted from 1 in the order of the file
+	pub record: usize,
+	pub error: String,
+}
+
+// Body of `PATCH /admin/games/<id>`: the fields to change, the rest stay
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct GameEdit {
+	#[serde(default)]
+	pub player1: Option<String>,
+	#[serde(default)]
+	pub player2: Option<String>,
+	// "" for a draw
+	#[serde(default)]
+	pub winner: Option<String>,
+	#[serde(default)]
+	pub first_mover: Option<String>,
+	#[serde(default)]
+	pub player1_word: Option<String>,
+	#[serde(default)]
+	pub difficulty: Option<usize>,
+}
+
+// Body of `POST /admin/players/merge`: every game of `from` becomes `into`'s
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MergePlayers {
+	pub from: String,
+	pub into: String,
+}
+
+// Body of `PUT /admin/users/<name>/role`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RoleChange {
+	pub role: Role,
+}
+
+// How many games an admin change touched, or how many games were rated
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GamesChanged {
+	pub games: usize,
+}
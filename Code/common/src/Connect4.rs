@@ -1,9 +1,14 @@
 use rand::Rng;
 
+use crate::Position::Position;
+use crate::Search;
+use crate::Skill;
+
 #[derive(Clone, Debug)]
 pub struct Connect4 {
     board: [[Option<Piece>; 7]; 6],
     current_player: Player,
+    threads: usize,
 }
 
 #[derive(Copy, Clone, PartialEq, Debug)]
@@ -32,6 +37,7 @@ impl Connect4 {
         Connect4 {
             board: [[None; 7]; 6],
             current_player: Player::Red,
+            threads: 1,
         }
     }
 
@@ -39,6 +45,83 @@ impl Connect4 {
         self.board.clone()
     }
 
+    pub fn get_current_player(&self) -> Player {
+        self.current_player
+    }
+
+    // Build a game from a position string: six rows from top to bottom separated
+    // by '/', each row seven of 'R', 'Y' or '.', then a space and the side to move
+    // ('r' or 'y'), e.g. "......./......./......./......./......./...R... y"
+    pub fn from_position(position: &str) -> Result<Connect4, String> {
+        let mut parts = position.trim().split(' ');
+        let rows: Vec<&str> = parts.next().unwrap_or("").split('/').collect();
+        if rows.len() != 6 {
+            return Err(format!("expected 6 rows, found {}", rows.len()));
+        }
+        let mut board = [[None; 7]; 6];
+        for (i, row) in rows.iter().enumerate() {
+            if row.chars().count() != 7 {
+                return Err(format!("row {} must have 7 cells", i + 1));
+            }
+            for (j, cell) in row.chars().enumerate() {
+                board[i][j] = match cell {
+                    'R' => Some(Piece::R),
+                    'Y' => Some(Piece::Y),
+                    '.' => None,
+                    _ => return Err(format!("unknown piece '{}' in row {}", cell, i + 1)),
+                };
+                // pieces cannot float above an empty cell
+                if i > 0 && board[i - 1][j].is_some() && board[i][j].is_none() {
+                    return Err(format!("floating piece in column {}", j));
+                }
+            }
+        }
+        let current_player = match parts.next() {
+            Some("r") => Player::Red,
+            Some("y") => Player::Yellow,
+            _ => return Err("side to move must be 'r' or 'y'".to_string()),
+        };
+        if parts.next().is_some() {
+            return Err("unexpected text after side to move".to_string());
+        }
+        // red moves first, so it is red's turn when the counts are equal
+        let count = |piece| board.iter().flatten().filter(|cell| **cell == Some(piece)).count();
+        let (red, yellow) = (count(Piece::R), count(Piece::Y));
+        let expected = if red == yellow { Player::Red } else { Player::Yellow };
+        if red < yellow || red > yellow + 1 || current_player != expected {
+            return Err(format!("{} red and {} yellow pieces cannot have {:?} to move", red, yellow, current_player));
+        }
+        let game = Connect4 {
+            board,
+            current_player,
+            threads: 1,
+        };
+        if game.winner().is_some() {
+            return Err("the game is already over".to_string());
+        }
+        Ok(game)
+    }
+
+    pub fn to_position(&self) -> String {
+        let rows: Vec<String> = self.board.iter().map(|row| {
+            row.iter().map(|cell| match cell {
+                Some(Piece::R) => 'R',
+                Some(Piece::Y) => 'Y',
+                None => '.',
+            }).collect()
+        }).collect();
+        let side = match self.current_player {
+            Player::Red => 'r',
+            Player::Yellow => 'y',
+        };
+        format!("{} {}", rows.join("/"), side)
+    }
+
+    // Let the AI search on several threads; 1 keeps it on the calling thread
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
     pub fn top_row(&self, col: usize) -> usize {
         for row in (0..6).rev() {
             if self.board[row][col].is_none() {
@@ -95,6 +178,24 @@ impl Connect4 {
         self.board[row][col] = None;
     }
 
+    // take back the last piece dropped in a column and give the turn back
+    pub fn undo_move(&mut self, column: usize) -> bool {
+        if !self.check_bounds(column) {
+            return false;
+        }
+        match (0..6).find(|&row| self.board[row][column].is_some()) {
+            Some(row) => {
+                self.remove_piece(row, column);
+                self.current_player = match self.current_player {
+                    Player::Red => Player::Yellow,
+                    Player::Yellow => Player::Red,
+                };
+                true
+            }
+            None => false,
+        }
+    }
+
     pub fn ai_move(&mut self, depth: usize) -> bool {
         let mut done = false;
         let piece = if self.current_player == Player::Red { Piece::R } else { Piece::Y };
@@ -116,7 +217,7 @@ impl Connect4 {
                 4 => 2,
                 _ => 1,
             };
-            let (column, _score) = self.minimax(new_depth as i32, piece.clone());
+            let (column, _score) = self.search(new_depth as i32, piece);
             if column < 7 && self.place_piece(column, piece).is_some() {
                 done = true;
             }
@@ -128,6 +229,41 @@ impl Connect4 {
         done
     }
 
+    // move for the current player the way a person of this skill level would,
+    // returns the column played
+    pub fn skill_move(&mut self, level: usize) -> Option<usize> {
+        let position = Position::Connect4(self.clone());
+        match Skill::choose_move(&position, &Skill::level(level), self.threads, &mut rand::thread_rng()) {
+            Some(mv) if self.user_move(mv.column()) => Some(mv.column()),
+            _ => None,
+        }
+    }
+
+    // Same result as `minimax`, but with the root moves split across threads
+    fn search(&mut self, depth: i32, piece: Piece) -> (usize, i32) {
+        if self.threads <= 1 || depth == 0 || self.is_draw() || self.is_over() {
+            return self.minimax(depth, piece);
+        }
+        let next_piece = match piece {
+            Piece::R => Piece::Y,
+            Piece::Y => Piece::R,
+        };
+        let columns: Vec<usize> = (0..7).filter(|&column| self.board[0][column].is_none()).collect();
+        let scores = Search::split_root(self, &columns, self.threads, |game: &mut Connect4, column| {
+            let row = game.place_piece(column, piece).unwrap();
+            let (_, score) = game.minimax(depth - 1, next_piece);
+            game.remove_piece(row, column);
+            score
+        });
+        let mut best = (0, i32::MAX);
+        for (column, score) in columns.into_iter().zip(scores) {
+            if score < best.1 {
+                best = (column, score);
+            }
+        }
+        best
+    }
+
     fn minimax(&mut self, depth: i32, piece: Piece) -> (usize, i32) {
         if depth == 0 || self.is_draw() || self.is_over() {
             return (0, self.evaluate_board());
@@ -321,6 +457,11 @@ impl Connect4 {
         return false;
     }
 
+    // Number of pieces on the board, which is the number of moves played
+    pub fn move_count(&self) -> usize {
+        self.board.iter().flatten().filter(|cell| cell.is_some()).count()
+    }
+
     fn is_over(&self) -> bool {
         return match self.winner() {
             None => {
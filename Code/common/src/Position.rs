@@ -0,0 +1,236 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Connect4::{Connect4, Player as Connect4Player};
+use crate::TootOtto::{Piece, Player as TootOttoPlayer, TootOtto};
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum GameType {
+    Connect4,
+    TootOtto,
+}
+
+// The two seats of a game: Red and TOOT move first, Yellow and OTTO second
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Side {
+    First,
+    Second,
+}
+
+impl Side {
+    pub fn other(&self) -> Side {
+        match self {
+            Side::First => Side::Second,
+            Side::Second => Side::First,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Move {
+    Connect4(usize),
+    TootOtto(usize, Piece),
+}
+
+impl Move {
+    // Moves are written as the column number, followed by the letter for
+    // TOOT-OTTO, e.g. "3" or "3T"
+    pub fn parse(gametype: GameType, text: &str) -> Result<Move, String> {
+        let text = text.trim();
+        match gametype {
+            GameType::Connect4 => {
+                let column = text.parse::<usize>().map_err(|_| format!("invalid Connect4 move '{}'", text))?;
+                Ok(Move::Connect4(column))
+            }
+            GameType::TootOtto => {
+                let piece = match text.chars().last() {
+                    Some('T') | Some('t') => Piece::T,
+                    Some('O') | Some('o') => Piece::O,
+                    _ => return Err(format!("invalid TOOT-OTTO move '{}'", text)),
+                };
+                let column = text[..text.len() - 1].parse::<usize>().map_err(|_| format!("invalid TOOT-OTTO move '{}'", text))?;
+                Ok(Move::TootOtto(column, piece))
+            }
+        }
+    }
+
+    pub fn column(&self) -> usize {
+        match self {
+            Move::Connect4(column) => *column,
+            Move::TootOtto(column, _) => *column,
+        }
+    }
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Move::Connect4(column) => write!(f, "{}", column),
+            Move::TootOtto(column, Piece::T) => write!(f, "{}T", column),
+            Move::TootOtto(column, Piece::O) => write!(f, "{}O", column),
+        }
+    }
+}
+
+// Every line of four cells on the 6x7 board as (row, column) pairs
+pub fn lines() -> Vec<[(usize, usize); 4]> {
+    let mut lines = Vec::new();
+    for row in 0..6i32 {
+        for col in 0..7i32 {
+            for (d_row, d_col) in [(0, 1), (1, 0), (1, 1), (-1, 1)] {
+                if (0..6).contains(&(row + 3 * d_row)) && (0..7).contains(&(col + 3 * d_col)) {
+                    let cell = |k: i32| ((row + k * d_row) as usize, (col + k * d_col) as usize);
+                    lines.push([cell(0), cell(1), cell(2), cell(3)]);
+                }
+            }
+        }
+    }
+    lines
+}
+
+// A game of either kind, so puzzles, replays and the server can work with
+// both boards through the same moves and position strings
+#[derive(Clone)]
+pub enum Position {
+    Connect4(Connect4),
+    TootOtto(TootOtto),
+}
+
+impl Position {
+    pub fn new(gametype: GameType) -> Position {
+        match gametype {
+            GameType::Connect4 => Position::Connect4(Connect4::new()),
+            GameType::TootOtto => Position::TootOtto(TootOtto::new()),
+        }
+    }
+
+    pub fn parse(gametype: GameType, position: &str) -> Result<Position, String> {
+        match gametype {
+            GameType::Connect4 => Ok(Position::Connect4(Connect4::from_position(position)?)),
+            GameType::TootOtto => Ok(Position::TootOtto(TootOtto::from_position(position)?)),
+        }
+    }
+
+    pub fn gametype(&self) -> GameType {
+        match self {
+            Position::Connect4(_) => GameType::Connect4,
+            Position::TootOtto(_) => GameType::TootOtto,
+        }
+    }
+
+    pub fn side_to_move(&self) -> Side {
+        match self {
+            Position::Connect4(game) => match game.get_current_player() {
+                Connect4Player::Red => Side::First,
+                Connect4Player::Yellow => Side::Second,
+            },
+            Position::TootOtto(game) => match game.get_current_player() {
+                TootOttoPlayer::Toot => Side::First,
+                TootOttoPlayer::Otto => Side::Second,
+            },
+        }
+    }
+
+    pub fn winner(&self) -> Option<Side> {
+        match self {
+            Position::Connect4(game) => game.winner().map(|player| match player {
+                Connect4Player::Red => Side::First,
+                Connect4Player::Yellow => Side::Second,
+            }),
+            Position::TootOtto(game) => game.winner().map(|player| match player {
+                TootOttoPlayer::Toot => Side::First,
+                TootOttoPlayer::Otto => Side::Second,
+            }),
+        }
+    }
+
+    // The four cells that won the game, if it has been won
+    pub fn winning_line(&self) -> Option<[(usize, usize); 4]> {
+        match self {
+            Position::Connect4(game) => {
+                let grid = game.get_grid();
+                lines().into_iter().find(|line| {
+                    let first = grid[line[0].0][line[0].1];
+                    first.is_some() && line.iter().all(|&(row, col)| grid[row][col] == first)
+                })
+            }
+            Position::TootOtto(game) => {
+                let grid = game.get_grid();
+                let toot = [Piece::T, Piece::O, Piece::O, Piece::T];
+                let otto = [Piece::O, Piece::T, Piece::T, Piece::O];
+                lines().into_iter().find(|line| {
+                    let cells = line.map(|(row, col)| grid[row][col]);
+                    cells == toot.map(Some) || cells == otto.map(Some)
+                })
+            }
+        }
+    }
+
+    pub fn is_draw(&self) -> bool {
+        match self {
+            Position::Connect4(game) => game.is_draw(),
+            Position::TootOtto(game) => game.is_draw(),
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.winner().is_some() || self.is_draw()
+    }
+
+    pub fn valid_moves(&self) -> Vec<Move> {
+        if self.is_over() {
+            return Vec::new();
+        }
+        match self {
+            Position::Connect4(game) => (0..7).filter(|&col| game.top_row(col) < 6).map(Move::Connect4).collect(),
+            Position::TootOtto(game) => {
+                let mut moves = Vec::new();
+                for col in (0..7).filter(|&col| game.top_row(col) < 6) {
+                    moves.push(Move::TootOtto(col, Piece::T));
+                    moves.push(Move::TootOtto(col, Piece::O));
+                }
+                moves
+            }
+        }
+    }
+
+    pub fn play(&mut self, mv: Move) -> Result<(), String> {
+        if self.is_over() {
+            return Err("the game is already over".to_string());
+        }
+        if mv.column() >= 7 {
+            return Err(format!("column {} is off the board", mv.column()));
+        }
+        let done = match (self, mv) {
+            (Position::Connect4(game), Move::Connect4(col)) => game.top_row(col) < 6 && game.user_move(col),
+            (Position::TootOtto(game), Move::TootOtto(col, piece)) => game.play_move(col, piece),
+            _ => return Err(format!("'{}' is not a move in this game", mv)),
+        };
+        if done {
+            Ok(())
+        } else {
+            Err(format!("column {} is full", mv.column()))
+        }
+    }
+
+    pub fn undo(&mut self, mv: Move) {
+        match self {
+            Position::Connect4(game) => {
+                game.undo_move(mv.column());
+            }
+            Position::TootOtto(game) => {
+                game.undo_move(mv.column());
+            }
+        }
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Position::Connect4(game) => write!(f, "{}", game.to_position()),
+            Position::TootOtto(game) => write!(f, "{}", game.to_position()),
+        }
+    }
+}
@@ -0,0 +1,298 @@
+use rand::Rng;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+
+use crate::Position::{GameType, Move, Position};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+impl Difficulty {
+    pub fn from_moves(moves_to_win: usize) -> Difficulty {
+        match moves_to_win {
+            0 | 1 => Difficulty::Easy,
+            2 => Difficulty::Medium,
+            _ => Difficulty::Hard,
+        }
+    }
+}
+
+// A training position where the side to move has exactly one move that
+// forces a win within `moves_to_win` of its own moves
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Puzzle {
+    pub gametype: GameType,
+    pub position: String,
+    pub moves_to_win: usize,
+    pub difficulty: Difficulty,
+    pub solution: String,
+}
+
+// Result of checking a solution line against a puzzle
+#[derive(Clone, Debug, PartialEq)]
+pub enum Verdict {
+    // the line ends in a win for the solver within the move budget
+    Solved,
+    // every move so far keeps the forced win but the game is not won yet
+    Incomplete,
+    // the move at this ply lets the win slip away
+    Wrong(usize),
+    // the move at this ply cannot be played
+    Illegal(usize, String),
+}
+
+impl Puzzle {
+    pub fn get_position(&self) -> Result<Position, String> {
+        Position::parse(self.gametype, &self.position)
+    }
+
+    // Check a line of moves written as in `Move::parse`, alternating the
+    // solver's moves and the opponent's replies and starting with the solver
+    pub fn check_solution(&self, line: &[&str]) -> Result<Verdict, String> {
+        let mut position = self.get_position()?;
+        let solver = position.side_to_move();
+        let mut moves_left = self.moves_to_win;
+        for (ply, text) in line.iter().enumerate() {
+            let mv = match Move::parse(self.gametype, text) {
+                Ok(mv) => mv,
+                Err(err) => return Ok(Verdict::Illegal(ply, err)),
+            };
+            if position.side_to_move() == solver {
+                // illegal moves are reported when they are played below
+                if position.valid_moves().contains(&mv) && !wins_after(&mut position, mv, moves_left) {
+                    return Ok(Verdict::Wrong(ply));
+                }
+                moves_left = moves_left.saturating_sub(1);
+            }
+            if let Err(err) = position.play(mv) {
+                return Ok(Verdict::Illegal(ply, err));
+            }
+            if position.winner() == Some(solver) {
+                if ply + 1 < line.len() {
+                    return Ok(Verdict::Illegal(ply + 1, "the game is already over".to_string()));
+                }
+                return Ok(Verdict::Solved);
+            }
+        }
+        Ok(Verdict::Incomplete)
+    }
+}
+
+// Generate a puzzle by playing random moves from the start and keeping the
+// first position with a unique forced win in exactly `moves_to_win` moves.
+// Gives up with None after `attempts` random games.
+pub fn generate<R: Rng>(gametype: GameType, moves_to_win: usize, attempts: usize, rng: &mut R) -> Option<Puzzle> {
+    if moves_to_win == 0 {
+        return None;
+    }
+    for _ in 0..attempts {
+        let mut position = Position::new(gametype);
+        // random game length, every position along the way is a candidate
+        let plies = rng.gen_range(6..36);
+        for _ in 0..plies {
+            let moves = position.valid_moves();
+            match moves.choose(rng) {
+                Some(mv) => {
+                    position.play(*mv).unwrap();
+                }
+                None => break,
+            }
+            if let Some(solution) = unique_win(&mut position, moves_to_win) {
+                return Some(Puzzle {
+                    gametype,
+                    position: position.to_string(),
+                    moves_to_win,
+                    difficulty: Difficulty::from_moves(moves_to_win),
+                    solution: solution.to_string(),
+                });
+            }
+        }
+    }
+    None
+}
+
+// Return the only move that wins within `moves` moves, provided there is
+// exactly one and no faster win exists
+pub fn unique_win(position: &mut Position, moves: usize) -> Option<Move> {
+    if position.is_over() || (moves > 1 && forced_win(position, moves - 1)) {
+        return None;
+    }
+    let mut winning = position.valid_moves().into_iter().filter(|mv| wins_after(position, *mv, moves));
+    match (winning.next(), winning.next()) {
+        (Some(mv), None) => Some(mv),
+        _ => None,
+    }
+}
+
+// Exhaustive search: can the side to move force a win within `moves` of its own moves?
+pub fn forced_win(position: &mut Position, moves: usize) -> bool {
+    if moves == 0 || position.is_over() {
+        return false;
+    }
+    position.valid_moves().into_iter().any(|mv| wins_after(position, mv, moves))
+}
+
+// Does playing `mv` keep a forced win within `moves` moves, the move itself included?
+fn wins_after(position: &mut Position, mv: Move, moves: usize) -> bool {
+    let mover = position.side_to_move();
+    if moves == 0 || position.play(mv).is_err() {
+        return false;
+    }
+    let result = match position.winner() {
+        Some(side) => side == mover,
+        None if position.is_draw() || moves == 1 => false,
+        None => {
+            // every reply by the opponent must still leave us a forced win
+            let replies = position.valid_moves();
+            replies.into_iter().all(|reply| {
+                position.play(reply).unwrap();
+                let won = match position.winner() {
+                    Some(side) => side == mover,
+                    None => forced_win(position, moves - 1),
+                };
+                position.undo(reply);
+                won
+            })
+        }
+    };
+    position.undo(mv);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    // Play a line of moves from the empty board
+    fn position(gametype: GameType, line: &[&str]) -> Position {
+        let mut position = Position::new(gametype);
+        for text in line {
+            position.play(Move::parse(gametype, text).unwrap()).unwrap();
+        }
+        position
+    }
+
+    fn puzzle(gametype: GameType, line: &[&str], moves_to_win: usize) -> Puzzle {
+        let position = position(gametype, line);
+        let solution = unique_win(&mut position.clone(), moves_to_win).unwrap();
+        Puzzle {
+            gametype,
+            position: position.to_string(),
+            moves_to_win,
+            difficulty: Difficulty::from_moves(moves_to_win),
+            solution: solution.to_string(),
+        }
+    }
+
+    // Red holds columns 0 to 2 of the bottom row, column 3 is the only win
+    const ONE_WIN: [&str; 6] = ["0", "0", "1", "1", "2", "6"];
+    // Red holds columns 1 to 3 of the bottom row and wins at 0 or 4
+    const TWO_WINS: [&str; 6] = ["1", "1", "2", "2", "3", "6"];
+    // TOOT has "TOO" in columns 0 to 2 of the bottom row, a T at 3 spells TOOT
+    const TOOT_WIN: [&str; 6] = ["0T", "6O", "1O", "6O", "2O", "5T"];
+
+    #[test]
+    fn finds_the_only_forced_win() {
+        let mut position = position(GameType::Connect4, &ONE_WIN);
+        assert_eq!(unique_win(&mut position, 1), Some(Move::Connect4(3)));
+    }
+
+    #[test]
+    fn rejects_positions_with_several_wins() {
+        let mut position = position(GameType::Connect4, &TWO_WINS);
+        assert!(forced_win(&mut position, 1));
+        assert_eq!(unique_win(&mut position, 1), None);
+    }
+
+    #[test]
+    fn rejects_positions_with_a_faster_win() {
+        let mut position = position(GameType::Connect4, &ONE_WIN);
+        assert_eq!(unique_win(&mut position, 2), None);
+    }
+
+    #[test]
+    fn checks_solutions() {
+        let puzzle = puzzle(GameType::Connect4, &ONE_WIN, 1);
+        assert_eq!(puzzle.check_solution(&["3"]), Ok(Verdict::Solved));
+        assert_eq!(puzzle.check_solution(&["5"]), Ok(Verdict::Wrong(0)));
+        assert_eq!(puzzle.check_solution(&[]), Ok(Verdict::Incomplete));
+        assert!(matches!(puzzle.check_solution(&["x"]), Ok(Verdict::Illegal(0, _))));
+        assert!(matches!(puzzle.check_solution(&["3", "4"]), Ok(Verdict::Illegal(1, _))));
+    }
+
+    #[test]
+    fn checks_toot_otto_solutions() {
+        let mut position = position(GameType::TootOtto, &TOOT_WIN);
+        assert_eq!(unique_win(&mut position, 1), Some(Move::parse(GameType::TootOtto, "3T").unwrap()));
+        let puzzle = puzzle(GameType::TootOtto, &TOOT_WIN, 1);
+        assert_eq!(puzzle.check_solution(&["3T"]), Ok(Verdict::Solved));
+        assert_eq!(puzzle.check_solution(&["3O"]), Ok(Verdict::Wrong(0)));
+        assert!(matches!(puzzle.check_solution(&["3"]), Ok(Verdict::Illegal(0, _))));
+    }
+
+    #[test]
+    fn checks_solutions_over_several_moves() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for gametype in [GameType::Connect4, GameType::TootOtto] {
+            let puzzle = generate(gametype, 2, 500, &mut rng).unwrap();
+            let mut position = puzzle.get_position().unwrap();
+            let solution = Move::parse(gametype, &puzzle.solution).unwrap();
+            let wrong = position.valid_moves().into_iter().find(|mv| *mv != solution).unwrap().to_string();
+            assert_eq!(puzzle.check_solution(&[&wrong]), Ok(Verdict::Wrong(0)));
+            assert_eq!(puzzle.check_solution(&[&puzzle.solution]), Ok(Verdict::Incomplete));
+
+            // any reply still leaves the solver a win on the next move
+            position.play(solution).unwrap();
+            let reply = position.valid_moves()[0];
+            position.play(reply).unwrap();
+            let (winning, missing): (Vec<Move>, Vec<Move>) =
+                position.valid_moves().into_iter().partition(|mv| wins_after(&mut position.clone(), *mv, 1));
+            let line = [puzzle.solution.clone(), reply.to_string(), winning[0].to_string()];
+            let line: Vec<&str> = line.iter().map(|mv| mv.as_str()).collect();
+            assert_eq!(puzzle.check_solution(&line[..2]), Ok(Verdict::Incomplete));
+            assert_eq!(puzzle.check_solution(&line), Ok(Verdict::Solved));
+            let missed = missing[0].to_string();
+            assert_eq!(puzzle.check_solution(&[line[0], line[1], &missed]), Ok(Verdict::Wrong(2)));
+        }
+    }
+
+    #[test]
+    fn rejects_positions_a_game_cannot_reach() {
+        // five red pieces against one yellow
+        assert!(Position::parse(GameType::Connect4, "......./......./......./......./R....../RRR.R.Y r").is_err());
+        // yellow to move with equal counts
+        assert!(Position::parse(GameType::Connect4, "......./......./......./......./......./RY..... y").is_err());
+        // red has already won along the bottom row
+        assert!(Position::parse(GameType::Connect4, "......./......./......./......./YYY..../RRRR... y").is_err());
+        // one piece on the board, so it is OTTO's turn
+        assert!(Position::parse(GameType::TootOtto, "......./......./......./......./......./...T... t").is_err());
+        // TOOT is already spelled
+        assert!(Position::parse(GameType::TootOtto, "......./......./......./......./......./TOOT... t").is_err());
+
+        let connect4 = position(GameType::Connect4, &ONE_WIN);
+        assert!(Position::parse(GameType::Connect4, &connect4.to_string()).is_ok());
+        let toot_otto = position(GameType::TootOtto, &TOOT_WIN);
+        assert!(Position::parse(GameType::TootOtto, &toot_otto.to_string()).is_ok());
+    }
+
+    #[test]
+    fn generated_puzzles_have_one_solution() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for gametype in [GameType::Connect4, GameType::TootOtto] {
+            for moves_to_win in 1..=2 {
+                let puzzle = generate(gametype, moves_to_win, 500, &mut rng).unwrap();
+                let mut position = puzzle.get_position().unwrap();
+                assert_eq!(unique_win(&mut position, moves_to_win).map(|mv| mv.to_string()), Some(puzzle.solution.clone()));
+                if moves_to_win == 1 {
+                    assert_eq!(puzzle.check_solution(&[&puzzle.solution]), Ok(Verdict::Solved));
+                }
+            }
+        }
+    }
+}
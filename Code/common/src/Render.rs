@@ -0,0 +1,317 @@
+// Draws a board as SVG (or PNG with the `png` feature) using the same colours
+// as the canvas in the frontend, so shared images and thumbnails look the same.
+
+use crate::Connect4::Piece as Connect4Piece;
+use crate::Position::Position;
+use crate::TootOtto::Piece as TootOttoPiece;
+
+const BOARD: &str = "#00bfff";
+const EMPTY: &str = "#ffffff";
+const INK: &str = "#111111";
+
+// bounds on the cell size of board images, in pixels
+pub const MIN_CELL: u32 = 16;
+pub const MAX_CELL: u32 = 200;
+
+#[derive(Clone, Debug)]
+pub struct RenderOptions {
+    // column of the last move, its top piece gets a ring around it
+    pub last_move: Option<usize>,
+    // draw a line through the four pieces that won the game
+    pub winning_line: bool,
+    // width and height of one cell in pixels
+    pub cell_size: u32,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            last_move: None,
+            winning_line: true,
+            cell_size: 75,
+        }
+    }
+}
+
+// What is drawn in one cell: fill colour and the letter on the piece
+fn cell(position: &Position, row: usize, col: usize) -> Option<(&'static str, char)> {
+    match position {
+        Position::Connect4(game) => game.get_grid()[row][col].map(|piece| match piece {
+            Connect4Piece::R => ("#ff0000", 'R'),
+            Connect4Piece::Y => ("#ffff00", 'Y'),
+        }),
+        Position::TootOtto(game) => game.get_grid()[row][col].map(|piece| match piece {
+            TootOttoPiece::T => ("#99ffcc", 'T'),
+            TootOttoPiece::O => ("#ffff99", 'O'),
+        }),
+    }
+}
+
+// Row of the top piece in a column, if the column has any
+fn top_piece(position: &Position, col: usize) -> Option<usize> {
+    if col >= 7 {
+        return None;
+    }
+    (0..6).find(|&row| cell(position, row, col).is_some())
+}
+
+fn centre(options: &RenderOptions, row: usize, col: usize) -> (f64, f64) {
+    let size = options.cell_size as f64;
+    (size * (col as f64 + 0.5), size * (row as f64 + 0.5))
+}
+
+pub fn size(options: &RenderOptions) -> (u32, u32) {
+    (7 * options.cell_size, 6 * options.cell_size)
+}
+
+pub fn svg(position: &Position, options: &RenderOptions) -> String {
+    let (width, height) = size(options);
+    let size = options.cell_size as f64;
+    let radius = size / 3.0;
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">",
+        width, height, width, height
+    );
+    svg += &format!("<rect width=\"{}\" height=\"{}\" rx=\"{}\" fill=\"{}\"/>", width, height, size / 6.0, BOARD);
+    for row in 0..6 {
+        for col in 0..7 {
+            let (x, y) = centre(options, row, col);
+            match cell(position, row, col) {
+                Some((fill, letter)) => {
+                    svg += &format!("<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{}\"/>", x, y, radius, fill);
+                    svg += &format!(
+                        "<text x=\"{}\" y=\"{}\" font-family=\"serif\" font-weight=\"bold\" font-size=\"{}\" text-anchor=\"middle\" dominant-baseline=\"central\" fill=\"{}\">{}</text>",
+                        x, y, radius, INK, letter
+                    );
+                }
+                None => {
+                    svg += &format!("<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{}\"/>", x, y, radius, EMPTY);
+                }
+            }
+        }
+    }
+    if let Some(col) = options.last_move {
+        if let Some(row) = top_piece(position, col) {
+            let (x, y) = centre(options, row, col);
+            svg += &format!(
+                "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"{}\"/>",
+                x, y, radius + size / 15.0, INK, size / 20.0
+            );
+        }
+    }
+    if options.winning_line {
+        if let Some(line) = position.winning_line() {
+            let (x1, y1) = centre(options, line[0].0, line[0].1);
+            let (x2, y2) = centre(options, line[3].0, line[3].1);
+            svg += &format!(
+                "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"{}\" stroke-width=\"{}\" stroke-linecap=\"round\" opacity=\"0.6\"/>",
+                x1, y1, x2, y2, INK, size / 8.0
+            );
+        }
+    }
+    svg += "</svg>";
+    svg
+}
+
+#[cfg(feature = "png")]
+pub fn png(position: &Position, options: &RenderOptions) -> Result<Vec<u8>, String> {
+    let (width, height) = size(options);
+    let mut canvas = Canvas {
+        width,
+        height,
+        pixels: vec![0; (width * height * 3) as usize],
+    };
+    let size = options.cell_size as f64;
+    let radius = size / 3.0;
+    canvas.fill_rect(0.0, 0.0, width as f64, height as f64, BOARD);
+    for row in 0..6 {
+        for col in 0..7 {
+            let (x, y) = centre(options, row, col);
+            match cell(position, row, col) {
+                Some((fill, letter)) => {
+                    canvas.fill_circle(x, y, radius, fill);
+                    canvas.draw_letter(letter, x, y, radius * 0.9);
+                }
+                None => canvas.fill_circle(x, y, radius, EMPTY),
+            }
+        }
+    }
+    if let Some(col) = options.last_move {
+        if let Some(row) = top_piece(position, col) {
+            let (x, y) = centre(options, row, col);
+            canvas.stroke_circle(x, y, radius + size / 15.0, size / 20.0, INK);
+        }
+    }
+    if options.winning_line {
+        if let Some(line) = position.winning_line() {
+            let (x1, y1) = centre(options, line[0].0, line[0].1);
+            let (x2, y2) = centre(options, line[3].0, line[3].1);
+            canvas.stroke_line(x1, y1, x2, y2, size / 8.0, INK);
+        }
+    }
+    canvas.encode()
+}
+
+// A tiny RGB rasteriser, enough for discs, rings, lines and block letters
+#[cfg(feature = "png")]
+struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+#[cfg(feature = "png")]
+impl Canvas {
+    fn rgb(colour: &str) -> [u8; 3] {
+        let value = u32::from_str_radix(colour.trim_start_matches('#'), 16).unwrap_or(0);
+        [(value >> 16) as u8, (value >> 8) as u8, value as u8]
+    }
+
+    // colour every pixel whose centre is inside the shape, within a bounding box
+    fn paint(&mut self, bounds: (f64, f64, f64, f64), colour: &str, inside: impl Fn(f64, f64) -> bool) {
+        let rgb = Self::rgb(colour);
+        let (left, top, right, bottom) = bounds;
+        let x_range = (left.floor().max(0.0) as u32)..(right.ceil().min(self.width as f64) as u32);
+        for y in (top.floor().max(0.0) as u32)..(bottom.ceil().min(self.height as f64) as u32) {
+            for x in x_range.clone() {
+                if inside(x as f64 + 0.5, y as f64 + 0.5) {
+                    let i = ((y * self.width + x) * 3) as usize;
+                    self.pixels[i..i + 3].copy_from_slice(&rgb);
+                }
+            }
+        }
+    }
+
+    fn fill_rect(&mut self, x: f64, y: f64, width: f64, height: f64, colour: &str) {
+        self.paint((x, y, x + width, y + height), colour, |_, _| true);
+    }
+
+    fn fill_circle(&mut self, cx: f64, cy: f64, r: f64, colour: &str) {
+        self.paint((cx - r, cy - r, cx + r, cy + r), colour, |x, y| (x - cx).powi(2) + (y - cy).powi(2) <= r * r);
+    }
+
+    fn stroke_circle(&mut self, cx: f64, cy: f64, r: f64, thickness: f64, colour: &str) {
+        let (inner, outer) = (r - thickness / 2.0, r + thickness / 2.0);
+        self.paint((cx - outer, cy - outer, cx + outer, cy + outer), colour, |x, y| {
+            let d = ((x - cx).powi(2) + (y - cy).powi(2)).sqrt();
+            d >= inner && d <= outer
+        });
+    }
+
+    fn stroke_line(&mut self, x1: f64, y1: f64, x2: f64, y2: f64, thickness: f64, colour: &str) {
+        let half = thickness / 2.0;
+        let bounds = (x1.min(x2) - half, y1.min(y2) - half, x1.max(x2) + half, y1.max(y2) + half);
+        self.paint(bounds, colour, |x, y| {
+            // distance from the pixel to the segment, which gives round caps
+            let (dx, dy) = (x2 - x1, y2 - y1);
+            let length = dx * dx + dy * dy;
+            let t = if length == 0.0 { 0.0 } else { (((x - x1) * dx + (y - y1) * dy) / length).clamp(0.0, 1.0) };
+            (x - (x1 + t * dx)).powi(2) + (y - (y1 + t * dy)).powi(2) <= half * half
+        });
+    }
+
+    // block letters made of strokes in a unit box centred on the piece
+    fn draw_letter(&mut self, letter: char, cx: f64, cy: f64, size: f64) {
+        let strokes: &[(f64, f64, f64, f64)] = match letter {
+            'T' => &[(0.0, 0.0, 1.0, 0.0), (0.5, 0.0, 0.5, 1.0)],
+            'Y' => &[(0.0, 0.0, 0.5, 0.5), (1.0, 0.0, 0.5, 0.5), (0.5, 0.5, 0.5, 1.0)],
+            'R' => &[(0.0, 0.0, 0.0, 1.0), (0.0, 0.0, 0.8, 0.0), (0.8, 0.0, 0.8, 0.5), (0.8, 0.5, 0.0, 0.5), (0.3, 0.5, 0.9, 1.0)],
+            'O' => {
+                self.stroke_circle(cx, cy, size * 0.35, size * 0.12, INK);
+                return;
+            }
+            _ => &[],
+        };
+        let (left, top, scale) = (cx - size * 0.3, cy - size * 0.35, size * 0.7);
+        for (x1, y1, x2, y2) in strokes {
+            self.stroke_line(left + x1 * scale * 0.85, top + y1 * scale, left + x2 * scale * 0.85, top + y2 * scale, size * 0.12, INK);
+        }
+    }
+
+    fn encode(&self) -> Result<Vec<u8>, String> {
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut bytes, self.width, self.height);
+            encoder.set_color(png::ColorType::Rgb);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header().map_err(|err| err.to_string())?;
+            writer.write_image_data(&self.pixels).map_err(|err| err.to_string())?;
+        }
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Position::{GameType, Move};
+
+    fn position(gametype: GameType, line: &[&str]) -> Position {
+        let mut position = Position::new(gametype);
+        for text in line {
+            position.play(Move::parse(gametype, text).unwrap()).unwrap();
+        }
+        position
+    }
+
+    fn count(svg: &str, fill: &str) -> usize {
+        svg.matches(&format!("fill=\"{}\"", fill)).count()
+    }
+
+    #[test]
+    fn draws_connect4_pieces() {
+        let position = position(GameType::Connect4, &["3", "3", "4"]);
+        let svg = svg(&position, &RenderOptions { last_move: Some(4), ..RenderOptions::default() });
+        assert!(svg.starts_with("<svg") && svg.ends_with("</svg>"));
+        assert!(svg.contains("width=\"525\" height=\"450\""));
+        assert_eq!(count(&svg, "#ff0000"), 2);
+        assert_eq!(count(&svg, "#ffff00"), 1);
+        assert_eq!(count(&svg, EMPTY), 39);
+        // the ring around the last move and no winning line yet
+        assert_eq!(count(&svg, "none"), 1);
+        assert!(!svg.contains("<line"));
+    }
+
+    #[test]
+    fn draws_toot_otto_pieces_and_the_winning_line() {
+        let position = position(GameType::TootOtto, &["0T", "1O", "2O", "3T"]);
+        let plain = svg(&position, &RenderOptions { winning_line: false, ..RenderOptions::default() });
+        assert!(!plain.contains("<line"));
+        let svg = svg(&position, &RenderOptions::default());
+        assert_eq!(count(&svg, "#99ffcc"), 2);
+        assert_eq!(count(&svg, "#ffff99"), 2);
+        assert_eq!(svg.matches(">T</text>").count(), 2);
+        assert_eq!(svg.matches(">O</text>").count(), 2);
+        assert!(svg.contains("<line"));
+    }
+
+    #[test]
+    fn scales_with_the_cell_size() {
+        let position = position(GameType::Connect4, &["3"]);
+        for cell_size in [MIN_CELL, MAX_CELL] {
+            let options = RenderOptions { cell_size, ..RenderOptions::default() };
+            assert_eq!(size(&options), (7 * cell_size, 6 * cell_size));
+            let svg = svg(&position, &options);
+            assert!(svg.contains(&format!("width=\"{}\" height=\"{}\"", 7 * cell_size, 6 * cell_size)));
+        }
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn encodes_png_of_the_board_size() {
+        let position = position(GameType::Connect4, &["3"]);
+        for cell_size in [MIN_CELL, MAX_CELL] {
+            let options = RenderOptions { cell_size, ..RenderOptions::default() };
+            let bytes = png(&position, &options).unwrap();
+            let mut reader = png::Decoder::new(&bytes[..]).read_info().unwrap();
+            let mut pixels = vec![0; reader.output_buffer_size()];
+            let frame = reader.next_frame(&mut pixels).unwrap();
+            assert_eq!((frame.width, frame.height), size(&options));
+            // inside the red piece at the bottom of column 3, clear of its letter
+            let (x, y) = centre(&options, 5, 3);
+            let (x, y) = ((x - cell_size as f64 / 4.0) as u32, y as u32);
+            let i = ((y * frame.width + x) * 3) as usize;
+            assert_eq!(&pixels[i..i + 3], &[0xff, 0, 0]);
+        }
+    }
+}
@@ -0,0 +1,67 @@
+// Helpers for spreading a game tree search across threads. The browser build
+// has no threads, so on wasm everything runs on the calling thread.
+
+// Number of threads worth using on this machine
+pub fn available_threads() -> usize {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        1
+    }
+}
+
+// Root splitting: score every root move with `score`, handing the moves out
+// round-robin to up to `threads` workers that each search their own copy of
+// the game. Scores come back in the same order as `moves`, so callers pick
+// the same move a single threaded search would.
+pub fn split_root<G, M, F>(game: &G, moves: &[M], threads: usize, score: F) -> Vec<i32>
+where
+    G: Clone + Sync,
+    M: Copy + Sync,
+    F: Fn(&mut G, M) -> i32 + Sync,
+{
+    let workers = threads.min(moves.len()).min(available_threads());
+    if workers <= 1 {
+        let mut copy = game.clone();
+        return moves.iter().map(|mv| score(&mut copy, *mv)).collect();
+    }
+    split_across(game, moves, workers, &score)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn split_across<G, M, F>(game: &G, moves: &[M], workers: usize, score: &F) -> Vec<i32>
+where
+    G: Clone + Sync,
+    M: Copy + Sync,
+    F: Fn(&mut G, M) -> i32 + Sync,
+{
+    let mut scores = vec![0; moves.len()];
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers).map(|worker| {
+            scope.spawn(move || {
+                let mut copy = game.clone();
+                (worker..moves.len()).step_by(workers).map(|i| (i, score(&mut copy, moves[i]))).collect::<Vec<_>>()
+            })
+        }).collect();
+        for handle in handles {
+            for (i, value) in handle.join().expect("search thread panicked") {
+                scores[i] = value;
+            }
+        }
+    });
+    scores
+}
+
+#[cfg(target_arch = "wasm32")]
+fn split_across<G, M, F>(game: &G, moves: &[M], _workers: usize, score: &F) -> Vec<i32>
+where
+    G: Clone + Sync,
+    M: Copy + Sync,
+    F: Fn(&mut G, M) -> i32 + Sync,
+{
+    let mut copy = game.clone();
+    moves.iter().map(|mv| score(&mut copy, *mv)).collect()
+}
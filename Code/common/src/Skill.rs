@@ -0,0 +1,333 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use rand::Rng;
+
+use crate::Connect4::Piece as Connect4Piece;
+use crate::Position::{lines, GameType, Move, Position, Side};
+use crate::Search;
+use crate::TootOtto::Piece as TootOttoPiece;
+
+// score of a won position; a win found n plies ahead scores WIN - n
+pub const WIN: i32 = 1_000_000;
+
+// One rung of the AI ladder. Every level runs the same alpha-beta search and
+// then plays like a person would: it only notices wins and losses that happen
+// within `horizon` plies, picks among close moves at random (`temperature`, in
+// evaluation points) and now and then just plays a random move (`blunder_rate`).
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct SkillLevel {
+    pub level: usize,
+    pub name: &'static str,
+    pub connect4_depth: usize,
+    pub toototto_depth: usize,
+    pub horizon: usize,
+    pub temperature: f64,
+    pub blunder_rate: f64,
+    // measured with `calibrate` over 100 games between neighbouring levels,
+    // anchored at 800 for level 1; `cargo run --release --example calibrate`
+    // repeats the run
+    pub connect4_elo: u32,
+    pub toototto_elo: u32,
+}
+
+pub const LEVELS: [SkillLevel; 8] = [
+    SkillLevel { level: 1, name: "Beginner", connect4_depth: 1, toototto_depth: 1, horizon: 1, temperature: 400.0, blunder_rate: 0.5, connect4_elo: 800, toototto_elo: 800 },
+    SkillLevel { level: 2, name: "Novice", connect4_depth: 2, toototto_depth: 2, horizon: 2, temperature: 300.0, blunder_rate: 0.4, connect4_elo: 900, toototto_elo: 956 },
+    SkillLevel { level: 3, name: "Casual", connect4_depth: 2, toototto_depth: 2, horizon: 2, temperature: 100.0, blunder_rate: 0.2, connect4_elo: 1015, toototto_elo: 1103 },
+    SkillLevel { level: 4, name: "Intermediate", connect4_depth: 3, toototto_depth: 2, horizon: 3, temperature: 60.0, blunder_rate: 0.12, connect4_elo: 1162, toototto_elo: 1214 },
+    SkillLevel { level: 5, name: "Skilled", connect4_depth: 4, toototto_depth: 3, horizon: 4, temperature: 30.0, blunder_rate: 0.07, connect4_elo: 1367, toototto_elo: 1405 },
+    SkillLevel { level: 6, name: "Advanced", connect4_depth: 5, toototto_depth: 3, horizon: 5, temperature: 15.0, blunder_rate: 0.03, connect4_elo: 1631, toototto_elo: 1565 },
+    SkillLevel { level: 7, name: "Expert", connect4_depth: 6, toototto_depth: 4, horizon: 6, temperature: 5.0, blunder_rate: 0.01, connect4_elo: 1846, toototto_elo: 1989 },
+    SkillLevel { level: 8, name: "Professional", connect4_depth: 7, toototto_depth: 4, horizon: 7, temperature: 0.0, blunder_rate: 0.0, connect4_elo: 2010, toototto_elo: 2108 },
+];
+
+// The levels the browser offers. It searches on the page's only thread, and
+// deeper levels freeze the page between moves; the server's `POST /ai/move`
+// plays all of them.
+pub const BROWSER_LEVELS: usize = 6;
+
+// Look up a level by number, clamping to the ends of the ladder
+pub fn level(level: usize) -> SkillLevel {
+    LEVELS[level.clamp(1, LEVELS.len()) - 1]
+}
+
+impl SkillLevel {
+    pub fn depth(&self, gametype: GameType) -> usize {
+        match gametype {
+            GameType::Connect4 => self.connect4_depth,
+            GameType::TootOtto => self.toototto_depth,
+        }
+    }
+
+    pub fn elo(&self, gametype: GameType) -> u32 {
+        match gametype {
+            GameType::Connect4 => self.connect4_elo,
+            GameType::TootOtto => self.toototto_elo,
+        }
+    }
+}
+
+// Pick the move a player of this level would make for the side to move
+pub fn choose_move<R: Rng>(position: &Position, skill: &SkillLevel, threads: usize, rng: &mut R) -> Option<Move> {
+    let moves = ordered_moves(position);
+    if moves.is_empty() {
+        return None;
+    }
+    if rng.gen::<f64>() < skill.blunder_rate {
+        return Some(moves[rng.gen_range(0..moves.len())]);
+    }
+    let scores = score_moves(position, &moves, skill.depth(position.gametype()), skill.horizon, threads);
+    let best = *scores.iter().max().unwrap();
+    if skill.temperature <= 0.0 {
+        let index = scores.iter().position(|&score| score == best).unwrap();
+        return Some(moves[index]);
+    }
+    // softmax over the scores, relative to the best so the weights stay finite
+    let weights: Vec<f64> = scores.iter().map(|&score| ((score - best) as f64 / skill.temperature).exp()).collect();
+    let mut pick = rng.gen::<f64>() * weights.iter().sum::<f64>();
+    for (mv, weight) in moves.iter().zip(weights) {
+        if pick < weight {
+            return Some(*mv);
+        }
+        pick -= weight;
+    }
+    moves.last().copied()
+}
+
+// never raised, for searches that always run to the end
+static NEVER: AtomicBool = AtomicBool::new(false);
+
+// Exact score of every root move for the side to move, best is highest
+pub fn score_moves(position: &Position, moves: &[Move], depth: usize, horizon: usize, threads: usize) -> Vec<i32> {
+    score_moves_until(position, moves, depth, horizon, threads, &NEVER).expect("the search is never stopped")
+}
+
+// `score_moves` that gives up as soon as `stop` is raised, returning None
+pub fn score_moves_until(position: &Position, moves: &[Move], depth: usize, horizon: usize, threads: usize, stop: &AtomicBool) -> Option<Vec<i32>> {
+    let depth = depth.max(1);
+    let scores = Search::split_root(position, moves, threads, |game: &mut Position, mv| {
+        game.play(mv).unwrap();
+        let score = -negamax(game, depth - 1, 1, horizon, -WIN - 1, WIN + 1, stop);
+        game.undo(mv);
+        score
+    });
+    if stop.load(Ordering::Relaxed) { None } else { Some(scores) }
+}
+
+// Score for the side to move; wins found further than `horizon` plies from
+// the root go unnoticed and the position is judged on its looks instead.
+// Once `stop` is raised every node returns at once and the score means nothing.
+fn negamax(position: &mut Position, depth: usize, ply: usize, horizon: usize, mut alpha: i32, beta: i32, stop: &AtomicBool) -> i32 {
+    if stop.load(Ordering::Relaxed) {
+        return 0;
+    }
+    if let Some(side) = position.winner() {
+        if ply > horizon {
+            return evaluate(position);
+        }
+        let score = WIN - ply as i32;
+        return if side == position.side_to_move() { score } else { -score };
+    }
+    if position.is_draw() {
+        return 0;
+    }
+    if depth == 0 {
+        return evaluate(position);
+    }
+    let mut best = -WIN - 1;
+    for mv in ordered_moves(position) {
+        position.play(mv).unwrap();
+        let score = -negamax(position, depth - 1, ply + 1, horizon, -beta, -alpha, stop);
+        position.undo(mv);
+        best = best.max(score);
+        alpha = alpha.max(score);
+        if alpha >= beta {
+            break;
+        }
+    }
+    best
+}
+
+// Centre columns first, they are usually the better moves and prune more
+pub fn ordered_moves(position: &Position) -> Vec<Move> {
+    let mut moves = position.valid_moves();
+    moves.sort_by_key(|mv| (mv.column() as i32 - 3).abs());
+    moves
+}
+
+// Static evaluation for the side to move from every window of four cells
+pub fn evaluate(position: &Position) -> i32 {
+    let me = position.side_to_move();
+    let mut score = 0;
+    match position {
+        Position::Connect4(game) => {
+            let grid = game.get_grid();
+            let mine = if me == Side::First { Connect4Piece::R } else { Connect4Piece::Y };
+            for window in lines() {
+                let cells = window.map(|(row, col)| grid[row][col]);
+                let own = cells.iter().filter(|cell| **cell == Some(mine)).count();
+                let empty = cells.iter().filter(|cell| cell.is_none()).count();
+                let theirs = 4 - own - empty;
+                score += match (own, theirs) {
+                    (1, 0) => 1,
+                    (2, 0) => 10,
+                    (3, 0) => 50,
+                    (0, 1) => -1,
+                    (0, 2) => -10,
+                    (0, 3) => -50,
+                    _ => 0,
+                };
+            }
+            for row in grid.iter() {
+                match row[3] {
+                    Some(piece) if piece == mine => score += 3,
+                    Some(_) => score -= 3,
+                    None => {}
+                }
+            }
+        }
+        Position::TootOtto(game) => {
+            let grid = game.get_grid();
+            let toot = [TootOttoPiece::T, TootOttoPiece::O, TootOttoPiece::O, TootOttoPiece::T];
+            let otto = [TootOttoPiece::O, TootOttoPiece::T, TootOttoPiece::T, TootOttoPiece::O];
+            for window in lines() {
+                let cells = window.map(|(row, col)| grid[row][col]);
+                // how far each word is along in this window, if still possible
+                let progress = |word: &[TootOttoPiece; 4]| {
+                    if cells.iter().zip(word).all(|(cell, letter)| cell.is_none() || *cell == Some(*letter)) {
+                        cells.iter().filter(|cell| cell.is_some()).count()
+                    } else {
+                        0
+                    }
+                };
+                let value = |letters: usize| match letters {
+                    1 => 1,
+                    2 => 10,
+                    3 => 50,
+                    _ => 0,
+                };
+                let toot_score = value(progress(&toot)) - value(progress(&otto));
+                score += if me == Side::First { toot_score } else { -toot_score };
+            }
+        }
+    }
+    score
+}
+
+// Play one game between two levels from the start; returns the winning side
+pub fn play_game<R: Rng>(gametype: GameType, first: &SkillLevel, second: &SkillLevel, rng: &mut R) -> Option<Side> {
+    let mut position = Position::new(gametype);
+    while !position.is_over() {
+        let skill = if position.side_to_move() == Side::First { first } else { second };
+        let mv = choose_move(&position, skill, Search::available_threads(), rng)?;
+        position.play(mv).unwrap();
+    }
+    position.winner()
+}
+
+// Measure the Elo of every level in `levels` (usually `LEVELS`) by playing
+// `games` games between each pair of neighbours, alternating who moves first.
+// The first level is fixed at 800 and each level is rated from its score
+// against the one before it.
+pub fn calibrate<R: Rng>(gametype: GameType, levels: &[SkillLevel], games: usize, rng: &mut R) -> Vec<f64> {
+    let mut ratings = vec![800.0];
+    for pair in levels.windows(2) {
+        let (lower, upper) = (&pair[0], &pair[1]);
+        let mut points = 0.0;
+        for game in 0..games {
+            let upper_side = if game % 2 == 0 { Side::First } else { Side::Second };
+            let winner = if upper_side == Side::First {
+                play_game(gametype, upper, lower, rng)
+            } else {
+                play_game(gametype, lower, upper, rng)
+            };
+            points += match winner {
+                Some(side) if side == upper_side => 1.0,
+                Some(_) => 0.0,
+                None => 0.5,
+            };
+        }
+        // keep a perfect score from turning into an infinite gap
+        let fraction = (points / games as f64).clamp(0.5 / games as f64, 1.0 - 0.5 / games as f64);
+        let gap = 400.0 * (fraction / (1.0 - fraction)).log10();
+        ratings.push(ratings.last().unwrap() + gap);
+    }
+    ratings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    // Play a line of moves from the empty board
+    fn position(gametype: GameType, line: &[&str]) -> Position {
+        let mut position = Position::new(gametype);
+        for text in line {
+            position.play(Move::parse(gametype, text).unwrap()).unwrap();
+        }
+        position
+    }
+
+    #[test]
+    fn browser_levels_leave_some_to_the_server() {
+        assert!(BROWSER_LEVELS > 0 && BROWSER_LEVELS < LEVELS.len());
+        assert_eq!(level(0), LEVELS[0]);
+        assert_eq!(level(99), LEVELS[LEVELS.len() - 1]);
+    }
+
+    #[test]
+    fn chooses_only_legal_moves() {
+        let mut rng = StdRng::seed_from_u64(3);
+        for gametype in [GameType::Connect4, GameType::TootOtto] {
+            for skill in &LEVELS[..BROWSER_LEVELS] {
+                // a random opening, then the level's own move
+                let mut position = Position::new(gametype);
+                for _ in 0..rng.gen_range(0..12) {
+                    let moves = position.valid_moves();
+                    if moves.is_empty() {
+                        break;
+                    }
+                    position.play(moves[rng.gen_range(0..moves.len())]).unwrap();
+                }
+                if position.is_over() {
+                    continue;
+                }
+                let mv = choose_move(&position, skill, 1, &mut rng).unwrap();
+                assert!(position.valid_moves().contains(&mv), "level {} played {}", skill.level, mv);
+            }
+        }
+        // even random blunders stay out of a full column
+        let full = position(GameType::Connect4, &["0", "0", "0", "0", "0", "0"]);
+        for _ in 0..20 {
+            assert_ne!(choose_move(&full, &level(1), 1, &mut rng), Some(Move::Connect4(0)));
+        }
+        // and nothing is played once the game is over
+        let won = position(GameType::Connect4, &["0", "6", "1", "6", "2", "6", "3"]);
+        assert_eq!(choose_move(&won, &level(8), 1, &mut rng), None);
+    }
+
+    #[test]
+    fn the_top_level_never_misses_a_win_and_the_bottom_one_does() {
+        // red to move with columns 0 to 2 of the bottom row, 3 wins at once
+        let position = position(GameType::Connect4, &["0", "0", "1", "1", "2", "6"]);
+        let mut rng = StdRng::seed_from_u64(5);
+        // level 8 neither blunders nor samples, so one try is enough
+        assert_eq!(choose_move(&position, &level(8), 1, &mut rng), Some(Move::Connect4(3)));
+        let misses = (0..20).filter(|_| choose_move(&position, &level(1), 1, &mut rng) != Some(Move::Connect4(3))).count();
+        assert!(misses > 0);
+    }
+
+    #[test]
+    fn calibration_ranks_the_levels_in_order() {
+        // the deep levels take too long in a debug build, `cargo run --release
+        // --example calibrate` measures the whole ladder
+        let levels = [level(1), level(3), level(5)];
+        let mut rng = StdRng::seed_from_u64(1);
+        let ratings = calibrate(GameType::TootOtto, &levels, 4, &mut rng);
+        assert_eq!(ratings.len(), levels.len());
+        assert_eq!(ratings[0], 800.0);
+        assert!(ratings.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", ratings);
+    }
+}
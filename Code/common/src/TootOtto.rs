@@ -1,10 +1,15 @@
 use rand::{Rng};
 
+use crate::Position::{Move, Position};
+use crate::Search;
+use crate::Skill;
+
 #[derive(Clone)]
 pub struct TootOtto {
     board: [[Option<Piece>; 7]; 6],
     current_player: Player,
     difficulty: Difficulty,
+    threads: usize,
 }
 
 #[derive(Copy, Clone, PartialEq, Debug)]
@@ -21,11 +26,19 @@ pub enum Difficulty {
 }
 
 
-#[derive(PartialEq, Clone)]
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum Player {
     Toot,
     Otto,
-    AI,
+}
+
+impl Player {
+    pub fn opponent(&self) -> Player {
+        match self {
+            Player::Toot => Player::Otto,
+            Player::Otto => Player::Toot,
+        }
+    }
 }
 
 impl From<Piece> for Player {
@@ -43,6 +56,7 @@ impl TootOtto {
             board: [[None; 7]; 6],
             current_player: Player::Toot,
             difficulty: Difficulty::Easy,
+            threads: 1,
         }
     }
 
@@ -54,6 +68,75 @@ impl TootOtto {
         self.board.clone()
     }
 
+    // Build a game from a position string: six rows from top to bottom separated
+    // by '/', each row seven of 'T', 'O' or '.', then a space and the side to move
+    // ('t' for TOOT or 'o' for OTTO), e.g. "......./......./......./......./......./...T... o"
+    pub fn from_position(position: &str) -> Result<TootOtto, String> {
+        let mut parts = position.trim().split(' ');
+        let rows: Vec<&str> = parts.next().unwrap_or("").split('/').collect();
+        if rows.len() != 6 {
+            return Err(format!("expected 6 rows, found {}", rows.len()));
+        }
+        let mut board = [[None; 7]; 6];
+        for (i, row) in rows.iter().enumerate() {
+            if row.chars().count() != 7 {
+                return Err(format!("row {} must have 7 cells", i + 1));
+            }
+            for (j, cell) in row.chars().enumerate() {
+                board[i][j] = match cell {
+                    'T' => Some(Piece::T),
+                    'O' => Some(Piece::O),
+                    '.' => None,
+                    _ => return Err(format!("unknown piece '{}' in row {}", cell, i + 1)),
+                };
+                // pieces cannot float above an empty cell
+                if i > 0 && board[i - 1][j].is_some() && board[i][j].is_none() {
+                    return Err(format!("floating piece in column {}", j));
+                }
+            }
+        }
+        let current_player = match parts.next() {
+            Some("t") => Player::Toot,
+            Some("o") => Player::Otto,
+            _ => return Err("side to move must be 't' or 'o'".to_string()),
+        };
+        if parts.next().is_some() {
+            return Err("unexpected text after side to move".to_string());
+        }
+        // either side may place either letter, so only the number of pieces
+        // tells whose turn it is; TOOT moves first
+        let pieces = board.iter().flatten().filter(|cell| cell.is_some()).count();
+        let expected = if pieces % 2 == 0 { Player::Toot } else { Player::Otto };
+        if current_player != expected {
+            return Err(format!("{} pieces cannot have {:?} to move", pieces, current_player));
+        }
+        let game = TootOtto {
+            board,
+            current_player,
+            difficulty: Difficulty::Easy,
+            threads: 1,
+        };
+        if game.winner().is_some() {
+            return Err("the game is already over".to_string());
+        }
+        Ok(game)
+    }
+
+    pub fn to_position(&self) -> String {
+        let rows: Vec<String> = self.board.iter().map(|row| {
+            row.iter().map(|cell| match cell {
+                Some(Piece::T) => 'T',
+                Some(Piece::O) => 'O',
+                None => '.',
+            }).collect()
+        }).collect();
+        let side = match self.current_player {
+            Player::Toot => 't',
+            Player::Otto => 'o',
+        };
+        format!("{} {}", rows.join("/"), side)
+    }
+
     pub fn set_difficulty(&mut self, diff: Difficulty){
         match diff {
             Difficulty::Easy => {
@@ -68,6 +151,11 @@ impl TootOtto {
         }
     }
 
+    // Let the AI search on several threads; 1 keeps it on the calling thread
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
     pub fn top_row(&self, col: usize) -> usize {
         for row in (0..6).rev() {
             if self.board[row][col].is_none() {
@@ -84,20 +172,47 @@ impl TootOtto {
         //     return;
         // }
         // check if the given column is valid
-        if column >= 7 {
+        if column >= 7 || self.top_row(column) == 10 {
             return;
         }
         self.place_piece(column, piece);
+        self.current_player = Player::Otto;
     }
 
     pub fn make_move_by_otto(&mut self, column: usize, piece: Piece) {
         // if self.current_player != Player::Otto {
         //     return;
         // }
-        if column >= 7 {
+        if column >= 7 || self.top_row(column) == 10 {
             return;
         }
         self.place_piece(column, piece);
+        self.current_player = Player::Toot;
+    }
+
+    // place a piece for whoever is to move and hand the turn to the other player
+    pub fn play_move(&mut self, column: usize, piece: Piece) -> bool {
+        if column >= 7 || self.top_row(column) == 10 {
+            return false;
+        }
+        self.place_piece(column, piece);
+        self.current_player = self.current_player.opponent();
+        true
+    }
+
+    // take back the last piece placed in a column and give the turn back
+    pub fn undo_move(&mut self, column: usize) -> bool {
+        if column >= 7 {
+            return false;
+        }
+        match (0..6).find(|&row| self.board[row][column].is_some()) {
+            Some(row) => {
+                self.board[row][column] = None;
+                self.current_player = self.current_player.opponent();
+                true
+            }
+            None => false,
+        }
     }
 
     fn place_piece(&mut self, column: usize, piece: Piece) {
@@ -121,29 +236,25 @@ impl TootOtto {
     // }
 
 
-    pub fn make_move_by_ai(&mut self) {
-        let depth:i32;
-        let mut rand_piece = Piece::T;
-        match self.difficulty{
+    // Let the computer move as `role` (TOOT or OTTO) and hand the turn to the other side
+    pub fn make_move_by_ai(&mut self, role: Player) {
+        if self.is_draw() || self.is_over() {
+            return;
+        }
+        self.current_player = role;
+        let depth: i32;
+        match self.difficulty {
             Difficulty::Easy => {
-                if self.is_draw() || self.is_over() {
-                    return;
-                }
                 let mut rng = rand::thread_rng();
                 loop {
                     let random_col = rng.gen_range(0..7);
-                    let rand_piece_no = rng.gen_range(0..2);
-                    match rand_piece_no{
-                        0 => {
-                            rand_piece = Piece::O;
-                        },
-                        _ => {}
-                    }
+                    let rand_piece = if rng.gen_range(0..2) == 0 { Piece::O } else { Piece::T };
                     if self.top_row(random_col) != 10 {
                         self.place_piece(random_col, rand_piece);
                         break;
                     }
                 }
+                self.current_player = role.opponent();
                 return;
             }
             Difficulty::Medium => {
@@ -153,47 +264,65 @@ impl TootOtto {
                 depth = 3;
             }
         }
-        let mut maximizing_player = true;
-        let mut next_toot = false;
-        // Let Toot be max and Otto be min
-        match self.current_player {
-            Player::Toot => {
-                maximizing_player = false;
-                next_toot = true;
-            }
-            _ => {}
-        };
-
-        self.current_player = Player::AI;
-        // let mut current_state = self.board.clone();
-        // let mut best_score = i32::MIN;
-        let (column, _, piece) = self.minimax(depth as i32, maximizing_player);
+        let (column, _, piece) = self.search(depth, role);
         if column != 10 {
             self.place_piece(column, piece);
         }
-
         // switch to the next player
-        if next_toot == true {
-            self.current_player = Player::Toot;
-        } else {
-            self.current_player = Player::Otto;
+        self.current_player = role.opponent();
+    }
+
+    // Move as `role` the way a person of this skill level would, returns the
+    // column and letter played
+    pub fn make_move_by_skill(&mut self, level: usize, role: Player) -> Option<(usize, Piece)> {
+        let mut game = self.clone();
+        game.current_player = role;
+        let position = Position::TootOtto(game);
+        match Skill::choose_move(&position, &Skill::level(level), self.threads, &mut rand::thread_rng()) {
+            Some(Move::TootOtto(column, piece)) => {
+                self.place_piece(column, piece);
+                self.current_player = role.opponent();
+                Some((column, piece))
+            }
+            _ => None,
         }
     }
 
-    pub fn minimax(&mut self, depth: i32, maximizing_player: bool) -> (usize, i32, Piece) {
-        let (mut best_col, mut best_piece) = (0, Piece::T);
-        let mut best_score:i32;
-        if maximizing_player {
-            best_score = -10000000;
-        } else {
-            best_score = 10000000;
+    // Same result as `negamax`, but with the root moves split across threads
+    pub fn search(&mut self, depth: i32, player: Player) -> (usize, i32, Piece) {
+        if self.threads <= 1 || depth == 0 || self.is_draw() || self.is_over() {
+            return self.negamax(depth, player);
+        }
+        let mut moves = Vec::new();
+        for piece in [Piece::T, Piece::O] {
+            for col in 0..7 {
+                if self.get_valid_row(col).is_some() {
+                    moves.push((col, piece));
+                }
+            }
+        }
+        let scores = Search::split_root(self, &moves, self.threads, |game: &mut TootOtto, (col, piece)| {
+            let row = game.get_valid_row(col).unwrap();
+            game.board[row][col] = Some(piece);
+            let score = -game.negamax(depth - 1, player.opponent()).1;
+            game.board[row][col] = None;
+            score
+        });
+        let mut best = (0, -10000000, Piece::T);
+        for ((col, piece), score) in moves.into_iter().zip(scores) {
+            if score > best.1 {
+                best = (col, score, piece);
+            }
         }
+        best
+    }
 
+    // Best (column, score, piece) for `player` to move, with the score always
+    // from the point of view of the player to move
+    pub fn negamax(&mut self, depth: i32, player: Player) -> (usize, i32, Piece) {
+        let (mut best_col, mut best_score, mut best_piece) = (0, -10000000, Piece::T);
         if depth == 0 || self.is_draw() || self.is_over() {
-            // self.print_board();
-            // println!();
-            // println!("{}", self.evaluate_board(maximizing_player));
-            return (0, self.evaluate_board(maximizing_player), best_piece);
+            return (0, self.evaluate_for(player), best_piece);
         }
 
         let pieces = [Piece::T, Piece::O];
@@ -201,44 +330,24 @@ impl TootOtto {
             for col in 0..7 {
                 if let Some(row) = self.get_valid_row(col) {
                     self.board[row][col] = Some(*piece);
-
-                    let score = self.minimax(depth - 1, !maximizing_player).1;
-                    // println!();
-                    // println!("{}", score);
-                    // println!();
-
-                    if maximizing_player == true {
-                        if score > best_score {
-                            best_score = score;
-                            best_col = col;
-                            best_piece = *piece;
-                        }
-                    } else {
-                        // println!("Maximizing player = false");
-                        // println!();
-                        // println!("{}", score);
-                        // println!();
-                        if score < best_score {
-                            best_score = score;
-                            best_col = col;
-                            best_piece = *piece;
-                            // println!();
-                            // println!("{}", best_score);
-                            // println!();
-                        }
+                    let score = -self.negamax(depth - 1, player.opponent()).1;
+                    if score > best_score {
+                        best_score = score;
+                        best_col = col;
+                        best_piece = *piece;
                     }
-
                     self.board[row][col] = None;
                 }
             }
         }
+        (best_col, best_score, best_piece)
+    }
 
-        if best_col == 10 {
-            (10, best_score, best_piece)
-        } else {
-            // println!("{}", best_col);
-            // println!("{:?}", best_piece);
-            (best_col, best_score, best_piece)
+    // evaluate_board scores TOOT as positive, flip it round for OTTO
+    fn evaluate_for(&self, player: Player) -> i32 {
+        match player {
+            Player::Toot => self.evaluate_board(true),
+            Player::Otto => -self.evaluate_board(false),
         }
     }
 
@@ -424,6 +533,11 @@ impl TootOtto {
         return false;
     }
 
+    // Number of pieces on the board, which is the number of moves played
+    pub fn move_count(&self) -> usize {
+        self.board.iter().flatten().filter(|cell| cell.is_some()).count()
+    }
+
     pub fn is_over(&self) -> bool {
         return match self.winner() {
             None => {
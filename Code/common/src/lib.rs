@@ -1,4 +1,9 @@
 #![allow(non_snake_case)]
 pub mod TootOtto;
 pub mod Connect4;
-pub mod Backend;
\ No newline at end of file
+pub mod Backend;
+pub mod Position;
+pub mod Puzzle;
+pub mod Search;
+pub mod Skill;
+pub mod Render;
@@ -2,6 +2,7 @@ use std::io;
 use std::io::Write;
 
 use common::Connect4::Connect4;
+use common::Search;
 
 fn main() {
     /*
@@ -29,6 +30,8 @@ fn main() {
 
      */
     let mut game = Connect4::new();
+    // the terminal is not limited to one thread like the browser is
+    game.set_threads(Search::available_threads());
 
     loop {
         println!("Enter column:");
@@ -0,0 +1,97 @@
+#![allow(non_snake_case)]
+use common::Backend::{Credentials, ErrorBody, Session, User};
+use reqwest;
+use yew::prelude::*;
+use yew_router::prelude::*;
+
+use crate::Route;
+
+const SERVER: &str = "http://127.0.0.1:8000";
+// where the session is kept in the browser's local storage
+const STORAGE_KEY: &str = "session";
+
+fn storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+// The session of the logged in user, if any
+pub fn current() -> Option<Session> {
+    let text = storage()?.get_item(STORAGE_KEY).ok()??;
+    serde_json::from_str(&text).ok()
+}
+
+// Name of the logged in user, which is who sits in player 1's seat
+pub fn name() -> Option<String> {
+    current().map(|session| session.user.name)
+}
+
+fn save(session: Option<&Session>) {
+    if let Some(storage) = storage() {
+        let _ = match session.and_then(|session| serde_json::to_string(session).ok()) {
+            Some(text) => storage.set_item(STORAGE_KEY, &text),
+            None => storage.remove_item(STORAGE_KEY),
+        };
+    }
+}
+
+// Send the session token along, for routes that need a logged in user
+pub fn authorize(request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+    match current() {
+        Some(session) => request.bearer_auth(session.token),
+        None => request,
+    }
+}
+
+// The message of an error answer, which comes as an `ErrorBody`
+pub async fn error_text(response: reqwest::Response) -> String {
+    let text = response.text().await.unwrap_or_else(|err| err.to_string());
+    serde_json::from_str::<ErrorBody>(&text).map(|body| body.error).unwrap_or(text)
+}
+
+pub async fn register(credentials: Credentials) -> Result<User, String> {
+    let response = reqwest::Client::new()
+        .post(format!("{}/users", SERVER))
+        .json(&credentials)
+        .send()
+        .await
+        .map_err(|err| err.to_string())?;
+    if !response.status().is_success() {
+        return Err(error_text(response).await);
+    }
+    response.json().await.map_err(|err| err.to_string())
+}
+
+pub async fn login(credentials: Credentials) -> Result<Session, String> {
+    let response = reqwest::Client::new()
+        .post(format!("{}/sessions", SERVER))
+        .json(&credentials)
+        .send()
+        .await
+        .map_err(|err| err.to_string())?;
+    if !response.status().is_success() {
+        return Err(error_text(response).await);
+    }
+    let session: Session = response.json().await.map_err(|err| err.to_string())?;
+    save(Some(&session));
+    Ok(session)
+}
+
+// Forget the session here even if the server cannot be reached
+pub async fn logout() {
+    let request = authorize(reqwest::Client::new().delete(format!("{}/sessions", SERVER)));
+    save(None);
+    let _ = request.send().await;
+}
+
+// Shown next to the name fields of the game pages
+pub fn record_notice() -> Html {
+    match current() {
+        Some(_) => html! {},
+        None => html! {
+            <p>
+                <Link<Route> to={Route::Login}>{"Log in"}</Link<Route>>
+                {" to have your games recorded on the score board."}
+            </p>
+        },
+    }
+}
@@ -5,15 +5,26 @@ use std::cell::RefCell;
 use std::f64::consts::PI;
 use std::rc::Rc;
 
-use common::Backend::Game;
+use common::Backend::{MatchState, NewMatch};
+use common::Position::GameType;
 use common::Connect4::{Connect4, Piece, Player};
+use common::Skill;
 use wasm_bindgen::{JsCast, JsValue, prelude::Closure};
 use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement, InputEvent, MouseEvent};
 use yew::prelude::*;
 
+use crate::Accounts;
+use crate::Matches;
+
 pub struct Connect4AI {
     game: Rc<RefCell<Connect4>>,
     is_game_started: bool,
+    // id of the match the server is keeping for this game; the server
+    // plays the computer's moves in it
+    match_id: Option<String>,
+    // clicks wait while the server opens the match or answers a move
+    waiting: bool,
+    moves: Vec<String>,
     player1: String,
     player2: String,
     winner: String,
@@ -31,7 +42,9 @@ pub enum Msg {
     Connect4,
     EndGame,
     ClickedColumn(Option<usize>),
-    Record(),
+    MatchCreated(Option<MatchState>),
+    // the server's answer to a move in the match with this id
+    Replied(String, Result<MatchState, String>),
 }
 
 impl Connect4AI {
@@ -127,18 +140,71 @@ impl Connect4AI {
         }
     }
 
-    fn make_move(&mut self, col: usize) {
-        let _done = self.game.borrow_mut().user_move(col);
+    // Play the user's move, then the computer's: the server's in a match,
+    // the browser's own for guests
+    fn make_move(&mut self, col: usize, ctx: &Context<Self>) {
+        if !self.game.borrow_mut().user_move(col) {
+            return;
+        }
+        self.moves.push(col.to_string());
+        self.check_winner();
+        match self.match_id.clone() {
+            Some(id) => {
+                self.waiting = true;
+                let mv = col.to_string();
+                ctx.link().send_future(async move {
+                    let reply = Matches::play(&id, mv).await;
+                    Msg::Replied(id, reply)
+                });
+            }
+            None if !self.is_game_over => self.computer_move(),
+            None => {}
+        }
+    }
+
+    fn computer_move(&mut self) {
+        if let Some(ai_col) = self.game.borrow_mut().skill_move(self.difficulty) {
+            self.moves.push(ai_col.to_string());
+        }
+    }
+
+    // Play the moves the server made that the board does not have yet
+    fn catch_up(&mut self, state: &MatchState) {
+        for mv in state.moves.iter().skip(self.moves.len()) {
+            if let Ok(col) = mv.parse::<usize>() {
+                self.game.borrow_mut().user_move(col);
+                self.moves.push(mv.clone());
+            }
+        }
+        self.render_board();
         self.check_winner();
-        if !self.is_game_over {
-            let _ai_done = self.game.borrow_mut().ai_move(self.difficulty.clone());
+        if self.is_game_over {
+            self.end_event.emit("end".to_string());
         }
     }
 
-    fn new_game(&mut self) {
+    // The server replays every move and records the result once the game ends
+    fn start_match(&mut self, ctx: &Context<Self>) {
+        self.match_id = None;
+        self.moves.clear();
+        self.waiting = Accounts::current().is_some();
+        let request = NewMatch {
+            gametype: GameType::Connect4,
+            player1: self.player1.clone(),
+            player2: self.player2.clone(),
+            first_mover: None,
+            player1_word: None,
+            difficulty: Some(self.difficulty),
+            online: false,
+        };
+        ctx.link().send_future(async move { Msg::MatchCreated(Matches::open(request).await) });
+    }
+
+    fn new_game(&mut self, ctx: &Context<Self>) {
         self.game = Rc::new(RefCell::new(Connect4::new()));
         self.winner = "".to_string();
         self.is_game_over = false;
+        self.start_match(ctx);
         let canvas: HtmlCanvasElement = self.canvas.cast().unwrap();
         let context: CanvasRenderingContext2d = canvas.get_context("2d").unwrap().unwrap().unchecked_into();
         context.clear_rect(0 as f64, 0 as f64, canvas.width() as f64, canvas.height() as f64);
@@ -154,7 +220,11 @@ impl Component for Connect4AI {
         Self {
             game: connect4.clone(),
             is_game_started: false,
-            player1: "".to_string(),
+            match_id: None,
+            waiting: false,
+            moves: Vec::new(),
+            // the logged in user plays as themselves
+            player1: Accounts::name().unwrap_or_default(),
             player2: "Computer".to_string(), //just done to add name on the scoreboard
             winner: "".to_string(),
             is_game_over: false,
@@ -179,7 +249,7 @@ impl Component for Connect4AI {
                     .unwrap()
                     .dyn_into::<web_sys::HtmlSelectElement>()
                     .unwrap();
-                self.difficulty = difficulty_drop_down.value().parse::<usize>().unwrap();
+                self.difficulty = difficulty_drop_down.value().parse::<usize>().unwrap().min(Skill::BROWSER_LEVELS);
                 let canvas: HtmlCanvasElement = self.canvas.cast().unwrap();
                 let rect = canvas.get_bounding_client_rect();
                 let link = _ctx.link().clone();
@@ -201,6 +271,7 @@ impl Component for Connect4AI {
                     .unwrap();
 
                 closure.forget();
+                self.start_match(_ctx);
                 self.render_background();
             }
             Msg::SetPlayer1Name(e) => {
@@ -208,26 +279,9 @@ impl Component for Connect4AI {
                     self.player1 += &e.data().unwrap().to_owned();
                 }
             }
-            Msg::EndGame => {
-                let game = Game {
-                        gametype: "Connect4".to_string(),
-                        player1: self.player1.clone(),
-                        player2: self.player2.clone(),
-                        winner: self.winner.clone(),
-                        date: "temp".to_string(),
-                    };
-                
-                _ctx.link().send_future(async move{
-                    let client = reqwest::Client::new();
-                    match client.post("http://127.0.0.1:8000/post/games").body(serde_json::to_string(&game).unwrap()).send().await{
-                        Ok(v) => {
-                            Msg::Record()
-                        }
-                        Err(err) => {
-                            Msg::Record()
-                        }
-                    }});
-            }
+            // the server stored the game with its last move
+            Msg::EndGame => {}
+            Msg::ClickedColumn(_) if self.waiting => {}
             Msg::ClickedColumn(column) => {
                 if self.is_game_over == false {
                     match column {
@@ -235,7 +289,7 @@ impl Component for Connect4AI {
                         Some(col) => {
                             let row = self.game.borrow_mut().top_row(col);
                             if row < (self.rows as usize) {
-                                self.make_move(col);
+                                self.make_move(col, _ctx);
                                 self.render_board();
                                 self.check_winner();
                                 if self.is_game_over {
@@ -245,11 +299,37 @@ impl Component for Connect4AI {
                         }
                     }
                 } else {
-                    self.new_game();
+                    self.new_game(_ctx);
                     self.render_background();
                 }
             }
-            Msg::Record() => {}
+            Msg::MatchCreated(state) => {
+                self.waiting = false;
+                if let Some(state) = state {
+                    self.match_id = Some(state.id.clone());
+                    self.catch_up(&state);
+                }
+            }
+            // an answer from a game already left
+            Msg::Replied(id, _) if self.match_id.as_ref() != Some(&id) => {}
+            Msg::Replied(_, reply) => {
+                self.waiting = false;
+                match reply {
+                    Ok(state) => self.catch_up(&state),
+                    // the server lost the match: finish the game here, unrecorded
+                    Err(_) => {
+                        self.match_id = None;
+                        if !self.is_game_over {
+                            self.computer_move();
+                            self.render_board();
+                            self.check_winner();
+                            if self.is_game_over {
+                                self.end_event.emit("end".to_string());
+                            }
+                        }
+                    }
+                }
+            }
         }
         true
     }
@@ -264,20 +344,21 @@ impl Component for Connect4AI {
                             <h5 class="w3-xxxlarge w3-text-red"><b>{"Enter Your Name"}</b></h5>
                             <hr style="width:50px;border:5px solid red" class="w3-round"/>
                             <div class="col-md-offset-3 col-md-8">
-                                <input id="textbox1" type="text" style="margin-right: 5px" placeholder="Your Name" oninput={ctx.link().callback(|e: InputEvent| Msg::SetPlayer1Name(e))}/>
+                                <input id="textbox1" type="text" style="margin-right: 5px" placeholder="Your Name" oninput={ctx.link().callback(|e: InputEvent| Msg::SetPlayer1Name(e))} value={self.player1.clone()} readonly={Accounts::current().is_some()}/>
                                 <button id="startbutton"
                                     onclick={ctx.link().callback(|_| Msg::Connect4)}
                                     disabled = {self.player1 == "".to_string()}
                                     title="Start Game">
                                         { "Start Game" }
                                 </button>
+                                { Accounts::record_notice() }
                             </div>
                             <div>
                                 <label for="difficulty_drop_down"> {"Difficulty: "} </label>
                                 <select id="difficulty_drop_down" style="margin-top: 5px">
-                                    <option value=1 selected=true> {"Beginner"}</option>
-                                    <option value=2 selected=false> {"Intermediate"}</option>
-                                    <option value=4 selected=false> {"Professional"}</option>
+                                    { for Skill::LEVELS[..Skill::BROWSER_LEVELS].iter().map(|level| html! {
+                                        <option value={level.level.to_string()} selected={level.level == 1}> {level.name}</option>
+                                    }) }
                                 </select>
                             </div>
                         </div>
@@ -289,7 +370,7 @@ impl Component for Connect4AI {
                         <div class="w3-container" id="services" style="margin-left:30%">
                             <div>
                                 <h4>{format!("New Game:  {} Vs {}",self.player1, self.player2)}</h4>
-                                    <p>{format!("Difficulty: {} (1: Beginner, 2: Intermediate, 4: Professional)", self.difficulty)}</p>
+                                    <p>{format!("Difficulty: {} (level {} of {})", Skill::level(self.difficulty).name, self.difficulty, Skill::BROWSER_LEVELS)}</p>
                                 <br/>
                                 <small>{format!("(Piece Alloted: {} - ", self.player1)} <b>{"Red"}</b> {format!("   and    {} - ", self.player2)} <b>{"Yellow)"}</b></small>
                                 <br/>
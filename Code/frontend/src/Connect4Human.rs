@@ -5,15 +5,22 @@ use std::cell::RefCell;
 use std::f64::consts::PI;
 use std::rc::Rc;
 
-use common::Backend::Game;
+use common::Backend::NewMatch;
+use common::Position::GameType;
 use common::Connect4::{Connect4, Piece, Player};
 use wasm_bindgen::{JsCast, JsValue, prelude::Closure};
 use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement, MouseEvent};
 use yew::prelude::*;
 
+use crate::Accounts;
+use crate::Matches;
+
 pub struct Connect4Human {
     game: Rc<RefCell<Connect4>>,
     is_game_started: bool,
+    // id of the match the server is keeping for this game
+    match_id: Option<String>,
+    moves: Vec<String>,
     player1: String,
     player2: String,
     winner: String,
@@ -32,6 +39,7 @@ pub enum Msg {
     EndGame,
     ClickedColumn(Option<usize>),
     Record(),
+    MatchCreated(Option<String>),
 }
 
 impl Connect4Human {
@@ -130,13 +138,32 @@ impl Connect4Human {
     }
 
     fn make_move(&mut self, col: usize) {
-        let _done = self.game.borrow_mut().user_move(col);
+        if self.game.borrow_mut().user_move(col) {
+            self.moves.push(col.to_string());
+        }
+    }
+
+    // The server replays the moves and records the result once the game ends
+    fn start_match(&mut self, ctx: &Context<Self>) {
+        self.match_id = None;
+        self.moves.clear();
+        let request = NewMatch {
+            gametype: GameType::Connect4,
+            player1: self.player1.clone(),
+            player2: self.player2.clone(),
+            first_mover: None,
+            player1_word: None,
+            difficulty: None,
+            online: false,
+        };
+        ctx.link().send_future(async move { Msg::MatchCreated(Matches::create(request).await) });
     }
 
-    fn new_game(&mut self) {
+    fn new_game(&mut self, ctx: &Context<Self>) {
         self.game = Rc::new(RefCell::new(Connect4::new()));
         self.winner = "".to_string();
         self.is_game_over = false;
+        self.start_match(ctx);
         let canvas: HtmlCanvasElement = self.canvas.cast().unwrap();
         let context: CanvasRenderingContext2d = canvas.get_context("2d").unwrap().unwrap().unchecked_into();
         context.clear_rect(0 as f64, 0 as f64, canvas.width() as f64, canvas.height() as f64);
@@ -153,7 +180,10 @@ impl Component for Connect4Human {
         Self {
             game: connect4.clone(),
             is_game_started: false,
-            player1: "".to_string(),
+            match_id: None,
+            moves: Vec::new(),
+            // the logged in user plays as themselves
+            player1: Accounts::name().unwrap_or_default(),
             player2: "".to_string(), //just done to add name on the scoreboard
             winner: "".to_string(),
             is_game_over: false,
@@ -191,6 +221,7 @@ impl Component for Connect4Human {
                     .unwrap();
 
                 closure.forget();
+                self.start_match(_ctx);
                 self.render_background();
             }
             Msg::SetPlayer1Name(e) => {
@@ -204,24 +235,13 @@ impl Component for Connect4Human {
                 }
             }
             Msg::EndGame => {
-                let game = Game {
-                        gametype: "Connect4".to_string(),
-                        player1: self.player1.clone(),
-                        player2: self.player2.clone(),
-                        winner: self.winner.clone(),
-                        date: "temp".to_string(),
-                    };
-
-                _ctx.link().send_future(async move{
-                    let client = reqwest::Client::new();
-                    match client.post("http://127.0.0.1:8000/post/games").body(serde_json::to_string(&game).unwrap()).send().await{
-                        Ok(v) => {
-                            Msg::Record()
-                        }
-                        Err(err) => {
-                            Msg::Record()
-                        }
-                    }});
+                if let Some(id) = self.match_id.take() {
+                    let moves = self.moves.clone();
+                    _ctx.link().send_future(async move {
+                        let _ = Matches::send_moves(&id, moves).await;
+                        Msg::Record()
+                    });
+                }
             }
             Msg::ClickedColumn(column) => {
                 if self.is_game_over == false {
@@ -237,11 +257,14 @@ impl Component for Connect4Human {
                         }
                     }
                 } else {
-                    self.new_game();
+                    self.new_game(_ctx);
                     self.render_background();
                 }
             }
             Msg::Record() => {}
+            Msg::MatchCreated(id) => {
+                self.match_id = id;
+            }
         }
         true
     }
@@ -257,7 +280,7 @@ impl Component for Connect4Human {
                     <hr style="width:50px;border:5px solid red" class="w3-round"/>
                 </div>
                 <div class="col-md-offset-3 col-md-8">
-                    <input id="textbox1" type="text" style="margin-right: 5px" placeholder="Player 1's Name" oninput={ctx.link().callback(|e: InputEvent| Msg::SetPlayer1Name(e))}/>
+                    <input id="textbox1" type="text" style="margin-right: 5px" placeholder="Player 1's Name" oninput={ctx.link().callback(|e: InputEvent| Msg::SetPlayer1Name(e))} value={self.player1.clone()} readonly={Accounts::current().is_some()}/>
                     <input id="textbox2" type="text" style="margin-right: 5px" placeholder="Player 2's Name" oninput={ctx.link().callback(|e: InputEvent| Msg::SetPlayer2Name(e))}/>
                     <button id="startbutton"
                         onclick={ctx.link().callback(|_| Msg::Connect4)}
@@ -265,6 +288,7 @@ impl Component for Connect4Human {
                         title="Start Game">
                             { "Start Game" }
                         </button>
+                        { Accounts::record_notice() }
                     </div>
                 </div>
             }
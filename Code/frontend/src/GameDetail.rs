@@ -0,0 +1,159 @@
+#![allow(non_snake_case)]
+use yew::prelude::*;
+use yew_router::prelude::*;
+use reqwest;
+use common::Backend::Game;
+
+use crate::Accounts;
+use crate::Route;
+use crate::GameHistory::{format_time, FetchState};
+
+// One stored game, looked up by the id the server gave it
+pub struct GameDetail {
+    state: FetchState<Game>,
+    deleted: bool,
+    // number of moves shown in the replay
+    ply: usize,
+}
+
+#[derive(Properties, PartialEq)]
+pub struct GameDetailProps {
+    pub id: String,
+}
+
+pub enum GameDetailMsg {
+    SetDataFetchState(FetchState<Game>),
+    GetData,
+    Delete,
+    Deleted(bool),
+    SetPly(usize),
+}
+
+impl GameDetail {
+    // The board after `ply` moves, drawn by the server, with buttons to step through the game
+    fn view_replay(&self, ctx: &Context<Self>, game: &Game) -> Html {
+        if game.moves.is_empty() {
+            return html! {};
+        }
+        let ply = self.ply;
+        let last = game.moves.len();
+        html! {
+            <div>
+            <img src={format!("http://127.0.0.1:8000/games/{}/image?ply={}", ctx.props().id, ply)} alt="board"/>
+            <br/>
+            <button class="button" disabled={ply == 0} onclick={ctx.link().callback(|_| GameDetailMsg::SetPly(0))}>{"<<"}</button>
+            <button class="button" disabled={ply == 0} onclick={ctx.link().callback(move |_| GameDetailMsg::SetPly(ply.saturating_sub(1)))}>{"<"}</button>
+            <span>{format!(" Move {} of {} ", ply, last)}</span>
+            <button class="button" disabled={ply == last} onclick={ctx.link().callback(move |_| GameDetailMsg::SetPly((ply + 1).min(last)))}>{">"}</button>
+            <button class="button" disabled={ply == last} onclick={ctx.link().callback(move |_| GameDetailMsg::SetPly(last))}>{">>"}</button>
+            </div>
+        }
+    }
+}
+
+impl Component for GameDetail {
+    type Message = GameDetailMsg;
+    type Properties = GameDetailProps;
+
+    fn create(_ctx: &Context<Self>) -> Self {
+        Self {
+            state: FetchState::NotFetching,
+            deleted: false,
+            ply: 0,
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        let url = format!("http://127.0.0.1:8000/games/{}", ctx.props().id);
+        match msg {
+            GameDetailMsg::SetDataFetchState(state) => {
+                // start the replay on the final board
+                if let FetchState::Success(game) = &state {
+                    self.ply = game.moves.len();
+                }
+                self.state = state;
+                true
+            }
+            GameDetailMsg::SetPly(ply) => {
+                self.ply = ply;
+                true
+            }
+            GameDetailMsg::GetData => {
+                ctx.link().send_future(async move {
+                    match reqwest::get(&url).await {
+                        Ok(v) if v.status().is_success() => match v.json().await {
+                            Ok(v) => GameDetailMsg::SetDataFetchState(FetchState::Success(v)),
+                            Err(_) => GameDetailMsg::SetDataFetchState(FetchState::Failed),
+                        },
+                        _ => GameDetailMsg::SetDataFetchState(FetchState::Failed),
+                    }
+                });
+                ctx.link().send_message(GameDetailMsg::SetDataFetchState(FetchState::Fetching));
+                false
+            }
+            GameDetailMsg::Delete => {
+                ctx.link().send_future(async move {
+                    match Accounts::authorize(reqwest::Client::new().delete(&url)).send().await {
+                        Ok(v) => GameDetailMsg::Deleted(v.status().is_success()),
+                        Err(_) => GameDetailMsg::Deleted(false),
+                    }
+                });
+                false
+            }
+            GameDetailMsg::Deleted(deleted) => {
+                self.deleted = deleted;
+                if !deleted {
+                    self.state = FetchState::Failed;
+                }
+                true
+            }
+        }
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        if matches!(&self.state, &FetchState::NotFetching) {
+            ctx.link().send_message(GameDetailMsg::GetData);
+        }
+        let body = if self.deleted {
+            html! { <p>{"The game has been deleted."}</p> }
+        } else {
+            match &self.state {
+                FetchState::Success(game) => html! {
+                    <div>
+                    <table>
+                        <tr><th>{"Game-ID"}</th><td>{ctx.props().id.clone()}</td></tr>
+                        <tr><th>{"Game-Type"}</th><td>{game.gametype.clone()}</td></tr>
+                        <tr><th>{"Player1"}</th><td>{game.player1.clone()}</td></tr>
+                        <tr><th>{"Player2"}</th><td>{game.player2.clone()}</td></tr>
+                        <tr><th>{"Winner"}</th><td>{game.winner.clone()}</td></tr>
+                        <tr><th>{"Started"}</th><td>{format_time(&game.started_at)}</td></tr>
+                        <tr><th>{"Ended"}</th><td>{format_time(&game.ended_at)}</td></tr>
+                        <tr><th>{"Duration"}</th><td>{game.duration_secs.map(|secs| format!("{}m {}s", secs / 60, secs % 60)).unwrap_or_else(|| "unknown".to_string())}</td></tr>
+                        <tr><th>{"Moves"}</th><td>{game.move_count.map(|count| count.to_string()).unwrap_or_else(|| "unknown".to_string())}</td></tr>
+                        <tr><th>{"Difficulty"}</th><td>{game.difficulty.map(|level| format!("level {}", level)).unwrap_or_else(|| "-".to_string())}</td></tr>
+                        <tr><th>{"Variant"}</th><td>{game.variant.clone()}</td></tr>
+                        <tr><th>{"First Move"}</th><td>{game.first_mover.clone().unwrap_or_else(|| "unknown".to_string())}</td></tr>
+                    </table>
+                    <br/>
+                    { self.view_replay(ctx, game) }
+                    <br/>
+                    <button class="button" onclick={ctx.link().callback(|_| GameDetailMsg::Delete)}>{"Delete"}</button>
+                    </div>
+                },
+                FetchState::Failed => html! { <p>{"Could not find this game."}</p> },
+                _ => html! { <p>{"Loading..."}</p> },
+            }
+        };
+        html! {
+            <div style = "margin-top: 75px">
+            <div class="w3-container" id="services" style="margin-left:30%">
+            <h5 class="w3-xxxlarge w3-text-red"><b>{"Game Details"}</b></h5>
+            <hr style="width:50px;border:5px solid red" class="w3-round"/>
+            { body }
+            <br/>
+            <Link<Route> to={Route::GameHistory}>{"Back to Game History"}</Link<Route>>
+            </div>
+            </div>
+        }
+    }
+}
@@ -3,13 +3,19 @@
 use yew::{
     prelude::*
 };
+use yew_router::prelude::*;
 use reqwest;
-use common::Backend::Game;
+use wasm_bindgen::JsValue;
+use common::Backend::{Game, GamePage};
+
+use crate::Route;
 
 pub struct GameHistory {
     // add any state necessary for the game
-    state: FetchState<Vec<Game>>,
+    state: FetchState<GamePage>,
     data: Vec<Game>,
+    // where the next page starts, if there is one
+    next_cursor: Option<String>,
 }
 
 /// The possible states a fetch request can be in.
@@ -26,16 +32,29 @@ pub enum FetchStateMsg<T> {
     GetData,
 }
 
+// Show a stored RFC 3339 time in the browser's locale and time zone
+pub fn format_time(time: &Option<String>) -> String {
+    match time {
+        Some(time) => js_sys::Date::new(&time.as_str().into()).to_locale_string("default", &JsValue::UNDEFINED).into(),
+        None => "unknown".to_string(),
+    }
+}
+
 impl GameHistory {
 	fn get_games(&self) -> Html {
         let games = self.data.iter().enumerate().map(|(i,game)| html! {
 		    <tr>
-                <td>{format!("{} ", i+1)}</td>
+                <td>{
+                    match &game.id {
+                        Some(id) => html! { <Link<Route> to={Route::GameDetail { id: id.clone() }}>{format!("{} ", i+1)}</Link<Route>> },
+                        None => html! { format!("{} ", i+1) },
+                    }
+                }</td>
                 <td>{format!("{} ", game.gametype)}</td>
 			    <td>{format!("{} ", game.player1)}</td>
 			    <td>{format!("{} ", game.player2)}</td>
 			    <td>{format!("{} ", game.winner)}</td>
-			    <td>{format!("{} ", game.date)}</td>
+			    <td>{format!("{} ", format_time(&game.ended_at))}</td>
 		    </tr>
 	    }).collect::<Html>();
 	    games
@@ -43,12 +62,13 @@ impl GameHistory {
 }
 
 impl Component for GameHistory {
-    type Message = FetchStateMsg<Vec<Game>>;
+    type Message = FetchStateMsg<GamePage>;
     type Properties = ();
 
     fn create(_ctx: &Context<Self>) -> Self {
         Self {
             data: Vec::new(),
+            next_cursor: None,
             state: FetchState::NotFetching,
         }
     }
@@ -57,8 +77,9 @@ impl Component for GameHistory {
         match _msg {
             FetchStateMsg::SetDataFetchState(state) => {
                 match state.clone() {
-                    FetchState::Success(s2) => {
-                        self.data = s2;
+                    FetchState::Success(page) => {
+                        self.data.extend(page.games);
+                        self.next_cursor = page.next_cursor;
                     },
                     _=> (),
                 }
@@ -66,8 +87,12 @@ impl Component for GameHistory {
                 true
             }
             FetchStateMsg::GetData => {
+                let url = match &self.next_cursor {
+                    Some(cursor) => format!("http://127.0.0.1:8000/games?cursor={}", cursor),
+                    None => "http://127.0.0.1:8000/games".to_string(),
+                };
                 _ctx.link().send_future(async move {
-                    match reqwest::get("http://127.0.0.1:8000/games").await {
+                    match reqwest::get(&url).await {
                         Ok(v) => match v.json().await {
                             Ok(v) => {
                                 FetchStateMsg::SetDataFetchState(FetchState::Success(v))
@@ -97,6 +122,12 @@ impl Component for GameHistory {
 		    <div class="w3-container" id="services" style="margin-left:30%">
 		    <h5 class="w3-xxxlarge w3-text-red"><b>{"Game History"}</b></h5>
 		    <hr style="width:50px;border:5px solid red" class="w3-round"/>
+		    <p>
+			    {"Download every game: "}
+			    <a href="http://127.0.0.1:8000/export?format=csv">{"CSV"}</a>{" | "}
+			    <a href="http://127.0.0.1:8000/export?format=json">{"JSON"}</a>{" | "}
+			    <a href="http://127.0.0.1:8000/export?format=pgn">{"PGN"}</a>
+		    </p>
     
 		    <div id="game-stream">
 		    <table>
@@ -110,6 +141,13 @@ impl Component for GameHistory {
   			    </tr>
 			    { self.get_games() }
 		    </table>		
+			    {
+			        if self.next_cursor.is_some() {
+			            html! { <button class="button" onclick={_ctx.link().callback(|_| FetchStateMsg::GetData)}>{"Load more"}</button> }
+			        } else {
+			            html! {}
+			        }
+			    }
 			    </div>
 		    </div>
 		    </div>
@@ -0,0 +1,96 @@
+#![allow(non_snake_case)]
+use common::Backend::LiveMatch;
+use common::Position::GameType;
+use yew::prelude::*;
+use yew_router::prelude::*;
+
+use crate::GameHistory::format_time;
+use crate::{Matches, Route};
+
+// Online matches being played right now, each with a link to watch it
+pub struct Live {
+    fetched: bool,
+    matches: Vec<LiveMatch>,
+    message: Option<String>,
+}
+
+pub enum LiveMsg {
+    Refresh,
+    Loaded(Result<Vec<LiveMatch>, String>),
+}
+
+impl Component for Live {
+    type Message = LiveMsg;
+    type Properties = ();
+
+    fn create(_ctx: &Context<Self>) -> Self {
+        Self {
+            fetched: false,
+            matches: Vec::new(),
+            message: None,
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            LiveMsg::Refresh => {
+                self.fetched = true;
+                ctx.link().send_future(async { LiveMsg::Loaded(Matches::live().await) });
+                false
+            }
+            LiveMsg::Loaded(Ok(matches)) => {
+                self.matches = matches;
+                self.message = None;
+                true
+            }
+            LiveMsg::Loaded(Err(err)) => {
+                self.message = Some(err);
+                true
+            }
+        }
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        if !self.fetched {
+            ctx.link().send_message(LiveMsg::Refresh);
+        }
+        let rows = self.matches.iter().map(|live| {
+            let state = &live.state;
+            html! {
+                <tr>
+                    <td>{match state.gametype { GameType::Connect4 => "Connect 4 ", GameType::TootOtto => "TOOT-OTTO " }}</td>
+                    <td>{format!("{} vs {} ", state.player1, state.player2)}</td>
+                    <td>{format!("{} ", state.moves.len())}</td>
+                    <td>{format!("{} ", live.watching)}</td>
+                    <td>{format!("{} ", format_time(&Some(live.started_at.clone())))}</td>
+                    <td><Link<Route> to={Route::Watch { id: state.id.clone() }}>{"Watch"}</Link<Route>></td>
+                </tr>
+            }
+        }).collect::<Html>();
+        html! {
+            <div style = "margin-top: 75px">
+            <div class="w3-container" id="services" style="margin-left:30%">
+            <h5 class="w3-xxxlarge w3-text-red"><b>{"Live Games"}</b></h5>
+            <hr style="width:50px;border:5px solid red" class="w3-round"/>
+            <button class="button" onclick={ctx.link().callback(|_| LiveMsg::Refresh)}>{"Refresh"}</button>
+            if self.matches.is_empty() {
+                <p>{"Nobody is playing online right now."}</p>
+            } else {
+                <table>
+                    <tr>
+                        <th>{"Game Type"}</th>
+                        <th>{"Players"}</th>
+                        <th>{"Moves"}</th>
+                        <th>{"Watching"}</th>
+                        <th>{"Started"}</th>
+                        <th></th>
+                    </tr>
+                    { rows }
+                </table>
+            }
+            { self.message.as_ref().map(|message| html! { <p>{message.clone()}</p> }).unwrap_or_default() }
+            </div>
+            </div>
+        }
+    }
+}
@@ -0,0 +1,256 @@
+#![allow(non_snake_case)]
+use common::Backend::{standard_variant, MatchStatus, NewMatch, QueueRequest, QueueStatus, Room};
+use common::Position::GameType;
+use gloo::timers::callback::Interval;
+use web_sys::{HtmlInputElement, HtmlSelectElement};
+use yew::prelude::*;
+use yew_router::prelude::*;
+
+use crate::{Accounts, Matches, Route};
+
+// how often the page asks about the queue or an open room
+const POLL_MILLIS: u32 = 2000;
+
+// Find an opponent for an online match: wait in the matchmaking queue,
+// or open a private room and share its code
+pub struct Lobby {
+    gametype: NodeRef,
+    word: NodeRef,
+    code: NodeRef,
+    queued: Option<QueueStatus>,
+    room: Option<Room>,
+    // runs while queued or while a room is open
+    poll: Option<Interval>,
+    message: Option<String>,
+}
+
+pub enum LobbyMsg {
+    Queue,
+    Queued(Result<QueueStatus, String>),
+    LeaveQueue,
+    OpenRoom,
+    RoomOpened(Result<Room, String>),
+    CloseRoom,
+    JoinRoom,
+    Poll,
+    // someone joined the open room
+    RoomFilled(bool),
+    Play(String),
+    Notice(String),
+}
+
+impl Lobby {
+    fn selected_gametype(&self) -> GameType {
+        match self.gametype.cast::<HtmlSelectElement>().map(|select| select.value()).as_deref() {
+            Some("TootOtto") => GameType::TootOtto,
+            _ => GameType::Connect4,
+        }
+    }
+
+    fn start_polling(&mut self, ctx: &Context<Self>) {
+        let poll = ctx.link().callback(|_| LobbyMsg::Poll);
+        self.poll = Some(Interval::new(POLL_MILLIS, move || poll.emit(())));
+    }
+
+    fn stop(&mut self) {
+        self.poll = None;
+        self.queued = None;
+        self.room = None;
+    }
+
+    fn view_waiting(&self, ctx: &Context<Self>) -> Html {
+        if let Some(status) = &self.queued {
+            return html! {
+                <div>
+                    <p>{format!(
+                        "Looking for an opponent rated near {:.0}... {} waiting, {}s so far.",
+                        status.rating, status.waiting, status.waiting_secs
+                    )}</p>
+                    <button class="button" onclick={ctx.link().callback(|_| LobbyMsg::LeaveQueue)}>{"Cancel"}</button>
+                </div>
+            };
+        }
+        if let Some(room) = &self.room {
+            return html! {
+                <div>
+                    <p>{"Give your opponent this code: "}<b>{room.code.clone()}</b></p>
+                    <p>{format!("The room stays open until {}.", room.expires_at)}</p>
+                    <button class="button" onclick={ctx.link().callback(|_| LobbyMsg::CloseRoom)}>{"Close Room"}</button>
+                </div>
+            };
+        }
+        html! {}
+    }
+}
+
+impl Component for Lobby {
+    type Message = LobbyMsg;
+    type Properties = ();
+
+    fn create(_ctx: &Context<Self>) -> Self {
+        Self {
+            gametype: NodeRef::default(),
+            word: NodeRef::default(),
+            code: NodeRef::default(),
+            queued: None,
+            room: None,
+            poll: None,
+            message: None,
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            LobbyMsg::Queue => {
+                let request = QueueRequest { gametype: self.selected_gametype(), variant: standard_variant() };
+                ctx.link().send_future(async move { LobbyMsg::Queued(Matches::enqueue(request).await) });
+                false
+            }
+            LobbyMsg::Queued(Ok(status)) => {
+                if let Some(id) = status.match_id.clone() {
+                    ctx.link().send_message(LobbyMsg::Play(id));
+                    return false;
+                }
+                if self.poll.is_none() {
+                    self.start_polling(ctx);
+                }
+                self.queued = Some(status);
+                self.message = None;
+                true
+            }
+            LobbyMsg::Queued(Err(err)) => {
+                self.stop();
+                ctx.link().send_message(LobbyMsg::Notice(err));
+                true
+            }
+            LobbyMsg::LeaveQueue => {
+                self.stop();
+                ctx.link().send_future(async {
+                    Matches::leave_queue().await;
+                    LobbyMsg::Notice("You left the queue.".to_string())
+                });
+                true
+            }
+            LobbyMsg::OpenRoom => {
+                let gametype = self.selected_gametype();
+                let word = self.word.cast::<HtmlSelectElement>().map(|select| select.value());
+                let request = NewMatch {
+                    gametype,
+                    player1: Accounts::name().unwrap_or_default(),
+                    player2: String::new(),
+                    first_mover: None,
+                    player1_word: word.filter(|_| gametype == GameType::TootOtto),
+                    difficulty: None,
+                    online: true,
+                };
+                ctx.link().send_future(async move { LobbyMsg::RoomOpened(Matches::open_room(request).await) });
+                false
+            }
+            LobbyMsg::RoomOpened(Ok(room)) => {
+                self.room = Some(room);
+                self.message = None;
+                self.start_polling(ctx);
+                true
+            }
+            LobbyMsg::RoomOpened(Err(err)) | LobbyMsg::Notice(err) => {
+                self.message = Some(err);
+                true
+            }
+            LobbyMsg::CloseRoom => {
+                if let Some(room) = self.room.take() {
+                    ctx.link().send_future(async move {
+                        Matches::close_room(&room.code).await;
+                        LobbyMsg::Notice("The room is closed.".to_string())
+                    });
+                }
+                self.stop();
+                true
+            }
+            LobbyMsg::JoinRoom => {
+                let code = self.code.cast::<HtmlInputElement>().map(|input| input.value()).unwrap_or_default();
+                ctx.link().send_future(async move {
+                    match Matches::join_room(code.trim()).await {
+                        Ok(room) => LobbyMsg::Play(room.match_id),
+                        Err(err) => LobbyMsg::Notice(err),
+                    }
+                });
+                false
+            }
+            LobbyMsg::Poll => {
+                if self.queued.is_some() {
+                    ctx.link().send_future(async { LobbyMsg::Queued(Matches::queue_status().await) });
+                } else if let Some(room) = &self.room {
+                    let id = room.match_id.clone();
+                    ctx.link().send_future(async move {
+                        LobbyMsg::RoomFilled(matches!(Matches::get(&id).await, Ok(state) if state.status != MatchStatus::Waiting))
+                    });
+                }
+                false
+            }
+            LobbyMsg::RoomFilled(filled) => {
+                if let (Some(room), true) = (&self.room, filled) {
+                    ctx.link().send_message(LobbyMsg::Play(room.match_id.clone()));
+                }
+                false
+            }
+            LobbyMsg::Play(id) => {
+                self.stop();
+                if let Some(navigator) = ctx.link().navigator() {
+                    navigator.push(&Route::OnlineMatch { id });
+                }
+                false
+            }
+        }
+    }
+
+    // Leaving the page gives up the place in the queue
+    fn destroy(&mut self, _ctx: &Context<Self>) {
+        if self.queued.is_some() {
+            wasm_bindgen_futures::spawn_local(Matches::leave_queue());
+        }
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let body = if Accounts::current().is_none() {
+            html! {
+                <p>
+                    <Link<Route> to={Route::Login}>{"Log in"}</Link<Route>>
+                    {" to find an opponent."}
+                </p>
+            }
+        } else if self.queued.is_some() || self.room.is_some() {
+            self.view_waiting(ctx)
+        } else {
+            html! {
+                <div>
+                    <select ref={self.gametype.clone()} style="margin-right: 5px">
+                        <option value="Connect4">{"Connect 4"}</option>
+                        <option value="TootOtto">{"TOOT-OTTO"}</option>
+                    </select>
+                    <button class="button" onclick={ctx.link().callback(|_| LobbyMsg::Queue)}>{"Find Opponent"}</button>
+                    <p>{"You are paired with a player of a similar rating; the longer you wait, the wider the search."}</p>
+
+                    <h4>{"Private Room"}</h4>
+                    <select ref={self.word.clone()} style="margin-right: 5px">
+                        <option value="TOOT">{"I spell TOOT"}</option>
+                        <option value="OTTO">{"I spell OTTO"}</option>
+                    </select>
+                    <button class="button" onclick={ctx.link().callback(|_| LobbyMsg::OpenRoom)}>{"Open Room"}</button>
+                    <p>{"Opens a room for the game chosen above; the word only counts for TOOT-OTTO."}</p>
+                    <input ref={self.code.clone()} type="text" style="margin-right: 5px" placeholder="Room code"/>
+                    <button class="button" onclick={ctx.link().callback(|_| LobbyMsg::JoinRoom)}>{"Join Room"}</button>
+                </div>
+            }
+        };
+        html! {
+            <div style = "margin-top: 75px">
+            <div class="w3-container" id="services" style="margin-left:30%">
+            <h5 class="w3-xxxlarge w3-text-red"><b>{"Lobby"}</b></h5>
+            <hr style="width:50px;border:5px solid red" class="w3-round"/>
+            { body }
+            { self.message.as_ref().map(|message| html! { <p>{message.clone()}</p> }).unwrap_or_default() }
+            </div>
+            </div>
+        }
+    }
+}
@@ -0,0 +1,118 @@
+#![allow(non_snake_case)]
+use common::Backend::{Credentials, Session};
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+
+use crate::Accounts;
+
+// Log in, register or log out
+pub struct Login {
+    name: NodeRef,
+    password: NodeRef,
+    session: Option<Session>,
+    message: Option<String>,
+}
+
+pub enum LoginMsg {
+    Login,
+    Register,
+    Registered(Result<(), String>),
+    LoggedIn(Result<Session, String>),
+    Logout,
+    LoggedOut,
+}
+
+impl Login {
+    fn credentials(&self) -> Credentials {
+        let value = |input: &NodeRef| input.cast::<HtmlInputElement>().map(|input| input.value()).unwrap_or_default();
+        Credentials {
+            name: value(&self.name),
+            password: value(&self.password),
+        }
+    }
+}
+
+impl Component for Login {
+    type Message = LoginMsg;
+    type Properties = ();
+
+    fn create(_ctx: &Context<Self>) -> Self {
+        Self {
+            name: NodeRef::default(),
+            password: NodeRef::default(),
+            session: Accounts::current(),
+            message: None,
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            LoginMsg::Login => {
+                let credentials = self.credentials();
+                ctx.link().send_future(async move { LoginMsg::LoggedIn(Accounts::login(credentials).await) });
+                false
+            }
+            LoginMsg::Register => {
+                let credentials = self.credentials();
+                ctx.link().send_future(async move {
+                    LoginMsg::Registered(Accounts::register(credentials).await.map(|_| ()))
+                });
+                false
+            }
+            // log straight in with the same name and password
+            LoginMsg::Registered(Ok(())) => {
+                ctx.link().send_message(LoginMsg::Login);
+                false
+            }
+            LoginMsg::Registered(Err(err)) | LoginMsg::LoggedIn(Err(err)) => {
+                self.message = Some(err);
+                true
+            }
+            LoginMsg::LoggedIn(Ok(session)) => {
+                self.session = Some(session);
+                self.message = None;
+                true
+            }
+            LoginMsg::Logout => {
+                ctx.link().send_future(async {
+                    Accounts::logout().await;
+                    LoginMsg::LoggedOut
+                });
+                false
+            }
+            LoginMsg::LoggedOut => {
+                self.session = None;
+                true
+            }
+        }
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let body = match &self.session {
+            Some(session) => html! {
+                <div>
+                    <p>{format!("Logged in as {}.", session.user.name)}</p>
+                    <button class="button" onclick={ctx.link().callback(|_| LoginMsg::Logout)}>{"Log Out"}</button>
+                </div>
+            },
+            None => html! {
+                <div>
+                    <input ref={self.name.clone()} type="text" style="margin-right: 5px" placeholder="Name"/>
+                    <input ref={self.password.clone()} type="password" style="margin-right: 5px" placeholder="Password"/>
+                    <button class="button" onclick={ctx.link().callback(|_| LoginMsg::Login)}>{"Log In"}</button>
+                    <button class="button" onclick={ctx.link().callback(|_| LoginMsg::Register)}>{"Register"}</button>
+                    { self.message.as_ref().map(|message| html! { <p>{message.clone()}</p> }).unwrap_or_default() }
+                </div>
+            },
+        };
+        html! {
+            <div style = "margin-top: 75px">
+            <div class="w3-container" id="services" style="margin-left:30%">
+            <h5 class="w3-xxxlarge w3-text-red"><b>{"Account"}</b></h5>
+            <hr style="width:50px;border:5px solid red" class="w3-round"/>
+            { body }
+            </div>
+            </div>
+        }
+    }
+}
@@ -0,0 +1,94 @@
+#![allow(non_snake_case)]
+use common::Backend::{LiveMatch, MatchMove, MatchState, NewMatch, QueueRequest, QueueStatus, Room};
+use reqwest;
+use serde::de::DeserializeOwned;
+
+use crate::Accounts;
+
+const SERVER: &str = "http://127.0.0.1:8000";
+
+// Open a match on the server when a game starts, returns its id. Only
+// logged in users get one, guests' games are not recorded.
+pub async fn create(request: NewMatch) -> Option<String> {
+    open(request).await.map(|state| state.id)
+}
+
+// Like `create`, but returns the whole state: against the computer it
+// already holds the computer's first move
+pub async fn open(request: NewMatch) -> Option<MatchState> {
+    Accounts::current()?;
+    let client = reqwest::Client::new();
+    let response = Accounts::authorize(client.post(format!("{}/matches", SERVER))).json(&request).send().await.ok()?;
+    if !response.status().is_success() {
+        return None;
+    }
+    response.json().await.ok()
+}
+
+async fn send<T: DeserializeOwned>(request: reqwest::RequestBuilder) -> Result<T, String> {
+    let response = Accounts::authorize(request).send().await.map_err(|err| err.to_string())?;
+    if !response.status().is_success() {
+        return Err(Accounts::error_text(response).await);
+    }
+    response.json().await.map_err(|err| err.to_string())
+}
+
+// Play one move as the logged in user
+pub async fn play(id: &str, mv: String) -> Result<MatchState, String> {
+    send(reqwest::Client::new().post(format!("{}/matches/{}/moves", SERVER, id)).json(&MatchMove { mv })).await
+}
+
+pub async fn get(id: &str) -> Result<MatchState, String> {
+    send(reqwest::Client::new().get(format!("{}/matches/{}", SERVER, id))).await
+}
+
+// Online matches being played now, to watch
+pub async fn live() -> Result<Vec<LiveMatch>, String> {
+    send(reqwest::Client::new().get(format!("{}/matches/live", SERVER))).await
+}
+
+// Take the free seat of an online match
+pub async fn join(id: &str) -> Result<MatchState, String> {
+    send(reqwest::Client::new().post(format!("{}/matches/{}/join", SERVER, id))).await
+}
+
+// Where the server pushes the state of a match after every change
+pub fn events_url(id: &str) -> String {
+    format!("{}/matches/{}/events", SERVER, id)
+}
+
+// Send the moves of a finished game in order. The server replays them and
+// stores the game itself once its board says the game is over.
+pub async fn send_moves(id: &str, moves: Vec<String>) -> Result<MatchState, String> {
+    let mut state = None;
+    for mv in moves {
+        state = Some(play(id, mv).await?);
+    }
+    state.ok_or_else(|| "no moves to send".to_string())
+}
+
+// Wait in the matchmaking queue; keep calling `queue_status` until paired
+pub async fn enqueue(request: QueueRequest) -> Result<QueueStatus, String> {
+    send(reqwest::Client::new().post(format!("{}/lobby/queue", SERVER)).json(&request)).await
+}
+
+pub async fn queue_status() -> Result<QueueStatus, String> {
+    send(reqwest::Client::new().get(format!("{}/lobby/queue", SERVER))).await
+}
+
+pub async fn leave_queue() {
+    let _ = Accounts::authorize(reqwest::Client::new().delete(format!("{}/lobby/queue", SERVER))).send().await;
+}
+
+// Open an online match that friends join with the room's code
+pub async fn open_room(request: NewMatch) -> Result<Room, String> {
+    send(reqwest::Client::new().post(format!("{}/lobby/rooms", SERVER)).json(&request)).await
+}
+
+pub async fn join_room(code: &str) -> Result<Room, String> {
+    send(reqwest::Client::new().post(format!("{}/lobby/rooms/{}/join", SERVER, code))).await
+}
+
+pub async fn close_room(code: &str) {
+    let _ = Accounts::authorize(reqwest::Client::new().delete(format!("{}/lobby/rooms/{}", SERVER, code))).send().await;
+}
@@ -0,0 +1,318 @@
+#![allow(non_snake_case)]
+use common::Backend::{MatchState, MatchStatus, NewMatch};
+use common::Position::{GameType, Move, Position};
+use common::Render::{self, RenderOptions};
+use common::TootOtto::Piece;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{EventSource, HtmlSelectElement, MessageEvent};
+use yew::prelude::*;
+use yew_router::prelude::*;
+
+use crate::{Accounts, Matches, Route};
+
+#[derive(Properties, PartialEq)]
+pub struct OnlineProps {
+    // the match to show; without one the page opens a new match
+    #[prop_or_default]
+    pub id: Option<String>,
+    // follow the match as a spectator, without the controls
+    #[prop_or_default]
+    pub watch: bool,
+}
+
+// A game against someone in another browser. The server sends the whole
+// state after every move, and again whenever the page (re)connects, so
+// a dropped connection only needs the browser's own retry to catch up.
+pub struct Online {
+    state: Option<MatchState>,
+    events: Option<EventSource>,
+    // must live as long as `events` may call it
+    on_message: Option<Closure<dyn FnMut(MessageEvent)>>,
+    gametype: NodeRef,
+    word: NodeRef,
+    piece: Piece,
+    message: Option<String>,
+}
+
+pub enum OnlineMsg {
+    Create,
+    Created(Option<String>),
+    Update(MatchState),
+    Join,
+    Play(usize),
+    SetPiece(Piece),
+    Failed(String),
+}
+
+impl Online {
+    fn subscribe(&mut self, ctx: &Context<Self>) {
+        self.unsubscribe();
+        self.state = None;
+        self.message = None;
+        let id = match &ctx.props().id {
+            Some(id) => id.clone(),
+            None => return,
+        };
+        let events = match EventSource::new(&Matches::events_url(&id)) {
+            Ok(events) => events,
+            Err(_) => {
+                self.message = Some("this browser cannot follow matches".to_string());
+                return;
+            }
+        };
+        let update = ctx.link().callback(OnlineMsg::Update);
+        let on_message = Closure::wrap(Box::new(move |event: MessageEvent| {
+            let state = event.data().as_string().and_then(|text| serde_json::from_str(&text).ok());
+            if let Some(state) = state {
+                update.emit(state);
+            }
+        }) as Box<dyn FnMut(MessageEvent)>);
+        events.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+        self.events = Some(events);
+        self.on_message = Some(on_message);
+    }
+
+    fn unsubscribe(&mut self) {
+        if let Some(events) = self.events.take() {
+            events.close();
+        }
+        self.on_message = None;
+    }
+
+    fn selected(input: &NodeRef) -> String {
+        input.cast::<HtmlSelectElement>().map(|select| select.value()).unwrap_or_default()
+    }
+
+    fn my_turn(state: &MatchState) -> bool {
+        state.to_move.is_some() && state.to_move == Accounts::name()
+    }
+
+    fn status(state: &MatchState) -> String {
+        match state.status {
+            MatchStatus::Waiting => "Waiting for an opponent to join.".to_string(),
+            MatchStatus::Won => format!("{} wins!", state.winner.clone().unwrap_or_default()),
+            MatchStatus::Draw => "It's a draw.".to_string(),
+            MatchStatus::Playing if Self::my_turn(state) => "Your turn.".to_string(),
+            MatchStatus::Playing => format!("{}'s turn.", state.to_move.clone().unwrap_or_default()),
+        }
+    }
+
+    fn board(state: &MatchState) -> Html {
+        // replay the moves, a finished position does not parse
+        let mut position = Position::new(state.gametype);
+        for mv in &state.moves {
+            if let Err(err) = Move::parse(state.gametype, mv).and_then(|mv| position.play(mv)) {
+                return html! { <p>{err}</p> };
+            }
+        }
+        let last_move = state.moves.last().and_then(|mv| Move::parse(state.gametype, mv).ok()).map(|mv| mv.column());
+        let options = RenderOptions { last_move, ..Default::default() };
+        Html::from_html_unchecked(AttrValue::from(Render::svg(&position, &options)))
+    }
+
+    fn view_new(&self, ctx: &Context<Self>) -> Html {
+        if Accounts::current().is_none() {
+            return html! {
+                <p>
+                    <Link<Route> to={Route::Login}>{"Log in"}</Link<Route>>
+                    {" to play online."}
+                </p>
+            };
+        }
+        html! {
+            <div>
+                <select ref={self.gametype.clone()} style="margin-right: 5px">
+                    <option value="Connect4">{"Connect 4"}</option>
+                    <option value="TootOtto">{"TOOT-OTTO"}</option>
+                </select>
+                <select ref={self.word.clone()} style="margin-right: 5px">
+                    <option value="TOOT">{"I spell TOOT"}</option>
+                    <option value="OTTO">{"I spell OTTO"}</option>
+                </select>
+                <button class="button" onclick={ctx.link().callback(|_| OnlineMsg::Create)}>{"Open Match"}</button>
+                <p>{"The word only counts for TOOT-OTTO. You move first; send the link of the match to your opponent."}</p>
+            </div>
+        }
+    }
+
+    fn view_match(&self, ctx: &Context<Self>, state: &MatchState) -> Html {
+        let player2 = if state.player2.is_empty() { "?" } else { &state.player2 };
+        if ctx.props().watch {
+            return html! {
+                <div>
+                    <h4>{format!("{} vs {}", state.player1, player2)}</h4>
+                    if let Some(word) = &state.player1_word {
+                        <p>{format!("{} spells {}.", state.player1, word)}</p>
+                    }
+                    <p>{Self::status(state)}</p>
+                    { Self::board(state) }
+                    <p>{format!("Moves: {}", state.moves.join(" "))}</p>
+                </div>
+            };
+        }
+        let me = Accounts::name();
+        let can_join = state.status == MatchStatus::Waiting && me.is_some() && me.as_ref() != Some(&state.player1);
+        let invite = web_sys::window().and_then(|window| window.location().href().ok()).unwrap_or_default();
+        let my_turn = Self::my_turn(state);
+        let columns = (0..7).map(|col| html! {
+            <button class="button" style="width: 75px" disabled={!my_turn} onclick={ctx.link().callback(move |_| OnlineMsg::Play(col))}>
+                {"▼"}
+            </button>
+        }).collect::<Html>();
+        let pieces = match state.gametype {
+            GameType::TootOtto => html! {
+                <div>
+                    {"Piece: "}
+                    <button class="button" disabled={self.piece == Piece::T} onclick={ctx.link().callback(|_| OnlineMsg::SetPiece(Piece::T))}>{"T"}</button>
+                    <button class="button" disabled={self.piece == Piece::O} onclick={ctx.link().callback(|_| OnlineMsg::SetPiece(Piece::O))}>{"O"}</button>
+                </div>
+            },
+            GameType::Connect4 => html! {},
+        };
+        html! {
+            <div>
+                <h4>{format!("{} vs {}", state.player1, player2)}</h4>
+                if let Some(word) = &state.player1_word {
+                    <p>{format!("{} spells {}.", state.player1, word)}</p>
+                }
+                if state.status == MatchStatus::Waiting && !can_join {
+                    <p>{format!("Invite your opponent with this link: {}", invite)}</p>
+                }
+                if can_join {
+                    <button class="button" onclick={ctx.link().callback(|_| OnlineMsg::Join)}>{"Join"}</button>
+                }
+                <p>{Self::status(state)}</p>
+                { pieces }
+                <div>{ columns }</div>
+                { Self::board(state) }
+                if let Some(game_id) = &state.game_id {
+                    <p><Link<Route> to={Route::GameDetail { id: game_id.clone() }}>{"View the stored game"}</Link<Route>></p>
+                }
+            </div>
+        }
+    }
+}
+
+impl Component for Online {
+    type Message = OnlineMsg;
+    type Properties = OnlineProps;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        let mut online = Self {
+            state: None,
+            events: None,
+            on_message: None,
+            gametype: NodeRef::default(),
+            word: NodeRef::default(),
+            piece: Piece::T,
+            message: None,
+        };
+        online.subscribe(ctx);
+        online
+    }
+
+    fn changed(&mut self, ctx: &Context<Self>, _old_props: &Self::Properties) -> bool {
+        self.subscribe(ctx);
+        true
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            OnlineMsg::Create => {
+                let gametype = match Self::selected(&self.gametype).as_str() {
+                    "TootOtto" => GameType::TootOtto,
+                    _ => GameType::Connect4,
+                };
+                let request = NewMatch {
+                    gametype,
+                    player1: Accounts::name().unwrap_or_default(),
+                    player2: String::new(),
+                    first_mover: None,
+                    player1_word: (gametype == GameType::TootOtto).then(|| Self::selected(&self.word)),
+                    difficulty: None,
+                    online: true,
+                };
+                ctx.link().send_future(async move { OnlineMsg::Created(Matches::create(request).await) });
+                false
+            }
+            OnlineMsg::Created(Some(id)) => {
+                if let Some(navigator) = ctx.link().navigator() {
+                    navigator.push(&Route::OnlineMatch { id });
+                }
+                false
+            }
+            OnlineMsg::Created(None) => {
+                self.message = Some("the match could not be opened".to_string());
+                true
+            }
+            OnlineMsg::Update(state) => {
+                // nothing more will happen, and the browser would keep reconnecting
+                if matches!(state.status, MatchStatus::Won | MatchStatus::Draw) {
+                    self.unsubscribe();
+                }
+                self.state = Some(state);
+                self.message = None;
+                true
+            }
+            OnlineMsg::Join => {
+                if let Some(id) = ctx.props().id.clone() {
+                    ctx.link().send_future(async move {
+                        match Matches::join(&id).await {
+                            Ok(state) => OnlineMsg::Update(state),
+                            Err(err) => OnlineMsg::Failed(err),
+                        }
+                    });
+                }
+                false
+            }
+            OnlineMsg::Play(col) => {
+                let (id, gametype) = match &self.state {
+                    Some(state) => (state.id.clone(), state.gametype),
+                    None => return false,
+                };
+                let mv = match gametype {
+                    GameType::Connect4 => Move::Connect4(col),
+                    GameType::TootOtto => Move::TootOtto(col, self.piece),
+                };
+                ctx.link().send_future(async move {
+                    match Matches::play(&id, mv.to_string()).await {
+                        Ok(state) => OnlineMsg::Update(state),
+                        Err(err) => OnlineMsg::Failed(err),
+                    }
+                });
+                false
+            }
+            OnlineMsg::SetPiece(piece) => {
+                self.piece = piece;
+                true
+            }
+            OnlineMsg::Failed(err) => {
+                self.message = Some(err);
+                true
+            }
+        }
+    }
+
+    fn destroy(&mut self, _ctx: &Context<Self>) {
+        self.unsubscribe();
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let body = match (&ctx.props().id, &self.state) {
+            (None, _) => self.view_new(ctx),
+            (Some(_), Some(state)) => self.view_match(ctx, state),
+            (Some(_), None) => html! { <p>{"Connecting..."}</p> },
+        };
+        html! {
+            <div style = "margin-top: 75px">
+            <div class="w3-container" id="services" style="margin-left:30%">
+            <h5 class="w3-xxxlarge w3-text-red"><b>{if ctx.props().watch { "Watch" } else { "Play Online" }}</b></h5>
+            <hr style="width:50px;border:5px solid red" class="w3-round"/>
+            { body }
+            { self.message.as_ref().map(|message| html! { <p>{message.clone()}</p> }).unwrap_or_default() }
+            </div>
+            </div>
+        }
+    }
+}
@@ -1,151 +1,146 @@
 #![allow(non_snake_case)]
 #![allow(unused)]
-use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
 use yew::{
     prelude::*
 };
 use reqwest;
-use common::Backend::Game;
-use std::collections::HashMap;
+use common::Backend::{Game, GamePage, LeaderboardEntry, PlayerStats};
 
+use crate::GameHistory::format_time;
+
+const SERVER: &str = "http://127.0.0.1:8000";
+
+// Everything here is counted by the server; the page only lays it out
 pub struct ScoreBoard {
-    // add any state necessary for the game
-    state: FetchState<Vec<Game>>,
-    data: Vec<Game>,
-    data2: Vec<Game>,
-    data3: Vec<Game>,
+    fetched: bool,
+    computer: Option<PlayerStats>,
+    computer_games: Vec<Game>,
+    connect4: Vec<LeaderboardEntry>,
+    toot_otto: Vec<LeaderboardEntry>,
 }
 
-/// The possible states a fetch request can be in.
-#[derive(Debug, Clone, PartialEq)]
-pub enum FetchState<T> {
-    NotFetching,
-    Fetching,
-    Success1(T),
-    Success2(T),
-    Success3(T),
-    Failed,
+pub enum ScoreBoardMsg {
+    GetData,
+    Computer(Option<PlayerStats>),
+    ComputerGames(Option<GamePage>),
+    Connect4(Option<Vec<LeaderboardEntry>>),
+    TootOtto(Option<Vec<LeaderboardEntry>>),
 }
 
-pub enum FetchStateMsg<T> {
-    SetDataFetchState(FetchState<T>),
-    GetData,
+async fn fetch<T: DeserializeOwned>(path: String) -> Option<T> {
+    let response = reqwest::get(format!("{}{}", SERVER, path)).await.ok()?;
+    if !response.status().is_success() {
+        return None;
+    }
+    response.json().await.ok()
 }
 
 impl ScoreBoard {
 	fn get_compgames(&self) -> Html {
-        let games = self.data.iter().filter(|game| game.player2 == "Computer").enumerate().map(|(i,game)| html! {
+        let games = self.computer_games.iter().enumerate().map(|(i,game)| html! {
             <tr>
                 <td>{format!("{} ", i+1)}</td>
                 <td>{format!("{} ", game.gametype)}</td>
                 <td>{format!("{} ", game.winner)}</td>
-                <td>{format!("{} ", game.player1)}</td>
-                <td>{format!("{} ", game.date)}</td>
+                <td>{format!("{} ", if game.player1 == "Computer" { &game.player2 } else { &game.player1 })}</td>
+                <td>{format!("{} ", format_time(&game.ended_at))}</td>
             </tr>
 	    }).collect::<Html>();
 	    games
     }
 
-    fn get_playergames(&self) -> Html {
-        let mut result = HashMap::new();
-        
-        for item in &self.data {
-            *result.entry(&item.player1).or_insert(0 as i32) += 1;
-            *result.entry(&item.player2).or_insert(0 as i32) += 1;
-        }
-
-        let mut vec: Vec<(&String, i32)> = result.into_iter().filter(|x| x.0 != "Computer").collect();
-        vec.sort_by_key(|k| k.1);
-        vec.reverse();
-        let pgames = vec.iter().enumerate().map(|(i,(name,games))| {
-            let wins = self.data.iter().filter(|x| x.winner==**name).count();
-            html!{
-                <tr>
-                    <td>{format!("{} ", i+1)}</td>
-                    <td>{format!("{} ", name)}</td>
-			        <td>{format!("{} ", games)}</td>
-                    <td>{format!("{} ", wins)}</td>
-		        </tr>
-            }}).collect::<Html>();
-	    pgames
+    fn get_ranking(entries: &[LeaderboardEntry]) -> Html {
+        entries.iter().map(|entry| html! {
+            <tr>
+                <td>{format!("{} ", entry.rank)}</td>
+                <td>{format!("{} ", entry.player)}</td>
+                <td>{entry.rating.map(|rating| format!("{:.0} ", rating)).unwrap_or_else(|| "- ".to_string())}</td>
+                <td>{format!("{} ", entry.record.games)}</td>
+                <td>{format!("{} ", entry.record.wins)}</td>
+                <td>{format!("{} ", entry.record.losses)}</td>
+                <td>{format!("{} ", entry.record.draws)}</td>
+                <td>{format!("{:.0}% ", entry.record.win_rate * 100.0)}</td>
+            </tr>
+        }).collect::<Html>()
     }
 
     fn get_compstat(&self) -> Html {
-        let totalgames = self.data.iter().count();
-        let compgames = self.data.iter().filter(|x| x.player2=="Computer").count();
-        let compwins = self.data.iter().filter(|x| x.winner=="Computer").count();
-
-        let stat = 
+        let stats = match &self.computer {
+            Some(stats) => stats,
+            None => return html! {},
+        };
         html!{
             <tr>
-                <td>{format!("{} ", totalgames)}</td>
-			    <td>{format!("{} ", compgames)}</td>
-			    <td>{format!("{} ", compwins)}</td>
+			    <td>{format!("{} ", stats.record.games)}</td>
+			    <td>{format!("{} ", stats.record.wins)}</td>
+			    <td>{format!("{} ", stats.record.losses)}</td>
+			    <td>{format!("{} ", stats.record.draws)}</td>
 		    </tr>
-        };
-	    stat
+        }
     }
 }
 
 impl Component for ScoreBoard {
-    type Message = FetchStateMsg<Vec<Game>>;
+    type Message = ScoreBoardMsg;
     type Properties = ();
 
     fn create(_ctx: &Context<Self>) -> Self {
-        
         Self {
-            data: Vec::new(),
-            data2: Vec::new(),
-            data3: Vec::new(),
-            state: FetchState::NotFetching,
+            fetched: false,
+            computer: None,
+            computer_games: Vec::new(),
+            connect4: Vec::new(),
+            toot_otto: Vec::new(),
         }
     }
 
     fn update(&mut self, _ctx: &Context<Self>, _msg: Self::Message) -> bool {
         match _msg {
-            FetchStateMsg::SetDataFetchState(state) => {
-                match state.clone() {
-                    FetchState::Success1(s1) => {
-                        self.data = s1;
-                    },
-                    FetchState::Success2(s2) => {
-                        self.data2 = s2;
-                    },
-                    FetchState::Success3(s3) => {
-                        self.data3 = s3;
-                    },
-                    _=> (),
-                }
-                self.state = state;
+            ScoreBoardMsg::GetData => {
+                self.fetched = true;
+                let link = _ctx.link();
+                link.send_future(async { ScoreBoardMsg::Computer(fetch("/players/Computer/stats".to_string()).await) });
+                link.send_future(async { ScoreBoardMsg::ComputerGames(fetch("/games?opponent=computer".to_string()).await) });
+                link.send_future(async { ScoreBoardMsg::Connect4(fetch("/leaderboard?gametype=Connect4".to_string()).await) });
+                link.send_future(async { ScoreBoardMsg::TootOtto(fetch("/leaderboard?gametype=TootOtto".to_string()).await) });
+                false
+            }
+            ScoreBoardMsg::Computer(stats) => {
+                self.computer = stats;
                 true
             }
-            FetchStateMsg::GetData => {
-                _ctx.link().send_future(async move {
-                    match reqwest::get("http://127.0.0.1:8000/games").await {
-                        Ok(v) => match v.json().await {
-                            Ok(v) => {
-                                FetchStateMsg::SetDataFetchState(FetchState::Success1(v))
-                            }
-                            Err(err) => {
-                                FetchStateMsg::SetDataFetchState(FetchState::Failed)
-                            }
-                        }
-                        Err(err) => {
-                            FetchStateMsg::SetDataFetchState(FetchState::Failed)
-                        }
-                    }
-                });
-                _ctx.link()
-                    .send_message(FetchStateMsg::SetDataFetchState(FetchState::Fetching));
-                false
+            ScoreBoardMsg::ComputerGames(page) => {
+                self.computer_games = page.map(|page| page.games).unwrap_or_default();
+                true
+            }
+            ScoreBoardMsg::Connect4(entries) => {
+                self.connect4 = entries.unwrap_or_default();
+                true
+            }
+            ScoreBoardMsg::TootOtto(entries) => {
+                self.toot_otto = entries.unwrap_or_default();
+                true
             }
         }
     }
 
     fn view(&self, _ctx: &Context<Self>) -> Html {
-        if matches!(&self.state, &FetchState::NotFetching) {
-            _ctx.link().send_message(FetchStateMsg::GetData);
+        if !self.fetched {
+            _ctx.link().send_message(ScoreBoardMsg::GetData);
         }
+        let ranking_header = html! {
+            <tr>
+                <th>{"Ranking"}</th>
+                <th>{"Player Name"}</th>
+                <th>{"Rating"}</th>
+                <th>{"Total Games"}</th>
+                <th>{"No. of Wins"}</th>
+                <th>{"Losses"}</th>
+                <th>{"Draws"}</th>
+                <th>{"Win Rate"}</th>
+            </tr>
+        };
         html! {
             <div style = "margin-top: 75px">
             <div class="w3-container" id="services" style="margin-left:30%">
@@ -154,9 +149,10 @@ impl Component for ScoreBoard {
                 <div><h4>{"Games Won by Computer"}</h4></div>
                     <table>
 			                <tr>
-			                    <th>{"Total Games Played"}</th>
 			                    <th>{"Games Against Computer"}</th>
 			                    <th>{"Games Computer Won"}</th>
+			                    <th>{"Games Computer Lost"}</th>
+			                    <th>{"Draws"}</th>
   			                </tr>
                             { self.get_compstat() }
 	                </table>
@@ -178,23 +174,28 @@ impl Component for ScoreBoard {
 
 		        <br/>
 
-    	        <div><h4>{"Details of Games Won by All Players"}</h4></div>
+    	        <div><h4>{"Connect 4 Rankings"}</h4></div>
 	            <div id="game-stream">
 	                <table>
-			            <tr>
-                            <th>{"Ranking"}</th>
-				            <th>{"Player Name"}</th>
-			                <th>{"Total Games"}</th>
-			                <th>{"No. of Wins"}</th>
-  			            </tr>
-                        { self.get_playergames() }
+                        { ranking_header.clone() }
+                        { Self::get_ranking(&self.connect4) }
+		            </table>
+			        </div>
+
+		        <br/>
+
+    	        <div><h4>{"TOOT-OTTO Rankings"}</h4></div>
+	            <div id="game-stream">
+	                <table>
+                        { ranking_header }
+                        { Self::get_ranking(&self.toot_otto) }
 		            </table>
 			        </div>
 	        </div>
-	
+
             </div>
             </div>
         }
     }
 
-}
\ No newline at end of file
+}
@@ -5,24 +5,35 @@ use std::cell::RefCell;
 use std::f64::consts::PI;
 use std::rc::Rc;
 
-use common::TootOtto::{Piece, Player, TootOtto, Difficulty};
-use common::Backend::Game;
+use common::TootOtto::{Piece, Player, TootOtto};
+use common::Skill;
+use common::Backend::{MatchState, NewMatch};
+use common::Position::{GameType, Move};
 use stdweb::traits::*;
 use wasm_bindgen::{JsCast, JsValue, prelude::Closure};
 use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement, HtmlInputElement, MouseEvent};
 use yew::prelude::*;
-use reqwest;
+
+use crate::Accounts;
+use crate::Matches;
 
 pub struct TootOttoComputer {
     // add any state necessary for the game
     game: Rc<RefCell<TootOtto>>,
     is_game_started: bool,
+    // id of the match the server is keeping for this game; the server
+    // plays the computer's moves in it
+    match_id: Option<String>,
+    // clicks wait while the server opens the match or answers a move
+    waiting: bool,
+    moves: Vec<String>,
     p1_name: String,
     winner: String,
     is_game_over: bool,
     is_game_draw: bool,
     selected_letter: char,
-    current_player: Player,
+    human_player: Player,
+    computer_first: bool,
     difficulty: usize,
     p1_name_event: Callback<InputEvent>,
     disc_change_event: Callback<MouseEvent>,
@@ -39,7 +50,9 @@ pub enum Msg {
     EndGame,
     SetDisc(String),
     ClickedColumn(Option<usize>),
-    Record(),
+    MatchCreated(Option<MatchState>),
+    // the server's answer to a move in the match with this id
+    Replied(String, Result<MatchState, String>),
 }
 
 impl TootOttoComputer {
@@ -108,7 +121,7 @@ impl TootOttoComputer {
         match self.game.borrow_mut().winner() {
             None => {}
             Some(x) => {
-                if x == Player::Toot {
+                if x == self.human_player {
                     self.winner = self.p1_name.clone();
                     self.is_game_over = true;
                 } else {
@@ -142,51 +155,89 @@ impl TootOttoComputer {
         }
     }
 
-    fn make_move(&mut self, col: usize) {
-        if self.selected_letter == 'T' {
-            match self.current_player {
-                Player::Toot => {
-                    self.game.borrow_mut().make_move_by_toot(col, Piece::T);
-                }
-                Player::Otto => {
-                    self.game.borrow_mut().make_move_by_otto(col, Piece::T);
-                }
-                Player::AI => {}
+    // Play the user's move, then the computer's: the server's in a match,
+    // the browser's own for guests
+    fn make_move(&mut self, col: usize, ctx: &Context<Self>) {
+        let piece = if self.selected_letter == 'T' { Piece::T } else { Piece::O };
+        let mv = Move::TootOtto(col, piece).to_string();
+        self.moves.push(mv.clone());
+        match self.human_player {
+            Player::Toot => {
+                self.game.borrow_mut().make_move_by_toot(col, piece);
             }
-        } else {
-            match self.current_player {
-                Player::Toot => {
-                    self.game.borrow_mut().make_move_by_toot(col, Piece::O);
-                }
-                Player::Otto => {
-                    self.game.borrow_mut().make_move_by_otto(col, Piece::O);
-                }
-                Player::AI => {}
+            Player::Otto => {
+                self.game.borrow_mut().make_move_by_otto(col, piece);
             }
         }
-        self.game.borrow_mut().make_move_by_ai();
+        let is_over = self.game.borrow().is_over() || self.game.borrow().is_draw();
+        match self.match_id.clone() {
+            Some(id) => {
+                self.waiting = true;
+                ctx.link().send_future(async move {
+                    let reply = Matches::play(&id, mv).await;
+                    Msg::Replied(id, reply)
+                });
+            }
+            None if !is_over => self.computer_move(),
+            None => {}
+        }
     }
 
-    fn new_game(&mut self) {
-        self.game = Rc::new(RefCell::new(TootOtto::new()));
-        match self.difficulty{
-            1 => {
-                self.game.borrow_mut().set_difficulty(Difficulty::Easy);
-            },
-            2 => {
-                self.game.borrow_mut().set_difficulty(Difficulty::Medium);
-            },
-            3 => {
-                self.game.borrow_mut().set_difficulty(Difficulty::Hard);
-            },
-            _ => {},
+    // Play the moves the server made that the board does not have yet
+    fn catch_up(&mut self, state: &MatchState) {
+        for mv in state.moves.iter().skip(self.moves.len()) {
+            if let Ok(Move::TootOtto(col, piece)) = Move::parse(GameType::TootOtto, mv) {
+                self.game.borrow_mut().play_move(col, piece);
+                self.moves.push(mv.clone());
+            }
+        }
+        self.render_board();
+        self.check_winner();
+    }
+
+    fn word(&self, player: Player) -> &'static str {
+        match player {
+            Player::Toot => "TOOT",
+            Player::Otto => "OTTO",
+        }
+    }
+
+    fn computer_move(&mut self) {
+        if let Some((col, piece)) = self.game.borrow_mut().make_move_by_skill(self.difficulty, self.human_player.opponent()) {
+            self.moves.push(Move::TootOtto(col, piece).to_string());
         }
+    }
+
+    // The server replays every move and records the result once the game
+    // ends; when the computer starts, its first move comes with the match
+    fn start_match(&mut self, ctx: &Context<Self>) {
+        self.match_id = None;
+        self.moves.clear();
+        self.waiting = Accounts::current().is_some();
+        let request = NewMatch {
+            gametype: GameType::TootOtto,
+            player1: self.p1_name.clone(),
+            player2: "Computer".to_string(),
+            first_mover: if self.computer_first { Some("Computer".to_string()) } else { None },
+            player1_word: Some(self.word(self.human_player).to_string()),
+            difficulty: Some(self.difficulty),
+            online: false,
+        };
+        ctx.link().send_future(async move { Msg::MatchCreated(Matches::open(request).await) });
+    }
+
+    fn new_game(&mut self, ctx: &Context<Self>) {
+        self.game = Rc::new(RefCell::new(TootOtto::new()));
         self.winner = "".to_string();
         self.is_game_over = false;
+        self.start_match(ctx);
         self.is_game_draw = false;
         let canvas: HtmlCanvasElement = self.canvas.cast().unwrap();
         let context: CanvasRenderingContext2d = canvas.get_context("2d").unwrap().unwrap().unchecked_into();
         context.clear_rect(0 as f64, 0 as f64, canvas.width() as f64, canvas.height() as f64);
+        if self.computer_first && !self.waiting {
+            self.computer_move();
+        }
     }
 }
 
@@ -201,12 +252,17 @@ impl Component for TootOttoComputer {
             // initialize any state necessary for the game
             game: tootOtto.clone(),
             is_game_started: false,
-            p1_name: "".to_string(),
+            match_id: None,
+            waiting: false,
+            moves: Vec::new(),
+            // the logged in user plays as themselves
+            p1_name: Accounts::name().unwrap_or_default(),
             winner: "".to_string(),
             is_game_over: false,
             is_game_draw: false,
             selected_letter: 'T',
-            current_player: Player::Toot,
+            human_player: Player::Toot,
+            computer_first: false,
             difficulty: 0,
             p1_name_event: _ctx.link().callback(|e: InputEvent| Msg::P1NameInput(e)),
             disc_change_event: _ctx.link().callback(|e: MouseEvent| {
@@ -239,19 +295,19 @@ impl Component for TootOttoComputer {
                         .unwrap()
                         .dyn_into::<web_sys::HtmlSelectElement>()
                         .unwrap();
-                    self.difficulty = difficulty_drop_down.value().parse::<usize>().unwrap();
-                    match self.difficulty{
-                        1 => {
-                            self.game.borrow_mut().set_difficulty(Difficulty::Easy);
-                        },
-                        2 => {
-                            self.game.borrow_mut().set_difficulty(Difficulty::Medium);
-                        },
-                        3 => {
-                            self.game.borrow_mut().set_difficulty(Difficulty::Hard);
-                        },
-                        _ => {},
-                    }
+                    self.difficulty = difficulty_drop_down.value().parse::<usize>().unwrap().min(Skill::BROWSER_LEVELS);
+                    let side_drop_down = document.query_selector("#side_drop_down")
+                        .unwrap()
+                        .unwrap()
+                        .dyn_into::<web_sys::HtmlSelectElement>()
+                        .unwrap();
+                    self.human_player = if side_drop_down.value() == "OTTO" { Player::Otto } else { Player::Toot };
+                    let first_drop_down = document.query_selector("#first_drop_down")
+                        .unwrap()
+                        .unwrap()
+                        .dyn_into::<web_sys::HtmlSelectElement>()
+                        .unwrap();
+                    self.computer_first = first_drop_down.value() == "computer";
                     let canvas: HtmlCanvasElement = self.canvas.cast().unwrap();
                     // let context: CanvasRenderingContext2d = canvas.get_context("2d")
                     //     .unwrap()
@@ -278,29 +334,16 @@ impl Component for TootOttoComputer {
                         .unwrap();
 
                     closure.forget();
+                    self.start_match(_ctx);
                     self.render_background();
+                    if self.computer_first && !self.waiting {
+                        self.computer_move();
+                        self.render_board();
+                    }
                 }
             }
-            Msg::EndGame => {
-                let game = Game {
-                        gametype: "TootOtto".to_string(),
-                        player1: self.p1_name.clone(),
-                        player2: "Computer".to_string(),
-                        winner: self.winner.clone(),
-                        date: "temp".to_string(),
-                    };
-                
-                _ctx.link().send_future(async move{
-                    let client = reqwest::Client::new();
-                    match client.post("http://127.0.0.1:8000/post/games").body(serde_json::to_string(&game).unwrap()).send().await{
-                        Ok(v) => {
-                            Msg::Record()
-                        }
-                        Err(err) => {
-                            Msg::Record()
-                        }
-                    }});
-            }
+            // the server stored the game with its last move
+            Msg::EndGame => {}
             Msg::SetDisc(disc) => {
                 if disc == "T".to_string() {
                     self.selected_letter = 'T'
@@ -308,6 +351,7 @@ impl Component for TootOttoComputer {
                     self.selected_letter = 'O'
                 }
             }
+            Msg::ClickedColumn(_) if self.waiting => {}
             Msg::ClickedColumn(column) => {
                 if self.is_game_over == false {
                     match column {
@@ -316,19 +360,51 @@ impl Component for TootOttoComputer {
                             // let game = self.game.clone();
                             let row = self.game.borrow_mut().top_row(col);
                             if row != 10 {
-                                self.make_move(col);
+                                self.make_move(col, _ctx);
                                 self.render_board();
                                 self.check_winner();
                             }
                         }
                     }
                 } else {
-                    self.new_game();
+                    self.new_game(_ctx);
                     //let link = _ctx.link().clone();
                     self.render_background();
+                    self.render_board();
+                }
+            }
+            Msg::MatchCreated(state) => {
+                self.waiting = false;
+                match state {
+                    Some(state) => {
+                        self.match_id = Some(state.id.clone());
+                        self.catch_up(&state);
+                    }
+                    // no match, so the browser plays the computer
+                    None if self.computer_first && self.moves.is_empty() => {
+                        self.computer_move();
+                        self.render_board();
+                    }
+                    None => {}
+                }
+            }
+            // an answer from a game already left
+            Msg::Replied(id, _) if self.match_id.as_ref() != Some(&id) => {}
+            Msg::Replied(_, reply) => {
+                self.waiting = false;
+                match reply {
+                    Ok(state) => self.catch_up(&state),
+                    // the server lost the match: finish the game here, unrecorded
+                    Err(_) => {
+                        self.match_id = None;
+                        if !self.is_game_over {
+                            self.computer_move();
+                            self.render_board();
+                            self.check_winner();
+                        }
+                    }
                 }
             }
-            Msg::Record() => {}
         }
         true
     }
@@ -345,7 +421,7 @@ impl Component for TootOttoComputer {
                 <hr style="width:50px;border:5px solid red" class="w3-round"/>
                     <div class="col-md-offset-3 col-md-8">
 
-                        <input id="P1 Name" type="text" style="margin-right: 5px" placeholder="Your Name" oninput = {&self.p1_name_event}/>
+                        <input id="P1 Name" type="text" style="margin-right: 5px" placeholder="Your Name" oninput = {&self.p1_name_event} value={self.p1_name.clone()} readonly={Accounts::current().is_some()}/>
                         <button
                             id="Start Button"
                             onclick={&self.start_event}
@@ -353,14 +429,29 @@ impl Component for TootOttoComputer {
                             title="Start Game">
                             { "Start Game" }
                         </button>
+                        { Accounts::record_notice() }
 
                     </div>
                     <div>
                         <label for="difficulty_drop_down"> {"Difficulty: "} </label>
                         <select id="difficulty_drop_down" style="margin-top: 5px">
-                        <option value=1 selected=true> {"Beginner"}</option>
-                        <option value=2 selected=false> {"Intermediate"}</option>
-                        <option value=3 selected=false> {"Professional"}</option>
+                        { for Skill::LEVELS[..Skill::BROWSER_LEVELS].iter().map(|level| html! {
+                            <option value={level.level.to_string()} selected={level.level == 1}> {level.name}</option>
+                        }) }
+                        </select>
+                    </div>
+                    <div>
+                        <label for="side_drop_down"> {"Play as: "} </label>
+                        <select id="side_drop_down" style="margin-top: 5px">
+                        <option value="TOOT" selected=true> {"TOOT"}</option>
+                        <option value="OTTO" selected=false> {"OTTO"}</option>
+                        </select>
+                    </div>
+                    <div>
+                        <label for="first_drop_down"> {"First Move: "} </label>
+                        <select id="first_drop_down" style="margin-top: 5px">
+                        <option value="human" selected=true> {"You"}</option>
+                        <option value="computer" selected=false> {"Computer"}</option>
                         </select>
                     </div>
                 </div>
@@ -372,8 +463,9 @@ impl Component for TootOttoComputer {
                     <div class="w3-container" id="services" style="margin-left:30%">
                         <div>
                         <h4>{format!("New Game:  {} Vs Computer",self.p1_name)}</h4>
+                        <p>{format!("Difficulty: {} (level {} of {})", Skill::level(self.difficulty).name, self.difficulty, Skill::BROWSER_LEVELS)}</p>
                         <br/>
-                        <small>{format!("(Winning Combination: {} - ", self.p1_name)} <b>{"TOOT"}</b> {format!("   and    Computer - " )} <b>{"OTTO)"}</b></small>
+                        <small>{format!("(Winning Combination: {} - ", self.p1_name)} <b>{self.word(self.human_player)}</b> {format!("   and    Computer - " )} <b>{format!("{})", self.word(self.human_player.opponent()))}</b></small>
                         <br/>
                         {"Select a Disc Type:  "}
                         <input type="radio" name="choice" value="T" checked ={self.selected_letter == 'T'} onclick = {&self.disc_change_event}/> {"T"}
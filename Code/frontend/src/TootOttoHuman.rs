@@ -5,17 +5,23 @@ use std::cell::RefCell;
 use std::f64::consts::PI;
 use std::rc::Rc;
 
-use common::Backend::Game;
+use common::Backend::NewMatch;
+use common::Position::{GameType, Move};
 use common::TootOtto::{Piece, Player, TootOtto};
 use wasm_bindgen::{JsCast, JsValue, prelude::Closure};
 use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement, HtmlInputElement, MouseEvent};
 use yew::prelude::*;
-use reqwest;
+
+use crate::Accounts;
+use crate::Matches;
 
 pub struct TootOttoHuman {
     // add any state necessary for the game
     game: Rc<RefCell<TootOtto>>,
     is_game_started: bool,
+    // id of the match the server is keeping for this game
+    match_id: Option<String>,
+    moves: Vec<String>,
     p1_name: String,
     p2_name: String,
     winner: String,
@@ -40,6 +46,7 @@ pub enum Msg {
     SetDisc(String),
     ClickedColumn(Option<usize>),
     Record(),
+    MatchCreated(Option<String>),
 }
 
 impl TootOttoHuman {
@@ -143,6 +150,8 @@ impl TootOttoHuman {
     }
 
     fn make_move(&mut self, col: usize) {
+        let piece = if self.selected_letter == 'T' { Piece::T } else { Piece::O };
+        self.moves.push(Move::TootOtto(col, piece).to_string());
         if self.selected_letter == 'T' {
             match self.current_player {
                 Player::Toot => {
@@ -153,7 +162,6 @@ impl TootOttoHuman {
                     self.game.borrow_mut().make_move_by_otto(col, Piece::T);
                     self.current_player = Player::Toot;
                 }
-                Player::AI => {}
             }
         } else {
             match self.current_player {
@@ -165,15 +173,31 @@ impl TootOttoHuman {
                     self.game.borrow_mut().make_move_by_otto(col, Piece::O);
                     self.current_player = Player::Toot;
                 }
-                Player::AI => {}
             }
         }
     }
 
-    fn new_game(&mut self) {
+    // The server replays the moves and records the result once the game ends
+    fn start_match(&mut self, ctx: &Context<Self>) {
+        self.match_id = None;
+        self.moves.clear();
+        let request = NewMatch {
+            gametype: GameType::TootOtto,
+            player1: self.p1_name.clone(),
+            player2: self.p2_name.clone(),
+            first_mover: None,
+            player1_word: None,
+            difficulty: None,
+            online: false,
+        };
+        ctx.link().send_future(async move { Msg::MatchCreated(Matches::create(request).await) });
+    }
+
+    fn new_game(&mut self, ctx: &Context<Self>) {
         self.game = Rc::new(RefCell::new(TootOtto::new()));
         self.winner = "".to_string();
         self.is_game_over = false;
+        self.start_match(ctx);
         self.is_game_draw = false;
         let canvas: HtmlCanvasElement = self.canvas.cast().unwrap();
         let context: CanvasRenderingContext2d = canvas.get_context("2d").unwrap().unwrap().unchecked_into();
@@ -192,7 +216,10 @@ impl Component for TootOttoHuman {
             // initialize any state necessary for the game
             game: tootOtto.clone(),
             is_game_started: false,
-            p1_name: "".to_string(),
+            match_id: None,
+            moves: Vec::new(),
+            // the logged in user plays as themselves
+            p1_name: Accounts::name().unwrap_or_default(),
             p2_name: "".to_string(),
             winner: "".to_string(),
             is_game_over: false,
@@ -255,28 +282,18 @@ impl Component for TootOttoHuman {
                         .unwrap();
 
                     closure.forget();
+                    self.start_match(_ctx);
                     self.render_background();
                 }
             }
             Msg::EndGame => {
-                let game = Game {
-                    gametype: "TootOtto".to_string(),
-                    player1: self.p1_name.clone(),
-                    player2: self.p2_name.clone(),
-                    winner: self.winner.clone(),
-                    date: "temp".to_string(),
-                };
-                
-                _ctx.link().send_future(async move{
-                    let client = reqwest::Client::new();
-                    match client.post("http://127.0.0.1:8000/post/games").body(serde_json::to_string(&game).unwrap()).send().await{
-                        Ok(v) => {
-                            Msg::Record()
-                        }
-                        Err(err) => {
-                            Msg::Record()
-                        }
-                    }});
+                if let Some(id) = self.match_id.take() {
+                    let moves = self.moves.clone();
+                    _ctx.link().send_future(async move {
+                        let _ = Matches::send_moves(&id, moves).await;
+                        Msg::Record()
+                    });
+                }
             }
             Msg::SetDisc(disc) => {
                 if disc == "T".to_string() {
@@ -300,11 +317,14 @@ impl Component for TootOttoHuman {
                         }
                     }
                 } else {
-                    self.new_game();
+                    self.new_game(_ctx);
                     //let link = _ctx.link().clone();
                     self.render_background();
                 }
             }
+            Msg::MatchCreated(id) => {
+                self.match_id = id;
+            }
             _ => {}
         }
         true
@@ -322,7 +342,7 @@ impl Component for TootOttoHuman {
                 <hr style="width:50px;border:5px solid red" class="w3-round"/>
                     <div class="col-md-offset-3 col-md-8">
 
-                        <input id="P1 Name" type="text" style="margin-right: 5px" placeholder="Player 1's Name" oninput = {&self.p1_name_event}/>
+                        <input id="P1 Name" type="text" style="margin-right: 5px" placeholder="Player 1's Name" oninput = {&self.p1_name_event} value={self.p1_name.clone()} readonly={Accounts::current().is_some()}/>
                         <input id="P2 Name" type="text" style="margin-right: 5px" placeholder="Player 2's Name" oninput = {&self.p2_name_event}/>
                         <button
                             id="Start Button"
@@ -331,6 +351,7 @@ impl Component for TootOttoHuman {
                             title="Start Game">
                             { "Start Game" }
                         </button>
+                        { Accounts::record_notice() }
 
                     </div>
                 </div>
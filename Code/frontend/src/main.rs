@@ -12,6 +12,13 @@ mod HowToToot;
 mod TootOttoHuman;
 mod TootOttoComputer;
 mod GameHistory;
+mod GameDetail;
+mod Matches;
+mod Accounts;
+mod Login;
+mod Online;
+mod Lobby;
+mod Live;
 mod ScoreBoard;
 
 
@@ -33,8 +40,22 @@ pub enum Route {
     TootOttoComputer,
     #[at("/GameHistory")]
     GameHistory,
+    #[at("/Game/:id")]
+    GameDetail { id: String },
     #[at("/ScoreBoard")]
     ScoreBoard,
+    #[at("/Login")]
+    Login,
+    #[at("/Online")]
+    Online,
+    #[at("/Lobby")]
+    Lobby,
+    #[at("/Online/:id")]
+    OnlineMatch { id: String },
+    #[at("/Live")]
+    Live,
+    #[at("/Watch/:id")]
+    Watch { id: String },
 }
 
 
@@ -81,9 +102,15 @@ impl App {
             <Link<Route> to={Route::HowToToot}>{ "How to Play Toot"}</Link<Route>>
             <Link<Route> to={Route::TootOttoComputer}>{ "Play Toot-Otto with Computer"}</Link<Route>>
             <Link<Route> to={Route::TootOttoHuman}>{ "Play Toot-Otto with Another Human"}</Link<Route>>
+          <br/>
+            <Link<Route> to={Route::Lobby}>{"Find an Opponent"}</Link<Route>>
+            <Link<Route> to={Route::Online}>{"Play Online"}</Link<Route>>
+            <Link<Route> to={Route::Live}>{"Watch Live Games"}</Link<Route>>
           <br/>
             <Link<Route> to={Route::GameHistory}>{"View Game History"}</Link<Route>>
             <Link<Route> to={Route::ScoreBoard}>{"Score Board"}</Link<Route>>
+          <br/>
+            <Link<Route> to={Route::Login}>{"Log In / Register"}</Link<Route>>
         </nav>
         }
     }
@@ -99,7 +126,14 @@ fn switch(routes: Route) -> Html {
         Route::TootOttoHuman => html! {<TootOttoHuman::TootOttoHuman />},
         Route::TootOttoComputer => html! {<TootOttoComputer::TootOttoComputer />},
         Route::GameHistory => html! {<GameHistory::GameHistory />},
+        Route::GameDetail { id } => html! {<GameDetail::GameDetail id={id} />},
         Route::ScoreBoard => html! {<ScoreBoard::ScoreBoard />},
+        Route::Login => html! {<Login::Login />},
+        Route::Online => html! {<Online::Online />},
+        Route::Lobby => html! {<Lobby::Lobby />},
+        Route::OnlineMatch { id } => html! {<Online::Online id={Some(id)} />},
+        Route::Live => html! {<Live::Live />},
+        Route::Watch { id } => html! {<Online::Online id={Some(id)} watch=true />},
     }
 }
 